@@ -0,0 +1,226 @@
+//! `Describe` derive, which generates OpenAPI operations from the same attributes as the
+//! `FromRequest` derive.
+
+use super::parse::{placeholder_name, FieldKind, ItemData, PathMap, QueryKind, VariantData};
+use crate::utils::{option_inner_type, vec_inner_type};
+use proc_macro2::TokenStream;
+use quote::quote;
+use std::collections::HashMap;
+use syn::{Attribute, Lit, Meta};
+use synstructure::{AddBounds, Structure};
+
+pub fn derive_describe(mut s: Structure<'_>) -> TokenStream {
+    let is_struct = match &s.ast().data {
+        syn::Data::Union(_) => panic!("#[derive(Describe)] is not allowed on unions"),
+        syn::Data::Struct(_) => true,
+        syn::Data::Enum(_) => false,
+    };
+
+    let item_data = ItemData::parse(&s.ast().attrs, is_struct);
+    let variant_data = s
+        .variants()
+        .iter()
+        .map(|variant| VariantData::parse(&variant.ast(), &item_data, is_struct))
+        .collect::<Vec<_>>();
+    let pathmap = PathMap::build(&item_data, &variant_data);
+
+    // Doc comments of each variant (or of the struct itself)
+    let docs = if is_struct {
+        let mut docs = HashMap::new();
+        docs.insert(s.ast().ident.clone(), Docs::parse(&s.ast().attrs));
+        docs
+    } else {
+        s.variants()
+            .iter()
+            .map(|variant| (variant.ast().ident.clone(), Docs::parse(variant.ast().attrs)))
+            .collect()
+    };
+
+    let operations = pathmap
+        .routes()
+        .filter(|(_, route, _)| !route.is_implied())
+        .filter_map(|(_, route, variant)| {
+            let path = route.template()?;
+            let method = route.method();
+            let variant_name = variant.variant_name().to_string();
+
+            let docs = &docs[variant.variant_name()];
+            let summary = docs.summary.as_ref().map(|summary| quote!(.summary(#summary)));
+            let description = docs
+                .description
+                .as_ref()
+                .map(|description| quote!(.description(#description)));
+
+            let path_params = route.placeholders().iter().map(|name| {
+                let pattern = match route.constraint(name) {
+                    Some(constraint) => {
+                        let pattern = format!("^(?:{})$", constraint);
+                        quote!(Some(#pattern))
+                    }
+                    None => quote!(None),
+                };
+                match variant.placeholder_field(name) {
+                    Some(field) => {
+                        let ty = &field.ty;
+                        let name = placeholder_name(name);
+                        quote!(.path_param::<#ty>(gen, #name, #pattern))
+                    }
+                    None => {
+                        // Only stored in the `#[path_params]` field
+                        let ty = &variant
+                            .path_params_field()
+                            .expect("internal error: no field for placeholder")
+                            .ty;
+                        let name = placeholder_name(name);
+                        quote!(.path_param_of::<#ty>(gen, #name, #pattern))
+                    }
+                }
+            });
+            let query_params = field_type(variant, FieldKind::QueryParams)
+                .map(|ty| quote!(.query_params::<#ty>(gen)));
+            let query = variant.query_fields().iter().map(|query| {
+                let name = query.name();
+                let (ty, kind) = query.kind();
+                let required = kind == QueryKind::Required;
+                // `Vec`s are described as arrays, `Option`s by their inner type
+                let ty = if kind == QueryKind::Repeated {
+                    &query.field().ty
+                } else {
+                    ty
+                };
+                quote!(.query_param::<#ty>(gen, #name, #required))
+            });
+            let body = field_type(variant, FieldKind::Body).map(|ty| quote!(.body::<#ty>(gen)));
+
+            Some(quote! {
+                operations.push(
+                    Operation::new(
+                        http::Method::from_bytes(#method.as_bytes()).unwrap(),
+                        #path,
+                        #variant_name,
+                    )
+                    #summary
+                    #description
+                    #(#path_params)*
+                    #query_params
+                    #(#query)*
+                    #body
+                );
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let forwarded = pathmap
+        .mounts()
+        .iter()
+        .map(|mount| (mount.variant(), mount.prefix()))
+        .chain(pathmap.fallbacks().iter().map(|variant| (variant, "")))
+        .map(|(variant, prefix)| {
+            let ty = variant
+                .forward_type()
+                .expect("internal error: forwarding variant without #[forward] field");
+            let variant_name = variant.variant_name().to_string();
+            quote! {
+                operations.extend(
+                    <#ty as Describe>::describe(gen)
+                        .into_iter()
+                        .map(|operation| operation.nested(#variant_name, #prefix)),
+                );
+            }
+        })
+        .collect::<Vec<_>>();
+
+    // Like for `FromRequest`, bounds are only needed when the type is generic
+    s.add_bounds(AddBounds::None);
+    let is_type_generic = s.ast().generics.type_params().next().is_some();
+    let where_clause = if is_type_generic {
+        let bounds = variant_data
+            .iter()
+            .flat_map(|v| v.field_uses())
+            .filter_map(|(field, kind)| {
+                let ty = &field.ty;
+                match kind {
+                    FieldKind::PathSegment | FieldKind::PathParams | FieldKind::QueryParams => {
+                        Some(quote!(#ty: ::hyperdrive::openapi::schemars::JsonSchema))
+                    }
+                    FieldKind::Query => {
+                        let ty = option_inner_type(ty)
+                            .or_else(|| vec_inner_type(ty))
+                            .unwrap_or(ty);
+                        Some(quote!(#ty: ::hyperdrive::openapi::schemars::JsonSchema))
+                    }
+                    FieldKind::Body => Some(quote!(#ty: ::hyperdrive::openapi::BodySchema)),
+                    FieldKind::Forward => Some(quote!(#ty: ::hyperdrive::openapi::Describe)),
+                    FieldKind::Header | FieldKind::Cookie | FieldKind::Guard => None,
+                }
+            })
+            .collect::<Vec<_>>();
+        quote!(where #(#bounds),*)
+    } else {
+        TokenStream::new()
+    };
+
+    s.gen_impl(quote! {
+        extern crate hyperdrive;
+        use hyperdrive::{
+            http,
+            openapi::{Describe, Operation, schemars::gen::SchemaGenerator},
+        };
+
+        gen impl Describe for @Self #where_clause {
+            fn describe(gen: &mut SchemaGenerator) -> Vec<Operation> {
+                let mut operations = Vec::new();
+                #(#operations)*
+                #(#forwarded)*
+                operations
+            }
+        }
+    })
+}
+
+/// Returns the type of the (first) field of `kind` in `variant`.
+fn field_type(variant: &VariantData, kind: FieldKind) -> Option<&syn::Type> {
+    variant
+        .field_uses()
+        .find(|(_, k)| *k == kind)
+        .map(|(field, _)| &field.ty)
+}
+
+/// Summary and description taken from a doc comment.
+struct Docs {
+    /// The first paragraph.
+    summary: Option<String>,
+    /// All following paragraphs.
+    description: Option<String>,
+}
+
+impl Docs {
+    fn parse(attrs: &[Attribute]) -> Self {
+        let lines = attrs
+            .iter()
+            .filter_map(|attr| match attr.parse_meta() {
+                Ok(Meta::NameValue(ref nv)) if nv.ident == "doc" => match &nv.lit {
+                    Lit::Str(s) => Some(s.value().trim().to_string()),
+                    _ => None,
+                },
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        let mut paragraphs = lines
+            .split(|line| line.is_empty())
+            .filter(|lines| !lines.is_empty())
+            .map(|lines| lines.join(" "));
+        let summary = paragraphs.next();
+        let description = paragraphs.collect::<Vec<_>>().join("\n\n");
+
+        Self {
+            summary,
+            description: if description.is_empty() {
+                None
+            } else {
+                Some(description)
+            },
+        }
+    }
+}
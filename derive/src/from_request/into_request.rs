@@ -0,0 +1,196 @@
+//! `IntoRequest` derive, the inverse of the `FromRequest` derive.
+
+use super::parse::{FieldKind, ItemData, PathPart, VariantData};
+use super::{bind_placeholders, build_uri, field_bindings};
+use crate::utils::{option_inner_type, vec_inner_type};
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::Field;
+use synstructure::{AddBounds, Structure};
+
+pub fn derive_into_request(mut s: Structure<'_>) -> TokenStream {
+    let is_struct = match &s.ast().data {
+        syn::Data::Union(_) => panic!("#[derive(IntoRequest)] is not allowed on unions"),
+        syn::Data::Struct(_) => true,
+        syn::Data::Enum(_) => false,
+    };
+
+    // Only the host pattern is used, the rest is parsed for validation
+    let item_data = ItemData::parse(&s.ast().attrs, is_struct);
+
+    let variant_data = s
+        .variants()
+        .iter()
+        .map(|variant| VariantData::parse(&variant.ast(), &item_data, is_struct))
+        .collect::<Vec<_>>();
+
+    let name = &s.ast().ident;
+    let arms = s
+        .variants()
+        .iter()
+        .zip(&variant_data)
+        .map(|(variant, data)| {
+            let path = if is_struct {
+                quote!(#name)
+            } else {
+                let variant = variant.ast().ident;
+                quote!(#name::#variant)
+            };
+
+            if !data.constructible() {
+                let msg = format!(
+                    "`{}` has no route attribute or #[forward] field and can not be turned into a request",
+                    data.variant_name()
+                );
+                return quote!(#path { .. } => panic!(#msg),);
+            }
+
+            let mut bindings = Vec::new();
+            let mut steps = Vec::new();
+
+            let start = match (data.forward_field(), data.forward_type()) {
+                (Some(field), Some(ty)) => {
+                    bindings.push(field.clone());
+                    let prefix = data.forward_prefix().unwrap_or("");
+                    quote!(hyperdrive::RequestBuilder::forward::<#ty>(#field, #prefix))
+                }
+                _ => quote!(hyperdrive::RequestBuilder::new()),
+            };
+
+            if let Some(route) = data.routes().first() {
+                // Placeholders without a field take their value from the `#[path_params]` field
+                let placeholders = route.host_placeholders().iter().chain(route.placeholders());
+                let (placeholder_bindings, placeholder_steps) = bind_placeholders(placeholders, data);
+                bindings.extend(placeholder_bindings);
+                steps.extend(placeholder_steps);
+                bindings.extend(data.query_params_field().cloned());
+                bindings.extend(
+                    data.query_fields()
+                        .iter()
+                        .map(|query| query.field().ident.clone().unwrap()),
+                );
+                let method = route.method();
+                let uri = build_uri(route, data);
+                steps.push(quote!(_hyperdrive_request.route(#method, #uri);));
+
+                if let Some(host) = route.host() {
+                    let mut format = String::new();
+                    let mut args = Vec::new();
+                    for part in host.parts() {
+                        match part {
+                            PathPart::Literal(lit) => {
+                                format.push_str(&lit.replace('{', "{{").replace('}', "}}"))
+                            }
+                            PathPart::Placeholder(ident) | PathPart::Rest(ident) => {
+                                format.push_str("{}");
+                                args.push(ident);
+                            }
+                        }
+                    }
+                    steps.push(quote! {
+                        _hyperdrive_request.header("host", &format_args!(#format, #(#args),*));
+                    });
+                }
+            }
+
+            if let Some(body) = data.body_field() {
+                bindings.push(body.clone());
+                steps.push(quote!(_hyperdrive_request.body(#body);));
+            }
+
+            // Satisfy the route's media types (after the body, since it sets `Content-Type`)
+            if let Some(route) = data.routes().first() {
+                if let Some(consumes) = route.consumes() {
+                    let consumes = consumes.to_string();
+                    if !consumes.ends_with("/*") {
+                        steps.push(quote!(_hyperdrive_request.header("content-type", &#consumes);));
+                    }
+                }
+                if let Some(produces) = route.produces() {
+                    let produces = produces.to_string();
+                    steps.push(quote!(_hyperdrive_request.header("accept", &#produces);));
+                }
+            }
+
+            let mut add_field = |field: &Field, step: TokenStream| {
+                let ident = field.ident.clone().unwrap();
+                steps.push(if option_inner_type(&field.ty).is_some() {
+                    quote! {
+                        if let Some(#ident) = &#ident {
+                            #step
+                        }
+                    }
+                } else {
+                    step
+                });
+                bindings.push(ident);
+            };
+            for (field, header) in data.header_fields() {
+                let ident = field.ident.as_ref().unwrap();
+                add_field(field, quote!(_hyperdrive_request.header(#header, &#ident);));
+            }
+            for (field, cookie) in data.cookie_fields() {
+                let ident = field.ident.as_ref().unwrap();
+                add_field(field, quote!(_hyperdrive_request.cookie(#cookie, &#ident);));
+            }
+
+            let bindings = field_bindings(&bindings, data);
+
+            quote! {
+                #path { #(#bindings,)* .. } => {
+                    let mut _hyperdrive_request = #start;
+                    #(#steps)*
+                    _hyperdrive_request.build()
+                }
+            }
+        })
+        .collect::<Vec<_>>();
+
+    // Like for `FromRequest`, bounds are only needed when the type is generic
+    s.add_bounds(AddBounds::None);
+    let is_type_generic = s.ast().generics.type_params().next().is_some();
+    let where_clause = if is_type_generic {
+        let bounds = variant_data
+            .iter()
+            .flat_map(|v| v.field_uses())
+            .filter_map(|(field, kind)| {
+                let ty = &field.ty;
+                match kind {
+                    FieldKind::PathSegment => Some(quote!(#ty: ::std::fmt::Display)),
+                    FieldKind::Header | FieldKind::Cookie => {
+                        let ty = option_inner_type(ty).unwrap_or(ty);
+                        Some(quote!(#ty: ::std::fmt::Display))
+                    }
+                    FieldKind::Query => {
+                        let ty = option_inner_type(ty)
+                            .or_else(|| vec_inner_type(ty))
+                            .unwrap_or(ty);
+                        Some(quote!(#ty: ::std::fmt::Display))
+                    }
+                    FieldKind::PathParams | FieldKind::QueryParams => {
+                        Some(quote!(#ty: ::hyperdrive::serde::Serialize))
+                    }
+                    FieldKind::Body => Some(quote!(#ty: ::hyperdrive::body::IntoBody)),
+                    FieldKind::Forward => Some(quote!(#ty: ::hyperdrive::IntoRequest)),
+                    FieldKind::Guard => None,
+                }
+            })
+            .collect::<Vec<_>>();
+        quote!(where #(#bounds),*)
+    } else {
+        TokenStream::new()
+    };
+
+    s.gen_impl(quote! {
+        extern crate hyperdrive;
+        use hyperdrive::{IntoRequest, http, hyper};
+
+        gen impl IntoRequest for @Self #where_clause {
+            fn into_request(self) -> http::Request<hyper::Body> {
+                match self {
+                    #(#arms)*
+                }
+            }
+        }
+    })
+}
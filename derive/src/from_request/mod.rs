@@ -27,8 +27,8 @@
 //! }
 //! ```
 //!
-//! * Request path is matched completely (there is no regex support, although we
-//!   use regex internally)
+//! * Request path is matched completely, by a segment trie compiled into the
+//!   generated code (regexes are only used for `{field:regex}` constraints)
 //! * Path segments either match a literal (`/user/`) or a placeholder using
 //!   `FromStr` (`/:id`). The placeholder must not contain `/`, of course.
 //! * Query params are ignored (but can be deserialized)
@@ -66,12 +66,26 @@
 //!
 //! Placeholders must implement `Extract`.
 
+#[cfg(feature = "openapi")]
+mod describe;
+mod into_request;
 mod parse;
-
-use self::parse::{FieldKind, ItemData, PathMap, VariantData};
+mod to_uri;
+mod trie;
+
+#[cfg(feature = "openapi")]
+pub use self::describe::derive_describe;
+pub use self::into_request::derive_into_request;
+use self::parse::{
+    placeholder_name, positional_ident, FieldKind, ItemData, PathMap, PathPart, QueryField,
+    QueryKind, Route, TrailingSlash, VariantData,
+};
+pub use self::to_uri::derive_to_uri;
+use self::trie::PathTrie;
+use crate::utils::{option_inner_type, raw_inner_type, vec_inner_type};
 use proc_macro2::{Ident, Span, TokenStream};
 use quote::{quote, ToTokens};
-use std::iter::{self, FromIterator};
+use std::iter::FromIterator;
 use synstructure::{AddBounds, Structure, VariantInfo};
 
 pub fn derive_from_request(mut s: Structure<'_>) -> TokenStream {
@@ -84,7 +98,7 @@ pub fn derive_from_request(mut s: Structure<'_>) -> TokenStream {
         syn::Data::Enum(_) => is_struct = false,
     }
 
-    let item_data = ItemData::parse(s.ast().ident.clone(), &s.ast().attrs, is_struct);
+    let item_data = ItemData::parse(&s.ast().attrs, is_struct);
 
     let context = item_data.context().cloned().unwrap_or_else(|| {
         syn::parse_str("NoContext").expect("internal error: couldn't parse type")
@@ -93,28 +107,142 @@ pub fn derive_from_request(mut s: Structure<'_>) -> TokenStream {
     let variant_data = s
         .variants()
         .iter()
-        .map(|variant| {
-            let data = VariantData::parse(&variant.ast(), is_struct);
-            if data.constructible() {
-                // can be created by us
-                match &variant.ast().fields {
-                    syn::Fields::Unnamed(_) => panic!(
-                        "tuple variants are not supported (`{}::{}`)",
-                        s.ast().ident,
-                        variant.ast().ident
-                    ),
-                    _ => {}
-                }
-            }
-            data
-        })
+        .map(|variant| VariantData::parse(&variant.ast(), &item_data, is_struct))
         .collect::<Vec<_>>();
     let pathmap = PathMap::build(&item_data, &variant_data);
-    let all_regexes = pathmap
-        .paths()
-        .map(|p| p.regex().as_str().to_string())
+    let has_paths = pathmap.paths().next().is_some();
+
+    // All distinct HTTP methods used by any route. The generated code converts these to
+    // `http::Method`s via `Method::from_bytes`, which also supports extension methods.
+    let all_methods = pathmap.methods().collect::<Vec<_>>();
+    let method_index = |method: &str| -> usize {
+        all_methods
+            .iter()
+            .position(|m| *m == method)
+            .expect("internal error: method not in method list")
+    };
+
+    // The `(path index, method index, variant, route)` of all routes, in the order in which they
+    // should be tried. Routes with lower ranks come first, otherwise the declaration order is kept.
+    let mut ranked_routes = pathmap
+        .routes()
+        .map(|(i, route, variant)| {
+            (
+                route.rank(),
+                i,
+                method_index(route.method()),
+                variant.variant_name(),
+                route,
+            )
+        })
         .collect::<Vec<_>>();
-    let all_regexes = &all_regexes;
+    ranked_routes.sort_by_key(|&(rank, ..)| rank); // stable sort
+
+    // Conditions checking that the request path matched path `i`, and that the host matches the
+    // host pattern of `route` (if it has one).
+    let path_and_host_match = |i: usize, route: &Route| -> TokenStream {
+        match pathmap.host_index(route) {
+            Some(h) => quote!(matches[#i].is_some() && hosts[#h].is_some()),
+            None => quote!(matches[#i].is_some()),
+        }
+    };
+
+    // Condition checking that the request's `Content-Type` header matches the media type of
+    // `route` (`None` if the route doesn't restrict it).
+    let consumes_match = |route: &Route| {
+        route.consumes().map(|media| {
+            let media = media.to_string();
+            quote!(content_type_matches(request, #media))
+        })
+    };
+
+    // Expression choosing among candidate routes, evaluating to an `Option<#ty>`. Each candidate
+    // consists of the condition under which its route matches, the route, and the value to
+    // evaluate to. Routes whose media type isn't acceptable to the client are skipped. The route
+    // the client prefers most (see `MediaPreference`) is chosen, or the first of those (by rank).
+    let select_route = |candidates: Vec<(TokenStream, &Route, TokenStream)>, ty: TokenStream| {
+        if candidates
+            .iter()
+            .all(|(_, route, _)| route.produces().is_none())
+        {
+            let (conditions, values): (Vec<_>, Vec<_>) = candidates
+                .into_iter()
+                .map(|(condition, _, value)| (condition, value))
+                .unzip();
+            return quote! {
+                #( if #conditions { Some(#values) } else )* { None }
+            };
+        }
+
+        let candidates = candidates.into_iter().map(|(condition, route, value)| {
+            let preference = match route.produces() {
+                Some(media) => {
+                    let media = media.to_string();
+                    quote!(accept_preference(request, #media))
+                }
+                None => quote!(Some(MediaPreference::UNRESTRICTED)),
+            };
+            quote! {
+                if #condition {
+                    if let Some(preference) = #preference {
+                        if best.as_ref().map_or(true, |&(best, _)| preference > best) {
+                            best = Some((preference, #value));
+                        }
+                    }
+                }
+            }
+        });
+        quote! {{
+            let mut best: Option<(MediaPreference, #ty)> = None;
+            #(#candidates)*
+            best.map(|(_, candidate)| candidate)
+        }}
+    };
+
+    // Fallback variants are tried when no route accepts the request.
+    let fallbacks = fallback_chain(&s, &pathmap);
+
+    // Statements rejecting the request with `415 Unsupported Media Type` or `406 Not Acceptable`
+    // when a route matches everything about the request except its media types. Fallback
+    // variants are tried first, and the request is only rejected if they don't accept it either.
+    // `path_match` creates the condition checking the path (and host) of a route.
+    let media_type_errors = |path_match: &dyn Fn(usize, &Route, &Ident) -> TokenStream| {
+        let (method_match, consumes_ok): (Vec<_>, Vec<_>) = ranked_routes
+            .iter()
+            .filter(|(.., route)| route.consumes().is_some() || route.produces().is_some())
+            .map(|&(_, i, method, variant, route)| {
+                let path_match = path_match(i, route, variant);
+                let consumes = consumes_match(route).map(|cond| quote!(&& #cond));
+                (
+                    quote!((#path_match && *method == METHODS[#method])),
+                    quote!((#path_match && *method == METHODS[#method] #consumes)),
+                )
+            })
+            .unzip();
+
+        if method_match.is_empty() {
+            return quote!();
+        }
+
+        let reject = match &fallbacks {
+            Some(fallbacks) => quote! {
+                let future = #fallbacks;
+                return Box::new(future.map_err(move |e| media_type_fallback_error(status, e)));
+            },
+            None => quote!(return Error::from_status(status).into_future();),
+        };
+
+        quote! {
+            if #( #method_match )||* {
+                let status = if #( #consumes_ok )||* {
+                    StatusCode::NOT_ACCEPTABLE
+                } else {
+                    StatusCode::UNSUPPORTED_MEDIA_TYPE
+                };
+                #reject
+            }
+        }
+    };
 
     // Ensure that there's at least 1 way for us to instantiate the type
     if !variant_data.iter().any(|v| v.constructible()) {
@@ -130,60 +258,62 @@ pub fn derive_from_request(mut s: Structure<'_>) -> TokenStream {
         );
     }
 
-    let capturing_regexes = pathmap
-        .paths()
-        .map(|path| {
-            let regex = path.regex();
-            if regex.captures_len() > 0 {
-                // Captures something, so we need to store it separately
-                let r = regex.as_str();
-                quote!(Some(Regex::new(#r).expect("internal error: generated invalid regex")))
-            } else {
-                quote!(None)
-            }
-        })
-        .collect::<Vec<_>>();
-
     let (variants, variant_matches_path): (Vec<_>, Vec<_>) = variant_data
         .iter()
-        .zip(s.variants())
-        .filter_map(|(data, variant)| {
+        .filter_map(|data| {
             if let Some(route) = data.routes().first() {
                 let matches_path = if route.placeholders().is_empty() {
                     // If there's no placeholders, there's no FromStr impls we have to check
                     quote!(true)
                 } else {
-                    let tys = route
+                    let fields = route
                         .placeholders()
-                        .iter()
-                        .map(|name| {
-                            variant
-                                .ast()
-                                .fields
-                                .iter()
-                                .find(|field| field.ident.as_ref() == Some(name))
-                                .expect("internal error: couldn't find field by name")
-                                .ty
-                                .clone()
-                        })
-                        .collect::<Vec<_>>();
-                    let indices = tys
                         .iter()
                         .enumerate()
-                        .map(|(i, _)| i + 1)
+                        .filter_map(|(i, name)| {
+                            data.placeholder_field(name).map(|field| (i, field))
+                        });
+                    let mut checks = fields
+                        .map(|(i, field)| placeholder_matches(&field.ty, i))
                         .collect::<Vec<_>>();
 
-                    quote! {
-                        let caps = regex
-                            .captures(path)
-                            .expect("internal error: regex first matched but now didn't?");
-
-                        #( <#tys as FromStr>::from_str(
-                            caps
-                                .get(#indices)
-                                .expect("internal error: capture group did not match anything")
-                                .as_str()
-                        ).is_ok() )&&*
+                    // The `#[path_params]` field receives all placeholders without a field of their
+                    // own. It can only be checked if none of them are in the host (host captures
+                    // aren't available here, so those are only checked when constructing the
+                    // variant). Like for `FromStr` fields, invalid percent-encoding still selects
+                    // the route, so that it is rejected with `400 Bad Request`.
+                    let host_params = route
+                        .host_placeholders()
+                        .iter()
+                        .any(|name| data.placeholder_field(name).is_none());
+                    match data.path_params_field() {
+                        Some(field) if !host_params => {
+                            let ty = &field.ty;
+                            let params = route
+                                .placeholders()
+                                .iter()
+                                .enumerate()
+                                .filter(|(_, name)| data.placeholder_field(name).is_none())
+                                .map(|(i, name)| {
+                                    let name = placeholder_name(name);
+                                    quote!((#name, caps[#i]))
+                                });
+                            checks.push(quote! {
+                                match decode_path::<#ty>(&[#(#params),*]) {
+                                    Ok(_) => true,
+                                    Err(e) => e.http_status() == StatusCode::BAD_REQUEST,
+                                }
+                            });
+                        }
+                        _ => {}
+                    }
+
+                    if checks.is_empty() {
+                        quote!(true)
+                    } else {
+                        quote! {
+                            #( (#checks) )&&*
+                        }
                     }
                 };
                 Some((data.variant_name().clone(), matches_path))
@@ -202,135 +332,183 @@ pub fn derive_from_request(mut s: Structure<'_>) -> TokenStream {
         .unzip();
     let variants = &variants;
 
-    let mut regex_match_arms = pathmap
-        .paths()
-        .enumerate()
-        .flat_map(|(i, pathinfo)| {
-            pathinfo
-                .method_map()
-                .map(move |(method, variant)| {
-                    let variant = &variant.variant_name();
-                    quote! {
-                        (Some(#i), &http::Method::#method) => Variant::#variant,
+    // Code to run when the request path matches at least one route, but no route accepts the
+    // request (because of the method, or in fallthrough mode, also because a placeholder failed to
+    // parse). Here, we can still #[forward] to another `FromRequest` impl, so this doesn't always
+    // result in an error.
+    let no_route_accepts = if !has_paths {
+        None
+    } else {
+        // This evaluates to a `Vec<&'static Method>` containing all methods accepted by the
+        // routes whose path matches the request path (there can be more than one when ranks or
+        // fallthrough are used), ignoring any #[forward]-marked `FromRequest` impl.
+        // For routes with placeholders, the request path is checked against all variants sharing
+        // the path pattern, to see if their FromStr succeeds.
+        let per_path = pathmap.paths().enumerate().map(|(i, pathinfo)| {
+            let checks = pathinfo.method_map().map(|(method, variant, route)| {
+                let variant = variant.variant_name();
+                let method = method_index(method);
+                let host = pathmap
+                    .host_index(route)
+                    .map(|h| quote!(hosts[#h].is_some() &&));
+                quote! {
+                    if #host variant_matches_path(Variant::#variant, caps)
+                        && !methods.contains(&&METHODS[#method])
+                    {
+                        methods.push(&METHODS[#method]);
                     }
-                })
-                .chain(iter::once({
-                    // This arm matches when the path matches, but an incorrect method is used.
-                    // Here, we can still #[forward] to another `FromRequest` impl, so this doesn't
-                    // always.
-
-                    // This evaluates to a `&'static [Method]` or `Vec<Method>` containing all
-                    // methods accepted by the invoked route, ignoring any #[forward]-marked
-                    // `FromRequest` impl.
-                    let find_accepted_methods = {
-                        if pathinfo.regex().captures_len() == 0 {
-                            // No captures, no FromStr: We have a statically known list of allowed
-                            // methods.
-                            let methods = pathinfo.method_map().map(|(m, _)| m).collect::<Vec<_>>();
-
-                            quote! {
-                                &[
-                                    #( Method::#methods, )*
-                                ]
-                            }
-                        } else {
-                            // We have placeholders; check the request path against all variants that
-                            // share the same path pattern
-                            let (variants, methods): (Vec<_>, Vec<_>) = pathinfo
-                                .method_map()
-                                .map(|(method, variant)| (variant.variant_name(), method))
-                                .unzip();
-
-                            quote! {{
-                                let path = request.uri().path();
-                                let regex = REGEXES[#i].as_ref().unwrap();
-                                let mut methods = Vec::new();
-
-                                #(
-                                    if variant_matches_path(Variant::#variants, regex, path) {
-                                        methods.push(&http::Method::#methods);
-                                    }
-                                )*
-                                methods
-                            }}
-                        }
-                    };
-
-                    if let Some(fallback) = pathmap.fallback() {
-                        // If there's a fallback variant, it might save us and accept the request.
-                        // If not, we match the request path against all variants and collect the
-                        // accepted methods.
-                        // Note that if the fallback variant fails to match with a "wrong
-                        // method" error, we need to merge the sets of accepted methods.
+                }
+            });
 
-                        let info = s
-                            .variants()
-                            .iter()
-                            .find(|v| v.ast().ident == fallback.variant_name())
-                            .expect("couldn't find fallback variant");
-                        let construct = construct_variant(info, fallback);
+            quote! {
+                if let Some(caps) = &matches[#i] {
+                    #(#checks)*
+                }
+            }
+        });
+        let find_accepted_methods = quote! {{
+            let mut methods: Vec<&'static http::Method> = Vec::new();
+            #(#per_path)*
+            methods
+        }};
 
-                        quote! {
-                            (Some(#i), _) => {
-                                // FIXME `find_accepted_methods` needs access to `request.uri()`
-                                // in the `map_err`. Clean things up so we don't need this.
-                                let mut tmp_request = http::Request::new(());
-                                *tmp_request.uri_mut() = request.uri().clone();
-
-                                let future = #construct;
-                                let future = future.map_err(move |mut e| {
-                                    use hyperdrive::{Error, http::StatusCode};
-
-                                    // If the #[forward]ed impl also failed with "wrong_method", add
-                                    // our accepted methods to it.
-                                    if let Some(err) = e.downcast_mut::<Error>() {
-                                        if err.http_status() == StatusCode::METHOD_NOT_ALLOWED {
-                                            let request = tmp_request;
-                                            let mut our_methods = Vec::from(#find_accepted_methods);
-                                            let inner_methods = err.allowed_methods()
-                                                .expect("`WrongMethod` but no `allowed_methods()`?");
-
-                                            our_methods.extend(inner_methods);
-
-                                            Box::new(Error::wrong_method(Vec::from(our_methods)))
-                                        } else {
-                                            e
-                                        }
-                                    } else {
-                                        e
-                                    }
-                                });
+        if let Some(fallbacks) = &fallbacks {
+            // If there are fallback variants, they might save us and accept the request.
+            // If not, we match the request path against all variants and collect the
+            // accepted methods.
+            // Note that if the fallback variants fail to match with a "wrong
+            // method" error, we need to merge the sets of accepted methods.
+            let (clone_host, match_host) = if pathmap.uses_hosts() {
+                (
+                    quote!(let host = host.clone();),
+                    quote!(let hosts = match_host(&host);),
+                )
+            } else {
+                (quote!(), quote!())
+            };
 
-                                return Box::new(future);
+            Some(quote! {
+                // `find_accepted_methods` needs access to the path (and host) in the `map_err`.
+                let uri = routed.uri().clone();
+                let method = method.clone();
+                #clone_host
+
+                let future = #fallbacks;
+                let future = future.map_err(move |mut e| {
+                    use hyperdrive::Error;
+
+                    // If the #[forward]ed impl also failed with "wrong_method" (or answered an
+                    // `OPTIONS` request), add our accepted methods to it.
+                    if let Some(err) = e.downcast_mut::<Error>() {
+                        if let Some(inner_methods) = err.allowed_methods() {
+                            let path = uri.path();
+                            let matches = match_path(path);
+                            #match_host
+                            let mut our_methods = #find_accepted_methods;
+                            for inner in inner_methods {
+                                if !our_methods.contains(inner) {
+                                    our_methods.push(inner);
+                                }
                             }
+
+                            Box::new(method_not_accepted(&method, our_methods))
+                        } else {
+                            e
                         }
                     } else {
-                        // No fallback variant. Match the request path against all variants
-                        // sharing the same path pattern, checking if the FromStr succeeds,
-                        // and collecting all accepted methods.
-                        quote! {
-                            (Some(#i), _) => {
-                                let methods = #find_accepted_methods;
-                                return Error::wrong_method(methods).into_future();
-                            }
-                        }
+                        e
                     }
-                }))
+                });
+
+                return Box::new(future);
+            })
+        } else if item_data.fallthrough() {
+            // No fallback variant. In fallthrough mode, we might get here when placeholders
+            // failed to parse, in which case no method is accepted and the path isn't found.
+            Some(quote! {
+                let methods = #find_accepted_methods;
+                if methods.is_empty() {
+                    return Error::from_status(StatusCode::NOT_FOUND).into_future();
+                } else {
+                    return method_not_accepted(method, methods).into_future();
+                }
+            })
+        } else {
+            // No fallback variant. Match the request path against all variants
+            // sharing the same path pattern, checking if the FromStr succeeds,
+            // and collecting all accepted methods. `OPTIONS` requests are answered with that list.
+            Some(quote! {
+                let methods = #find_accepted_methods;
+                return method_not_accepted(method, methods).into_future();
+            })
+        }
+    };
+
+    let mut route_match_arms = pathmap
+        .paths()
+        .enumerate()
+        .flat_map(|(i, pathinfo)| {
+            let pathmap = &pathmap;
+            pathinfo.method_map().map(move |(method, variant, route)| {
+                let variant = &variant.variant_name();
+                let method = method_index(method);
+                let host = pathmap
+                    .host_index(route)
+                    .map(|h| quote!(&& hosts[#h].is_some()));
+                quote! {
+                    (Some(#i), m) if *m == METHODS[#method] #host => Variant::#variant,
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+
+    if let Some(no_route_accepts) = &no_route_accepts {
+        // This arm matches when the path matches, but an incorrect method is used.
+        route_match_arms.push(quote! {
+            (Some(_), _) => {
+                #no_route_accepts
+            }
+        });
+    }
+
+    // Variants with a mounted `#[forward]` field are used when no route matches, but the path
+    // starts with the mount prefix.
+    let mount_conditions = pathmap
+        .mounts()
+        .iter()
+        .map(|mount| {
+            let prefix = mount.prefix();
+            quote! {
+                path.starts_with(#prefix)
+                    && path[#prefix.len()..].bytes().next().map_or(true, |b| b == b'/')
+            }
         })
         .collect::<Vec<_>>();
+    let mount_variants = pathmap
+        .mounts()
+        .iter()
+        .map(|mount| mount.variant().variant_name())
+        .collect::<Vec<_>>();
+
+    for (condition, variant) in mount_conditions.iter().zip(&mount_variants) {
+        route_match_arms.push(quote! {
+            (None, _) if #condition => Variant::#variant,
+        });
+    }
 
-    if let Some(fallback) = pathmap.fallback() {
-        // If we have a fallback route, return it when no other regex matches.
+    if let Some(fallback) = pathmap.fallbacks().first() {
+        // If we have a fallback route, return it when no other path matches (later fallback
+        // variants are tried by the first one's arm).
         // Note that this is not sufficient to correctly handle #[forward].
         let variant = fallback.variant_name();
-        regex_match_arms.push(quote! {
+        route_match_arms.push(quote! {
             _ => {
                 Variant::#variant
             }
         });
     } else {
         // No fallback route, add an error arm
-        regex_match_arms.push(quote! {
+        route_match_arms.push(quote! {
             _ => {
                 return Error::from_status(StatusCode::NOT_FOUND).into_future();
             }
@@ -342,45 +520,247 @@ pub fn derive_from_request(mut s: Structure<'_>) -> TokenStream {
         .iter()
         .zip(&variant_data)
         .filter_map(|(variant, data)| {
-            if data.constructible() {
-                Some(construct_variant(variant, data))
+            let first_fallback = pathmap.fallbacks().first().map(VariantData::variant_name);
+            if first_fallback == Some(data.variant_name()) {
+                fallback_chain(&s, &pathmap)
+            } else if data.constructible() {
+                Some(construct_variant(variant, data, &pathmap))
             } else {
                 None
             }
         })
         .collect::<Vec<_>>();
 
-    // The `lazy_static!` declarations containing the route regexes
-    let statics = if all_regexes.is_empty() {
+    // The `lazy_static!` declaration containing the route methods, and the `match_path` function
+    let method_names = &all_methods;
+    let statics = if !has_paths {
         // No routes
         quote! {}
     } else {
+        let match_path = PathTrie::build(&pathmap).generate();
+        let match_host = if pathmap.uses_hosts() {
+            let regexes = pathmap.hosts().map(|host| host.regex());
+            quote! {
+                // Matches the request host against all host patterns. Returns the captured
+                // placeholders of every matching pattern.
+                fn match_host(host: &str) -> Vec<Option<hyperdrive::regex::Captures<'_>>> {
+                    lazy_static! {
+                        static ref HOSTS: Vec<Regex> = vec![
+                            #(
+                                Regex::new(#regexes)
+                                    .expect("invalid host regex from FromRequest derive"),
+                            )*
+                        ];
+                    }
+
+                    HOSTS.iter().map(|regex| regex.captures(host)).collect()
+                }
+            }
+        } else {
+            quote!()
+        };
         quote! {
-            lazy_static! {
-                static ref ROUTES: RegexSet = RegexSet::new(&[
-                    #(#all_regexes,)*
-                ][..]).expect("invalid regex from FromRequest derive");
+            #match_path
+            #match_host
 
-                static ref REGEXES: Vec<Option<Regex>> = vec![
-                    #(#capturing_regexes,)*
+            lazy_static! {
+                static ref METHODS: Vec<http::Method> = vec![
+                    #(
+                        http::Method::from_bytes(#method_names.as_bytes())
+                            .expect("invalid HTTP method from FromRequest derive"),
+                    )*
                 ];
             }
         }
     };
 
-    // An expression evaluating to the index of the matching regex (or `None`)
-    let matching_regex = if all_regexes.is_empty() {
-        quote!(None)
+    // Statements that find the route matching the request and define `variant`, the `Variant` to
+    // construct.
+    let select_variant = if !has_paths {
+        quote! {
+            let index: Option<usize> = None;
+
+            let variant = match (index, method) {
+                #(#route_match_arms)*
+            };
+        }
+    } else if item_data.fallthrough() {
+        // Try all routes matching the path and method in order, until the placeholders of one of
+        // them parse successfully. Among routes producing different media types, the one preferred
+        // by the client wins.
+        let path_match = |i: usize, route: &Route, variant: &Ident| {
+            let host = pathmap
+                .host_index(route)
+                .map(|h| quote!(hosts[#h].is_some() &&));
+            quote! {
+                #host matches[#i].map_or(false, |caps| variant_matches_path(Variant::#variant, &caps))
+            }
+        };
+        let candidates = ranked_routes
+            .iter()
+            .map(|&(_, i, method, variant, route)| {
+                let path_match = path_match(i, route, variant);
+                let consumes = consumes_match(route).map(|cond| quote!(&& #cond));
+                (
+                    quote!(*method == METHODS[#method] && #path_match #consumes),
+                    route,
+                    quote!((Some(#i), Variant::#variant)),
+                )
+            })
+            .collect();
+        let candidate = select_route(candidates, quote!((Option<usize>, Variant)));
+        let media_type_errors = media_type_errors(&path_match);
+        let mounts = mount_conditions
+            .iter()
+            .zip(&mount_variants)
+            .map(|(condition, variant)| {
+                quote! {
+                    if #condition {
+                        Some((None, Variant::#variant))
+                    }
+                }
+            });
+
+        quote! {
+            let matches = match_path(path);
+            let candidate: Option<(Option<usize>, Variant)> = match #candidate {
+                Some(candidate) => Some(candidate),
+                None => #( #mounts else )* { None },
+            };
+
+            let (index, variant) = match candidate {
+                Some(candidate) => candidate,
+                None => {
+                    #media_type_errors
+
+                    // Also reached when no path matched at all (then no methods are accepted).
+                    #no_route_accepts
+                }
+            };
+        }
+    } else if pathmap.uses_hosts() || pathmap.uses_media_types() {
+        // Multiple routes might match, since routes restricted to different hosts or media types
+        // may overlap. Pick the route matching the path, host, method and media types that
+        // produces the media type preferred by the client, or the first by rank. If there is
+        // none, but a route only fails to match the media types, reject the request's media types
+        // (unless a fallback variant accepts it). Otherwise, pick the path of any route matching path and host to
+        // produce a "wrong method" error. If no route matches the host, the path isn't found.
+        let full_match = ranked_routes
+            .iter()
+            .map(|&(_, i, method, variant, route)| {
+                let condition = path_and_host_match(i, route);
+                let consumes = consumes_match(route).map(|cond| quote!(&& #cond));
+                (
+                    quote!(#condition && *method == METHODS[#method] #consumes),
+                    route,
+                    quote!((#i, Variant::#variant)),
+                )
+            })
+            .collect();
+        let full_match = select_route(full_match, quote!((usize, Variant)));
+        let media_type_errors =
+            media_type_errors(&|i: usize, route: &Route, _: &Ident| path_and_host_match(i, route));
+        let host_match = ranked_routes.iter().map(|&(_, i, _, _, route)| {
+            let condition = path_and_host_match(i, route);
+            quote! {
+                if #condition {
+                    Some(#i)
+                }
+            }
+        });
+
+        quote! {
+            let matches = match_path(path);
+            let candidate: Option<(usize, Variant)> = #full_match;
+
+            let (index, variant) = match candidate {
+                Some((index, variant)) => (Some(index), variant),
+                None => {
+                    #media_type_errors
+
+                    let index: Option<usize> = #( #host_match else )* { None };
+                    let variant = match (index, method) {
+                        #(#route_match_arms)*
+                    };
+                    (index, variant)
+                }
+            };
+        }
+    } else if pathmap.uses_ranks() {
+        // Multiple paths might match. Pick the path of the route with the lowest rank that
+        // also accepts the request method. If there is none, pick any matching path to
+        // produce a "wrong method" error.
+        let (indices, methods): (Vec<_>, Vec<_>) =
+            ranked_routes.iter().map(|&(_, i, m, ..)| (i, m)).unzip();
+
+        quote! {
+            let matches = match_path(path);
+            let index: Option<usize> = [ #( (#indices, #methods), )* ]
+                .iter()
+                .find(|&&(i, m)| matches[i].is_some() && *method == METHODS[m])
+                .map(|&(i, _)| i)
+                .or_else(|| matches.iter().position(Option::is_some));
+
+            let variant = match (index, method) {
+                #(#route_match_arms)*
+            };
+        }
     } else {
-        quote! {{
-            let matches = ROUTES.matches(path);
+        quote! {
+            let matches = match_path(path);
             debug_assert!(
-                matches.iter().count() <= 1,
-                "internal error: FromRequest derive produced overlapping regexes (path={},method={},regexes={:?})",
-                path, method, &[ #(#all_regexes),* ]
+                matches.iter().filter(|m| m.is_some()).count() <= 1,
+                "internal error: FromRequest derive produced overlapping paths (path={},method={})",
+                path, method
             );
-            matches.iter().next()
-        }}
+            let index: Option<usize> = matches.iter().position(Option::is_some);
+
+            let variant = match (index, method) {
+                #(#route_match_arms)*
+            };
+        }
+    };
+
+    // Whether any variant passes the request on to a `#[forward]`ed type
+    let has_forwards = variant_data.iter().any(|v| v.forward_field().is_some());
+
+    // Statements that normalize the request path according to the `trailing_slash` policy, and
+    // define `path` (the path routes are matched against) and `routed` (the request passed to
+    // `#[forward]`ed types). `request` itself is never replaced, so that guards and fields still
+    // see all of its extensions.
+    let normalize = match item_data.trailing_slash() {
+        None if has_forwards => quote! {
+            let routed = request;
+            let path = request.uri().path();
+        },
+        None => quote! {
+            let path = request.uri().path();
+        },
+        Some(policy) => {
+            let policy = match policy {
+                TrailingSlash::Strict => quote!(TrailingSlash::Strict),
+                TrailingSlash::Ignore => quote!(TrailingSlash::Ignore),
+                TrailingSlash::Redirect => quote!(TrailingSlash::Redirect),
+            };
+            let is_route = if has_paths {
+                quote!(|path: &str| match_path(path).iter().any(Option::is_some))
+            } else {
+                quote!(|_: &str| false)
+            };
+
+            quote! {
+                let normalized;
+                let routed = match normalize_request(request, #policy, #is_route) {
+                    Ok(None) => request,
+                    Ok(Some(routed)) => {
+                        normalized = Arc::new(routed);
+                        &normalized
+                    }
+                    Err(e) => return e.into_future(),
+                };
+                let path = routed.uri().path();
+            }
+        }
     };
 
     // Don't automatically add bounds, we'll do that ourselves
@@ -408,11 +788,27 @@ pub fn derive_from_request(mut s: Structure<'_>) -> TokenStream {
         Vec::new()
     };
 
+    // Statements that extract the request host and match it against all host patterns.
+    let host = if pathmap.uses_hosts() {
+        quote! {
+            let host = request_host(request);
+            let hosts = match_host(&host);
+        }
+    } else {
+        quote!()
+    };
+
+    let route_table = route_table(&pathmap);
+
     s.gen_impl(quote!(
         extern crate hyperdrive;
         use hyperdrive::{
-            FromBody, FromRequest, Guard, DefaultFuture, NoContext, BoxedError, Error,
-            http::{self, StatusCode}, hyper, lazy_static, regex::{RegexSet, Regex},
+            FromBody, FromRequest, Guard, DefaultFuture, NoContext, BoxedError, Error, RouteInfo,
+            http::{self, StatusCode}, hyper, lazy_static, regex::Regex, SegmentPart, match_segment,
+            percent_decode, normalize_request, TrailingSlash, method_not_accepted, request_host,
+            content_type_matches, accept_preference, MediaPreference, media_type_fallback_error,
+            BodySlot, merge_fallback_errors, BufferedBody,
+            decode_nested, decode_path,
             futures::{IntoFuture, Future},
         };
         // Make sure `.as_ref()` always refers to the `AsRef` trait in libstd.
@@ -431,44 +827,70 @@ pub fn derive_from_request(mut s: Structure<'_>) -> TokenStream {
                 context: Self::Context,
             ) -> Self::Future {
                 // Step 0: `Variant` has all variants of the input enum that have a route attribute
-                // but without any data.
+                // but without any data. In fallthrough mode, the fallback variant is never
+                // selected through `Variant`.
+                #[allow(dead_code)]
                 enum Variant {
                     #(#variants,)*
                 }
 
-                // Returns whether `self`, with `regex`, matches `path`.
+                // Returns whether `var` accepts the placeholder values `caps` captured by
+                // `match_path`.
                 //
                 // This checks all path placeholder's `FromStr` implementations against the
-                // path segments and returns `true` if they all succeed.
+                // captured values and returns `true` if they all succeed.
                 //
                 // This is a closure instead of a function to allow use of the `impl`-level generics
                 // (if any).
-                let variant_matches_path = |var: Variant, regex: &Regex, path: &str| -> bool {
+                let variant_matches_path = |var: Variant, caps: &[&str]| -> bool {
                     match var {
                         #( Variant::#variants => { #variant_matches_path } )*
                     }
                 };
 
-                // Step 1: Match against the generated regex set and inspect the HTTP
+                // Step 1: Match the path against the generated segment trie and inspect the HTTP
                 // method in order to find the route that matches.
                 #statics
 
-                let method = request.method();
-                let path = request.uri().path();
-                let index: Option<usize> = #matching_regex;
+                #normalize
 
-                let variant = match (index, method) {
-                    #(#regex_match_arms)*
-                };
+                let method = request.method();
+                #host
+                #select_variant
 
                 match variant {
                     #( Variant::#variants => #variant_arms, )*
                 }
             }
+
+            fn routes() -> &'static [RouteInfo]
+            where
+                Self: 'static,
+            {
+                #route_table
+            }
         }
     ))
 }
 
+/// Generates a condition checking that the value captured for placeholder `i` (in `caps`) can be
+/// converted to `ty`.
+///
+/// The value is decoded like `construct_variant` does. Invalid encodings still match, so that the
+/// route rejects them with `400 Bad Request`.
+fn placeholder_matches(ty: &syn::Type, i: usize) -> TokenStream {
+    if raw_inner_type(ty).is_some() {
+        quote!(<#ty as FromStr>::from_str(caps[#i]).is_ok())
+    } else {
+        quote! {
+            match percent_decode(caps[#i]) {
+                Ok(value) => <#ty as FromStr>::from_str(&value).is_ok(),
+                Err(_) => true,
+            }
+        }
+    }
+}
+
 /// Information about trait bounds that need to hold for a `FromRequest` impl to be applicable.
 struct Bounds {
     /// Additional type parameters to add to the impl.
@@ -535,7 +957,26 @@ fn generate_trait_bounds(item: &ItemData, variants: &[VariantData]) -> Bounds {
                         ),
                     ],
                 },
-                FieldKind::QueryParams => Bounds {
+                FieldKind::Header | FieldKind::Cookie | FieldKind::Query => {
+                    // `Option<T>` fields are optional, the `FromStr` bound is on `T` (the same
+                    // goes for repeated `Vec<T>` query parameters).
+                    let ty = match vec_inner_type(&ty) {
+                        Some(inner) if field_kind == FieldKind::Query => inner,
+                        _ => option_inner_type(&ty).unwrap_or(&ty),
+                    };
+                    Bounds {
+                        addl_ty_params: Vec::new(),
+                        impl_bounds: vec![
+                            quote!( #ty:
+                                ::std::str::FromStr + ::std::marker::Send + 'static
+                            ),
+                            quote!( <#ty as ::std::str::FromStr>::Err:
+                                ::std::error::Error + ::std::marker::Sync + ::std::marker::Send + 'static
+                            ),
+                        ],
+                    }
+                },
+                FieldKind::PathParams | FieldKind::QueryParams => Bounds {
                     addl_ty_params: Vec::new(),
                     impl_bounds: vec![quote!( #ty:
                         ::hyperdrive::serde::de::DeserializeOwned +
@@ -623,36 +1064,239 @@ fn generate_trait_bounds(item: &ItemData, variants: &[VariantData]) -> Bounds {
                         ],
                     }
                 },
-                FieldKind::Forward => Bounds {
-                    addl_ty_params: Vec::new(),
-                    impl_bounds: vec![
-                        // FIXME: support `AsRef` conversion here too
-                        quote!( #ty:
-                            ::hyperdrive::FromRequest<Context=#context> +
-                            ::std::marker::Send +
-                            'static
-                        ),
-                    ],
+                FieldKind::Forward => {
+                    let forward_context = mkty("Forward_Context");
+                    Bounds {
+                        addl_ty_params: Vec::new(),
+                        impl_bounds: vec![
+                            quote!( #ty:
+                                ::hyperdrive::FromRequest<Context=#forward_context> +
+                                ::std::marker::Send +
+                                'static
+                            ),
+                            quote!( #context: AsRef<#forward_context> ),
+                            // The nested impl takes its context by value
+                            quote!( #forward_context:
+                                ::hyperdrive::RequestContext + ::std::clone::Clone
+                            ),
+                        ],
+                    }
                 },
             }
         })
         .collect();
 
+    // Multiple fallback variants all need the context
+    if variants.iter().filter(|v| v.is_fallback()).count() > 1 {
+        bounds
+            .impl_bounds
+            .push(quote!( #context: ::std::clone::Clone ));
+    }
+
     bounds.addl_ty_params.extend(ty_params);
     bounds
 }
 
-/// Generates all the code needed to build an enum variant from a matching
-/// request.
+/// Generates the body of `FromRequest::routes`, which lists all routes in `pathmap` as well as
+/// those of all `#[forward]`ed types.
+fn route_table(pathmap: &PathMap) -> TokenStream {
+    let routes = pathmap.routes().map(|(_, route, variant)| {
+        let method = route.method();
+        let path = route.path();
+        let variant_name = variant.variant_name().to_string();
+        let placeholders = route.placeholders().iter().map(|name| {
+            let field = variant
+                .placeholder_field(name)
+                .or_else(|| variant.path_params_field())
+                .expect("internal error: no field for placeholder");
+            let name = placeholder_name(name);
+            let ty = type_name(&field.ty);
+            quote!(hyperdrive::PlaceholderInfo::new(#name, #ty))
+        });
+        let body = variant.body_field().is_some();
+        let query_params = variant.query_params_field().is_some();
+        let forward = variant.forward_field().is_some();
+        let implied = route.is_implied();
+
+        quote! {
+            table.push(RouteInfo::new(
+                http::Method::from_bytes(#method.as_bytes()).unwrap(),
+                #path,
+                vec![#(#placeholders),*],
+                #variant_name,
+                #body,
+                #query_params,
+                #forward,
+                #implied,
+            ));
+        }
+    });
+
+    let forwarded = pathmap
+        .mounts()
+        .iter()
+        .map(|mount| (mount.variant(), mount.prefix()))
+        .chain(pathmap.fallbacks().iter().map(|variant| (variant, "")))
+        .map(|(variant, prefix)| {
+            let ty = variant
+                .forward_type()
+                .expect("internal error: forwarding variant without #[forward] field");
+            let variant_name = variant.variant_name().to_string();
+            quote! {
+                table.extend(
+                    <#ty as FromRequest>::routes()
+                        .iter()
+                        .map(|route| route.nested(#variant_name, #prefix)),
+                );
+            }
+        });
+
+    quote! {
+        RouteInfo::cached::<Self, _>(|| {
+            let mut table = Vec::new();
+            #(#routes)*
+            #(#forwarded)*
+            table
+        })
+    }
+}
+
+/// Returns the fields of `variant` that hold the values of `placeholders`, and statements defining
+/// variables for the placeholders without a field of their own.
 ///
-/// Returns an expression of type `DefaultFuture<Self, BoxedError>`.
+/// Those placeholders take their value from the `#[path_params]` field, which is then also
+/// returned.
+fn bind_placeholders<'a>(
+    placeholders: impl IntoIterator<Item = &'a Ident>,
+    variant: &VariantData,
+) -> (Vec<Ident>, Vec<TokenStream>) {
+    let mut bindings = Vec::new();
+    let mut steps = Vec::new();
+    for placeholder in placeholders {
+        if variant.placeholder_field(placeholder).is_some() {
+            bindings.push(placeholder.clone());
+        } else {
+            let path_params = variant.path_params_field().unwrap().ident.as_ref().unwrap();
+            let name = placeholder_name(placeholder);
+            steps.push(quote! {
+                let #placeholder = hyperdrive::encode_path_param(&#path_params, #name);
+            });
+        }
+    }
+    if let Some(field) = variant.path_params_field() {
+        bindings.push(field.ident.clone().unwrap());
+    }
+
+    (bindings, steps)
+}
+
+/// Turns the identifiers of fields of `variant` into field patterns binding them to variables of
+/// the same name. Unnamed fields are bound by index (`Variant { 0: _0, .. }`).
+fn field_bindings(idents: &[Ident], variant: &VariantData) -> Vec<TokenStream> {
+    idents
+        .iter()
+        .map(|ident| match variant.unnamed_field_index(ident) {
+            Some(index) => {
+                let index = syn::Index::from(index);
+                quote!(#index: #ident)
+            }
+            None => quote!(#ident),
+        })
+        .collect()
+}
+
+/// Generates an expression that builds the URI of `route`.
 ///
-/// The generated code will do the following:
-/// * If the path has any segment placeholders:
-///   * Obtain the captures with the specific regex for this route
-///   * Call `FromStr` on all captured segments
+/// The values of the placeholders and the `#[query_params]` and `#[query]` fields of `variant` must
+/// be in scope as variables named like the fields.
+fn build_uri(route: &Route, variant: &VariantData) -> TokenStream {
+    let is_raw = |ident: &Ident| {
+        variant
+            .path_segment_fields()
+            .iter()
+            .any(|field| field.ident.as_ref() == Some(ident) && raw_inner_type(&field.ty).is_some())
+    };
+    let pushes = route.path_parts().into_iter().map(|part| match part {
+        PathPart::Literal(lit) => quote!(_hyperdrive_uri.literal(#lit);),
+        PathPart::Placeholder(ident) => {
+            if is_raw(&ident) {
+                quote!(_hyperdrive_uri.raw(&#ident);)
+            } else {
+                quote!(_hyperdrive_uri.placeholder(&#ident);)
+            }
+        }
+        PathPart::Rest(ident) => {
+            if is_raw(&ident) {
+                quote!(_hyperdrive_uri.raw(&#ident);)
+            } else {
+                quote!(_hyperdrive_uri.rest(&#ident);)
+            }
+        }
+    });
+    let query = variant.query_params_field().map(|query| {
+        if variant.query_params_nested() {
+            quote!(_hyperdrive_uri.query_nested(&#query);)
+        } else {
+            quote!(_hyperdrive_uri.query(&#query);)
+        }
+    });
+    let query_fields = variant.query_fields().iter().map(|query| {
+        let ident = &query.field().ident;
+        let name = query.name();
+        match query.kind().1 {
+            QueryKind::Required | QueryKind::Default => {
+                quote!(_hyperdrive_uri.query_param(#name, &#ident);)
+            }
+            QueryKind::Optional => quote! {
+                if let Some(value) = &#ident {
+                    _hyperdrive_uri.query_param(#name, value);
+                }
+            },
+            QueryKind::Repeated => quote! {
+                for value in #ident.iter() {
+                    _hyperdrive_uri.query_param(#name, value);
+                }
+            },
+        }
+    });
+
+    quote! {{
+        let mut _hyperdrive_uri = hyperdrive::UriBuilder::new();
+        #(#pushes)*
+        #query
+        #(#query_fields)*
+        _hyperdrive_uri.build()
+    }}
+}
+
+/// Renders `ty` as it would be written in source code.
+fn type_name(ty: &syn::Type) -> String {
+    ty.into_token_stream()
+        .to_string()
+        .replace(" :: ", "::")
+        .replace(":: ", "::")
+        .replace(" < ", "<")
+        .replace("< ", "<")
+        .replace(" >", ">")
+        .replace(" ,", ",")
+        .replace("& ", "&")
+}
+
+/// Generates all the code needed to build an enum variant from a matching
+/// request.
+///
+/// Returns an expression of type `DefaultFuture<Self, BoxedError>`.
+///
+/// The generated code will do the following:
+/// * If the path has any segment placeholders:
+///   * Obtain the placeholder values captured by `match_path` for this route
+///   * Percent-decode them (unless the field is a `Raw<T>`) and call `FromStr` on them
+/// * If the host pattern has any placeholders:
+///   * Call `FromStr` on the values captured by `match_host` (in `hosts`)
 /// * If it has `query_params`
 ///   * Deserialize from ?these&query=parameters
+/// * For each `#[header]` and `#[cookie]` field
+///   * Call `FromStr` on the header or cookie value
 /// * For each guard (= field that isn't mentioned in any attribute)
 ///   * Chain all calls to the `from_request` methods
 /// * If it has a `body`
@@ -660,15 +1304,14 @@ fn generate_trait_bounds(item: &ItemData, variants: &[VariantData]) -> Bounds {
 ///
 /// The code will also assume:
 /// * That `request` is the incoming request, and can be consumed.
-fn construct_variant(variant: &VariantInfo<'_>, data: &VariantData) -> TokenStream {
-    let field_by_name = |name: &Ident| -> &syn::Field {
-        variant
-            .ast()
-            .fields
-            .iter()
-            .find(|field| field.ident.as_ref() == Some(name))
-            .expect("internal error: couldn't find field by name")
-    };
+/// * That `routed` is the request with the normalized path, if the variant has a `#[forward]`
+///   field.
+fn construct_variant(
+    variant: &VariantInfo<'_>,
+    data: &VariantData,
+    pathmap: &PathMap,
+) -> TokenStream {
+    let field_by_name = |name: &Ident| data.field(name);
 
     let placeholders = {
         // If we have route attributes on this variant, they all have the same (order of)
@@ -676,20 +1319,33 @@ fn construct_variant(variant: &VariantInfo<'_>, data: &VariantData) -> TokenStre
         match data.routes().first() {
             Some(route) if !route.placeholders().is_empty() => {
                 // For each placeholder, get its captured string and parse it
+                // (Placeholders without a field are only used by the `#[path_params]` field)
                 let parse = route
                     .placeholders()
                     .iter()
                     .enumerate()
+                    .filter(|(_, field_name)| data.placeholder_field(field_name).is_some())
                     .map(|(i, field_name)| {
-                        let variable = Ident::new(&format!("fld_{}", field_name), Span::call_site());
-                        let capture = i + 1;
+                        let variable =
+                            Ident::new(&format!("fld_{}", field_name), Span::call_site());
                         let ty = &field_by_name(field_name).ty;
+                        let decode = if raw_inner_type(ty).is_some() {
+                            // `Raw` placeholders are passed to `FromStr` as they are
+                            quote!(let #variable = captures[#i];)
+                        } else {
+                            quote! {
+                                let #variable = match percent_decode(captures[#i]) {
+                                    Ok(v) => v,
+                                    Err(e) => {
+                                        return Error::with_source(StatusCode::BAD_REQUEST, e)
+                                            .into_future();
+                                    }
+                                };
+                            }
+                        };
                         quote! {
-                            let #variable = captures
-                                .get(#capture)
-                                .expect("internal error: capture group did not match anything")
-                                .as_str();
-                            let #variable = match <#ty as FromStr>::from_str(#variable) {
+                            #decode
+                            let #variable = match <#ty as FromStr>::from_str(&#variable) {
                                 Ok(v) => v,
                                 Err(e) => {
                                     return Error::with_source(StatusCode::NOT_FOUND, e)
@@ -701,12 +1357,9 @@ fn construct_variant(variant: &VariantInfo<'_>, data: &VariantData) -> TokenStre
                     .collect::<Vec<_>>();
 
                 quote! {
-                    // Re-match the path with the right regex and get the captures
-                    let captures = REGEXES[index.expect("no regex matched, but there's placeholders?")]
-                        .as_ref()
-                        .expect("internal error: no regex for route with placeholders")
-                        .captures(request.uri().path())
-                        .expect("internal error: regex first matched but now didn't?");
+                    // Get the placeholder values captured when matching the path
+                    let captures = matches[index.expect("no path matched, but there's placeholders?")]
+                        .expect("internal error: matched path has no captures");
 
                     #(#parse)*
                 }
@@ -719,13 +1372,96 @@ fn construct_variant(variant: &VariantInfo<'_>, data: &VariantData) -> TokenStre
         }
     };
 
+    let host_placeholders = match data.routes().first() {
+        Some(route) if !route.host_placeholders().is_empty() => {
+            // All routes of the variant have a host pattern with the same placeholders. Use the
+            // captures of the one that matched.
+            let indices = data
+                .routes()
+                .iter()
+                .filter_map(|route| pathmap.host_index(route));
+            let parse = route
+                .host_placeholders()
+                .iter()
+                .enumerate()
+                .filter(|(_, field_name)| data.placeholder_field(field_name).is_some())
+                .map(|(i, field_name)| {
+                    let variable = Ident::new(&format!("fld_{}", field_name), Span::call_site());
+                    let ty = &field_by_name(field_name).ty;
+                    let group = i + 1;
+                    quote! {
+                        let #variable = match <#ty as FromStr>::from_str(&host_captures[#group]) {
+                            Ok(v) => v,
+                            Err(e) => {
+                                return Error::with_source(StatusCode::NOT_FOUND, e)
+                                    .into_future();
+                            }
+                        };
+                    }
+                });
+
+            quote! {
+                // Get the placeholder values captured when matching the host
+                let host_captures = [#(#indices),*]
+                    .iter()
+                    .find_map(|&h| hosts[h].as_ref())
+                    .expect("internal error: no host pattern matched, but there's placeholders?");
+
+                #(#parse)*
+            }
+        }
+        _ => quote!(),
+    };
+
+    let path_params = match (data.path_params_field(), data.routes().first()) {
+        (Some(field), Some(route)) => {
+            let variable = Ident::new(
+                &format!("fld_{}", field.ident.as_ref().unwrap()),
+                Span::call_site(),
+            );
+            let ty = &field.ty;
+            let host_params = route
+                .host_placeholders()
+                .iter()
+                .enumerate()
+                .filter(|(_, name)| data.placeholder_field(name).is_none())
+                .map(|(i, name)| {
+                    let name = placeholder_name(name);
+                    let group = i + 1;
+                    quote!((#name, &host_captures[#group]))
+                });
+            let path_params = route
+                .placeholders()
+                .iter()
+                .enumerate()
+                .filter(|(_, name)| data.placeholder_field(name).is_none())
+                .map(|(i, name)| {
+                    let name = placeholder_name(name);
+                    quote!((#name, captures[#i]))
+                });
+            quote! {
+                // Deserialize all placeholders without a field into the `#[path_params]` field
+                let #variable = match decode_path::<#ty>(&[#(#host_params,)* #(#path_params),*]) {
+                    Ok(v) => v,
+                    Err(e) => return e.into_future(),
+                };
+            }
+        }
+        _ => quote!(),
+    };
+
     let query = if let Some(query_params_field) = data.query_params_field() {
         let ty = &field_by_name(&query_params_field).ty;
         let variable = Ident::new(&format!("fld_{}", query_params_field), Span::call_site());
+        let decode = if data.query_params_nested() {
+            quote!(decode_nested::<#ty>(raw_query.as_bytes()))
+        } else {
+            quote!(serde_urlencoded::from_str::<#ty>(raw_query))
+        };
         quote! {
             // Parse query params
             let raw_query = request.uri().query().unwrap_or("");
-            let #variable = match serde_urlencoded::from_str::<#ty>(raw_query) {
+            let #variable = match #decode {
                 Ok(val) => val,
                 Err(e) => return Error::with_source(StatusCode::BAD_REQUEST, e).into_future(),
             };
@@ -734,44 +1470,119 @@ fn construct_variant(variant: &VariantInfo<'_>, data: &VariantData) -> TokenStre
         quote!()
     };
 
+    let query_fields = if data.query_fields().is_empty() {
+        quote!()
+    } else {
+        let fields = data.query_fields().iter().map(query_field);
+
+        quote! {
+            let query = match hyperdrive::QueryParams::from_request(&request) {
+                Ok(query) => query,
+                Err(e) => return e.into_future(),
+            };
+
+            #(#fields)*
+        }
+    };
+
+    let headers = data
+        .header_fields()
+        .iter()
+        .map(|(field, name)| {
+            let lookup = quote! {
+                request
+                    .headers()
+                    .get(#name)
+                    .map(|value| value.to_str().map_err(BoxedError::from))
+            };
+            let missing = format!("missing required header `{}`", name);
+            from_str_field(field, lookup, &missing)
+        })
+        .collect::<Vec<_>>();
+
+    let cookies = if data.cookie_fields().is_empty() {
+        quote!()
+    } else {
+        let fields = data.cookie_fields().iter().map(|(field, name)| {
+            let lookup = quote! {
+                cookies.get(#name).map(Ok::<_, BoxedError>)
+            };
+            let missing = format!("missing required cookie `{}`", name);
+            from_str_field(field, lookup, &missing)
+        });
+
+        quote! {
+            let cookies = hyperdrive::guard::Cookies::from_headers(request.headers());
+
+            #(#fields)*
+        }
+    };
+
     // Last step, chain all the asynchronous operations (guards, #[body] and #[forward]).
     // Reverse order because we have to chain everything with `.and_then`.
 
     // Construct the final value from the `fld_X` variables
     let construct = variant.construct(|field, index| {
-        let name = if let Some(ident) = &field.ident {
-            ident.to_string()
-        } else {
-            index.to_string()
-        };
+        let name = field
+            .ident
+            .clone()
+            .unwrap_or_else(|| positional_ident(index));
         Ident::new(&format!("fld_{}", name), Span::call_site())
     });
     let mut future = quote! {
         Ok(#construct).into_future()
     };
 
+    // A buffered body is replayed to the #[body] and #[forward] fields
+    let body = if data.body_buffer_limit().is_some() {
+        quote!(buffered.replay())
+    } else {
+        quote!(body)
+    };
+
     // Read the body
-    if let Some(body) = data.body_field() {
-        let ty = &field_by_name(body).ty;
-        let var = Ident::new(&format!("fld_{}", body), Span::call_site());
+    if let Some(body_field) = data.body_field() {
+        let ty = &field_by_name(body_field).ty;
+        let var = Ident::new(&format!("fld_{}", body_field), Span::call_site());
         future = quote! {
-            <#ty as FromBody>::from_body(&request, body, context.as_ref())
+            <#ty as FromBody>::from_body(&request, #body, context.as_ref())
                 .into_future()
                 .and_then(move |#var| #future)
         };
     };
 
-    // Forward to another `FromRequest` implementor (can not be combined with #[body])
+    // Forward to another `FromRequest` implementor (can only be combined with #[body] if the body
+    // is buffered)
     if let Some(forward) = data.forward_field() {
         let ty = &field_by_name(forward).ty;
         let var = Ident::new(&format!("fld_{}", forward), Span::call_site());
+        let request = match data.forward_prefix() {
+            // Hide the prefix from the forwarded-to impl
+            Some(prefix) => quote! {
+                &Arc::new(hyperdrive::guard::OriginalUri::strip_prefix(&routed, #prefix))
+            },
+            None => quote!(&routed),
+        };
         future = quote! {{
-            <#ty as FromRequest>::from_request_and_body(&request, body, context)
+            let context = Clone::clone(AsRef::<<#ty as FromRequest>::Context>::as_ref(&context));
+            <#ty as FromRequest>::from_request_and_body(#request, #body, context)
                 .into_future()
                 .and_then(move |#var| #future)
         }};
     }
 
+    // Buffer the body (after the guards have accepted the request). It is only read once the
+    // #[forward] or #[body] field polls it, so a #[forward]ed type rejecting the request doesn't
+    // consume it.
+    if let Some(limit) = data.body_buffer_limit() {
+        future = quote! {{
+            let buffered = BufferedBody::new(body, #limit);
+            let error_buffered = buffered.clone();
+            let future = #future;
+            future.map_err(move |e| error_buffered.error(e))
+        }};
+    }
+
     // Check all guards
     // Reverse order so guards are evaluated top to bottom in declaration order.
     for guard in data
@@ -789,20 +1600,204 @@ fn construct_variant(variant: &VariantInfo<'_>, data: &VariantData) -> TokenStre
         };
     }
 
+    // The (possibly normalized) request is only needed by the #[forward] field
+    let clone_routed = if data.forward_field().is_some() {
+        quote!(let routed = Arc::clone(routed);)
+    } else {
+        quote!()
+    };
+
     quote! {{
         use std::str::FromStr;
 
         #placeholders
 
+        #host_placeholders
+
+        #path_params
+
         #query
 
+        #query_fields
+
+        #(#headers)*
+
+        #cookies
+
         let request = Arc::clone(request);
+        #clone_routed
         let future = #future;
 
         Box::new(future) as DefaultFuture<Self, BoxedError>
     }}
 }
 
+/// Generates the code trying all fallback variants in `pathmap` in declaration order.
+///
+/// Returns an expression of type `DefaultFuture<Self, BoxedError>`, or `None` if there are no
+/// fallback variants.
+///
+/// The next fallback variant is only tried if the previous one rejected the path or method
+/// without reading the request body. Until then, the body is lent out from a `BodySlot`. The
+/// allowed methods of all variants rejecting the method are merged.
+///
+/// Besides the variables `construct_variant` needs, this uses `method` (and clones the context
+/// if there's more than one fallback variant).
+fn fallback_chain(s: &Structure<'_>, pathmap: &PathMap) -> Option<TokenStream> {
+    let constructs = pathmap
+        .fallbacks()
+        .iter()
+        .map(|fallback| {
+            let info = s
+                .variants()
+                .iter()
+                .find(|v| v.ast().ident == fallback.variant_name())
+                .expect("couldn't find fallback variant");
+            construct_variant(info, fallback, pathmap)
+        })
+        .collect::<Vec<_>>();
+
+    let (last, rest) = constructs.split_last()?;
+    if rest.is_empty() {
+        // A single fallback variant can consume the body right away
+        return Some(last.clone());
+    }
+
+    // Build the chain from the back, since each variant contains the code trying the next one
+    let mut chain = quote! {{
+        let body = slot.take();
+        #last
+    }};
+    for construct in rest.iter().rev() {
+        chain = quote! {{
+            let next_context = Clone::clone(&context);
+            let next_request = Arc::clone(request);
+            let next_routed = Arc::clone(routed);
+            let next_method = method.clone();
+            let future = {
+                let body = slot.lend();
+                #construct
+            };
+            let future = future.or_else(move |error| -> DefaultFuture<Self, BoxedError> {
+                if !slot.can_retry(&error) {
+                    return Box::new(Err(error).into_future());
+                }
+
+                let context = next_context;
+                let request = &next_request;
+                let routed = &next_routed;
+                let method = next_method;
+                let future = #chain;
+                Box::new(future.map_err(move |next| merge_fallback_errors(&method, error, next)))
+            });
+
+            Box::new(future) as DefaultFuture<Self, BoxedError>
+        }};
+    }
+
+    Some(quote! {{
+        let slot = BodySlot::new(body);
+        #chain
+    }})
+}
+
+/// Generates code that converts an optional string value using `FromStr` and stores the result in
+/// the `fld_X` variable for `field`.
+///
+/// `lookup` must evaluate to an `Option<Result<&str, BoxedError>>` containing the raw value. If the
+/// field is of type `Option<T>`, a missing value results in `None`. Otherwise, the generated code
+/// bails out with a `400 Bad Request` error carrying the `missing` message. Conversion errors also
+/// result in a `400 Bad Request` error, using the `FromStr` error as the source.
+fn from_str_field(field: &syn::Field, lookup: TokenStream, missing: &str) -> TokenStream {
+    let variable = Ident::new(
+        &format!("fld_{}", field.ident.as_ref().unwrap()),
+        Span::call_site(),
+    );
+    let (ty, value, missing) = match option_inner_type(&field.ty) {
+        Some(inner) => (inner, quote!(Some(value)), quote!(None)),
+        None => (
+            &field.ty,
+            quote!(value),
+            quote! {
+                return Error::with_source(StatusCode::BAD_REQUEST, #missing).into_future()
+            },
+        ),
+    };
+
+    quote! {
+        let #variable = match #lookup {
+            Some(value) => {
+                let value = value
+                    .and_then(|s| <#ty as FromStr>::from_str(s).map_err(BoxedError::from));
+                match value {
+                    Ok(value) => #value,
+                    Err(e) => {
+                        return Error::with_source(StatusCode::BAD_REQUEST, e).into_future();
+                    }
+                }
+            }
+            None => #missing,
+        };
+    }
+}
+
+/// Generates code that converts the value(s) of the query parameter of a `#[query]` field using
+/// `FromStr`, and stores the result in the `fld_X` variable for the field.
+///
+/// Expects the decoded `QueryParams` in `query`. Missing required parameters and conversion errors
+/// result in a `400 Bad Request` error naming the parameter.
+fn query_field(query: &QueryField) -> TokenStream {
+    let variable = Ident::new(
+        &format!("fld_{}", query.field().ident.as_ref().unwrap()),
+        Span::call_site(),
+    );
+    let name = query.name();
+    let (ty, kind) = query.kind();
+    let invalid = format!("invalid value for query parameter `{}`", name);
+    let parse = quote! {
+        match <#ty as FromStr>::from_str(value) {
+            Ok(value) => value,
+            Err(e) => {
+                let e = format!("{}: {}", #invalid, e);
+                return Error::with_source(StatusCode::BAD_REQUEST, e).into_future();
+            }
+        }
+    };
+
+    match kind {
+        QueryKind::Repeated => quote! {
+            let mut #variable = Vec::new();
+            for value in query.get_all(#name) {
+                #variable.push(#parse);
+            }
+        },
+        QueryKind::Optional => quote! {
+            let #variable = match query.get(#name) {
+                Some(value) => Some(#parse),
+                None => None,
+            };
+        },
+        QueryKind::Default => quote! {
+            let #variable = match query.get(#name) {
+                Some(value) => #parse,
+                None => Default::default(),
+            };
+        },
+        QueryKind::Required => {
+            let missing = format!("missing required query parameter `{}`", name);
+            quote! {
+                let #variable = match query.get(#name) {
+                    Some(value) => #parse,
+                    None => {
+                        return Error::with_source(StatusCode::BAD_REQUEST, #missing)
+                            .into_future();
+                    }
+                };
+            }
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::derive_from_request;
@@ -860,6 +1855,48 @@ mod tests {
         }
     }
 
+    #[test]
+    #[should_panic(expected = "`PROP FIND` is not a valid HTTP method")]
+    fn invalid_custom_method() {
+        expand! {
+            enum Routes {
+                #[route(method = "PROP FIND", path = "/")]
+                Variant,
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "#[route] attribute is missing the `path` argument")]
+    fn custom_method_no_path() {
+        expand! {
+            enum Routes {
+                #[route(method = "PROPFIND")]
+                Variant,
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(
+        expected = r#"duplicate route: `#[route(method = "PURGE", path = "/{ph}")]` on `Variant` matches the same requests as `#[route(method = "PURGE", path = "/{pl}")]` on `Var`"#
+    )]
+    fn dup_custom_routes() {
+        expand! {
+            enum Routes {
+                #[route(method = "PURGE", path = "/{ph}")]
+                Variant {
+                    ph: u32,
+                },
+
+                #[route(method = "PURGE", path = "/{pl}")]
+                Var {
+                    pl: u32,
+                },
+            }
+        }
+    }
+
     #[test]
     #[should_panic(expected = "different placeholders used")]
     fn wrong_routes() {
@@ -984,7 +2021,7 @@ mod tests {
     }
 
     #[test]
-    #[should_panic(expected = "#[body] and #[forward] cannot be combined")]
+    #[should_panic(expected = "#[body] and #[forward] can only be combined")]
     fn body_and_forward() {
         expand! {
             enum Routes {
@@ -1001,20 +2038,91 @@ mod tests {
     }
 
     #[test]
-    #[should_panic(expected = "cannot define multiple fallback variants")]
-    fn multiple_fallback_routes() {
+    #[should_panic(expected = "#[body] attributes must be of the form")]
+    fn invalid_body_buffer_limit() {
         expand! {
-            #[derive(FromRequest)]
-            enum Enum {
-                First {
-                    #[forward]
-                    inner: (),
-                },
+            enum Routes {
+                #[get("/")]
+                Index {
+                    #[body(buffer_limit = "1 MB")]
+                    body: (),
+                }
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "#[query_params] attributes must be of the form")]
+    fn invalid_query_params_attribute() {
+        expand! {
+            enum Routes {
+                #[get("/")]
+                Index {
+                    #[query_params(flat)]
+                    query: (),
+                }
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "#[path_params] attributes must be of the form `#[path_params]`")]
+    fn invalid_path_params_attribute() {
+        expand! {
+            enum Routes {
+                #[get("/{id}")]
+                Index {
+                    #[path_params(nested)]
+                    path: (),
+                }
+            }
+        }
+    }
 
-                Second {
+    #[test]
+    #[should_panic(
+        expected = "cannot mark a field with #[path_params] when the variant doesn't have a route attribute"
+    )]
+    fn path_params_without_route() {
+        expand! {
+            enum Routes {
+                Fallback {
+                    #[path_params]
+                    path: (),
                     #[forward]
-                    inner: (),
-                },
+                    inner: Inner,
+                }
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "`X-Request-Id:` is not a valid header name")]
+    fn invalid_header_name() {
+        expand! {
+            enum Routes {
+                #[get("/")]
+                Index {
+                    #[header("X-Request-Id:")]
+                    id: u32,
+                }
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "#[body]/#[path_params]/#[query_params]/#[query]/#[forward]/#[header]/#[cookie] must only be specified once"
+    )]
+    fn header_and_body() {
+        expand! {
+            enum Routes {
+                #[post("/")]
+                Index {
+                    #[body]
+                    #[header]
+                    body: (),
+                }
             }
         }
     }
@@ -1038,5 +2146,536 @@ mod tests {
         }
     }
 
+    #[test]
+    #[should_panic(
+        expected = r#"route `#[get("/{ph}", rank = 1)]` overlaps with previously defined route `#[get("/0", rank = 1)]`"#
+    )]
+    fn overlap_same_rank() {
+        expand! {
+            enum Routes {
+                #[get("/0", rank = 1)]
+                Var {},
+
+                #[get("/{ph}", rank = 1)]
+                Variant {
+                    #[allow(unused)]
+                    ph: u32,
+                },
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "`rank` must only be specified once")]
+    fn dup_rank() {
+        expand! {
+            enum Routes {
+                #[get("/", rank = 1, rank = 2)]
+                Variant,
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "unknown or invalid route argument `rank`")]
+    fn invalid_rank() {
+        expand! {
+            enum Routes {
+                #[get("/", rank = "high")]
+                Variant,
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "unknown #[routing] option `fall_through`")]
+    fn unknown_routing_option() {
+        expand! {
+            #[routing(fall_through)]
+            enum Routes {
+                #[get("/")]
+                Variant,
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "`trailing_slash` must be one of \"strict\", \"ignore\" or \"redirect\""
+    )]
+    fn invalid_trailing_slash() {
+        expand! {
+            #[routing(trailing_slash = "always")]
+            enum Routes {
+                #[get("/")]
+                Variant,
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "`trailing_slash` must only be specified once")]
+    fn dup_trailing_slash() {
+        expand! {
+            #[routing(trailing_slash = "strict", trailing_slash = "ignore")]
+            enum Routes {
+                #[get("/")]
+                Variant,
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "`#[routing]` is not valid on enum variants")]
+    fn routing_on_variant() {
+        expand! {
+            enum Routes {
+                #[get("/")]
+                #[routing(fallthrough)]
+                Variant,
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "regex constraint for placeholder `path` must not match `/`")]
+    fn constraint_matches_slash() {
+        expand! {
+            enum Routes {
+                #[get("/{path:.+}")]
+                Variant {
+                    #[allow(unused)]
+                    path: String,
+                },
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "regex constraint for placeholder `id` must not contain capture groups")]
+    fn constraint_capture_group() {
+        expand! {
+            enum Routes {
+                #[get("/{id:([0-9])+}")]
+                Variant {
+                    #[allow(unused)]
+                    id: u32,
+                },
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid regex constraint for placeholder `id`")]
+    fn invalid_constraint() {
+        expand! {
+            enum Routes {
+                #[get("/{id:[0-9}")]
+                Variant {
+                    #[allow(unused)]
+                    id: u32,
+                },
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(
+        expected = r#"route `#[get("/{hex:[0-9a-f]+}")]` overlaps with previously defined route `#[get("/{id:[0-9]+}")]`"#
+    )]
+    fn overlapping_constraints() {
+        expand! {
+            enum Routes {
+                #[get("/{id:[0-9]+}")]
+                Id {
+                    #[allow(unused)]
+                    id: u32,
+                },
+
+                #[get("/{hex:[0-9a-f]+}")]
+                Hex {
+                    #[allow(unused)]
+                    hex: String,
+                },
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "placeholders must be separated by literal text (in `{a}{b}.txt`)")]
+    fn adjacent_placeholders() {
+        expand! {
+            enum Routes {
+                #[get("/{a}{b}.txt")]
+                Variant {
+                    #[allow(unused)]
+                    a: u32,
+                    #[allow(unused)]
+                    b: u32,
+                },
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "...-placeholders must make up a whole path segment (in `v{rest...}`)")]
+    fn mixed_rest_placeholder() {
+        expand! {
+            enum Routes {
+                #[get("/v{rest...}")]
+                Variant {
+                    #[allow(unused)]
+                    rest: String,
+                },
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "duplicate placeholders in route path `/{id}/{id}.json`")]
+    fn mixed_dup_placeholder() {
+        expand! {
+            enum Routes {
+                #[get("/{id}/{id}.json")]
+                Variant {
+                    #[allow(unused)]
+                    id: u32,
+                },
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(
+        expected = r#"route `#[get("/api/v1/status")]` overlaps with `#[forward(prefix = "/api/v1")]` on `Api` (both would match path `/api/v1/status`)"#
+    )]
+    fn route_overlaps_mount() {
+        expand! {
+            enum Routes {
+                #[get("/api/v1/status")]
+                Status,
+
+                Api {
+                    #[forward(prefix = "/api/v1")]
+                    #[allow(unused)]
+                    api: Api,
+                },
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(
+        expected = r#"`#[forward(prefix = "/api/v1")]` on `V1` overlaps with `#[forward(prefix = "/api")]` on `Api`"#
+    )]
+    fn mounts_overlap() {
+        expand! {
+            enum Routes {
+                Api {
+                    #[forward(prefix = "/api")]
+                    #[allow(unused)]
+                    api: Api,
+                },
+
+                V1 {
+                    #[forward(prefix = "/api/v1")]
+                    #[allow(unused)]
+                    api: Api,
+                },
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "forward prefix `/api/` must start with `/` and must not end with `/`")]
+    fn mount_trailing_slash() {
+        expand! {
+            enum Routes {
+                Api {
+                    #[forward(prefix = "/api/")]
+                    #[allow(unused)]
+                    api: Api,
+                },
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "#[forward(prefix = \"...\")] cannot be used in a variant/struct with a route attribute"
+    )]
+    fn mount_with_route() {
+        expand! {
+            enum Routes {
+                #[get("/")]
+                Api {
+                    #[forward(prefix = "/api")]
+                    #[allow(unused)]
+                    api: Api,
+                },
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "duplicate route")]
+    fn dup_route_same_host() {
+        expand! {
+            enum Routes {
+                #[get("/", host = "example.com")]
+                A,
+                #[get("/", host = "EXAMPLE.com")]
+                B,
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "duplicate route")]
+    fn dup_route_overlapping_host() {
+        expand! {
+            #[host("{tenant}.example.com")]
+            enum Routes {
+                #[get("/")]
+                A { tenant: String },
+                #[get("/", host = "api.example.com")]
+                B,
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "both would match path `/a` on host `api.example.com`")]
+    fn host_overlap() {
+        expand! {
+            enum Routes {
+                #[get("/{x}", host = "{tenant}.example.com")]
+                A { x: String, tenant: String },
+                #[get("/a", host = "api.example.com")]
+                B,
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "`#[host]` is not valid on enum variants")]
+    fn host_on_variant() {
+        expand! {
+            enum Routes {
+                #[get("/")]
+                #[host("example.com")]
+                Variant,
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "must only contain a host name (no port or path)")]
+    fn host_with_port() {
+        expand! {
+            enum Routes {
+                #[get("/", host = "example.com:8080")]
+                Variant,
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "placeholder `{tenant}` does not refer to an existing field")]
+    fn host_placeholder_without_field() {
+        expand! {
+            #[host("{tenant}.example.com")]
+            enum Routes {
+                #[get("/")]
+                Variant,
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "placeholder `{id}` is used in both the host and the path")]
+    fn host_and_path_placeholder() {
+        expand! {
+            enum Routes {
+                #[get("/{id}", host = "{id}.example.com")]
+                Variant { id: u32 },
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "duplicate route")]
+    fn dup_route_same_media_type() {
+        expand! {
+            enum Routes {
+                #[post("/", consumes = "application/json")]
+                A,
+                #[post("/", consumes = "Application/JSON")]
+                B,
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "duplicate route")]
+    fn dup_route_overlapping_media_type() {
+        expand! {
+            enum Routes {
+                #[get("/", produces = "text/*")]
+                A,
+                #[get("/", produces = "text/html")]
+                B,
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "`produces` must be a media type of the form `type/subtype` or `type/*` (got `json`)"
+    )]
+    fn invalid_media_type() {
+        expand! {
+            enum Routes {
+                #[get("/", produces = "json")]
+                Variant,
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "`consumes` must only be specified once")]
+    fn dup_consumes() {
+        expand! {
+            enum Routes {
+                #[post("/", consumes = "text/csv", consumes = "application/json")]
+                Variant,
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "placeholder `{2}` does not refer to an existing field")]
+    fn positional_placeholder_out_of_range() {
+        expand! {
+            enum Routes {
+                #[get("/{0}/{2}")]
+                Variant(u32, u32),
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "placeholder `01` must be a valid identifier or field index")]
+    fn positional_placeholder_leading_zero() {
+        expand! {
+            enum Routes {
+                #[get("/{01}")]
+                Variant(u32, u32),
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "#[header] on an unnamed field must specify the header name")]
+    fn unnamed_header_without_name() {
+        expand! {
+            enum Routes {
+                #[get("/")]
+                Variant(#[header] String),
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "#[query(default)] has no effect on `Option` and `Vec` fields")]
+    fn query_default_on_option() {
+        expand! {
+            enum Routes {
+                #[get("/")]
+                Index {
+                    #[query(default)]
+                    page: Option<u32>,
+                },
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "query parameter `page` is extracted by multiple fields")]
+    fn duplicate_query_param() {
+        expand! {
+            enum Routes {
+                #[get("/")]
+                Index {
+                    #[query]
+                    page: u32,
+                    #[query("page")]
+                    other_page: u32,
+                },
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "#[query] on an unnamed field must specify the parameter name")]
+    fn unnamed_query_without_name() {
+        expand! {
+            enum Routes {
+                #[get("/")]
+                Variant(#[query] u32),
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "#[query] attributes must be of the form")]
+    fn invalid_query_attribute() {
+        expand! {
+            enum Routes {
+                #[get("/")]
+                Index {
+                    #[query = "page"]
+                    page: u32,
+                },
+            }
+        }
+    }
+
+    #[test]
+    fn route_attrs_in_sync() {
+        macro_rules! attr_names {
+            ([$($attr:ident),*]) => {
+                vec![$(stringify!($attr)),*]
+            };
+        }
+
+        let mut declared: Vec<&str> = with_route_attrs!(attr_names!());
+        let mut parsed = super::parse::our_attrs().collect::<Vec<_>>();
+        declared.sort();
+        parsed.sort();
+        assert_eq!(declared, parsed);
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "#[derive(ToUri)] requires a route attribute on every variant, but `Fallback` has none"
+    )]
+    fn to_uri_without_route() {
+        test_derive! {
+            super::derive_to_uri {
+                enum Routes {
+                    #[get("/")]
+                    Index,
+
+                    Fallback {
+                        #[forward]
+                        inner: Inner,
+                    },
+                }
+            }
+            expands to {} no_build
+        }
+    }
+
     // TODO write lots more tests
 }
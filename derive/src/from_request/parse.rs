@@ -1,9 +1,13 @@
-use crate::utils::ByProxy;
-use indexmap::{map::Entry, IndexMap};
+use crate::utils::{option_inner_type, vec_inner_type, ByProxy};
+use indexmap::{map::Entry, IndexMap, IndexSet};
 use proc_macro2::{Ident, Span};
+use quote::ToTokens;
 use regex::Regex;
-use std::{fmt, slice};
-use syn::{Attribute, Field, Lit, Meta, NestedMeta};
+use regex_syntax::hir::{
+    Class, ClassUnicode, ClassUnicodeRange, Hir, HirKind, Literal, RepetitionKind, RepetitionRange,
+};
+use std::{convert::TryFrom, fmt, slice};
+use syn::{Attribute, Field, Lit, Meta, MetaNameValue, NestedMeta};
 use synstructure::VariantAst;
 
 // Attributes need to be kept in sync with lib.rs
@@ -13,13 +17,30 @@ const METHOD_ATTRS: &[&str] = &[
 ];
 
 /// All attributes used by this custom derive.
-fn our_attrs() -> impl Iterator<Item = &'static str> {
+pub fn our_attrs() -> impl Iterator<Item = &'static str> {
     METHOD_ATTRS
         .iter()
-        .chain(&["context", "body", "forward", "query_params"])
+        .chain(&[
+            "route",
+            "context",
+            "body",
+            "forward",
+            "query_params",
+            "query",
+            "path_params",
+            "header",
+            "cookie",
+            "routing",
+            "host",
+        ])
         .cloned()
 }
 
+/// Field attributes that determine how a field is decoded (at most one of them may be used on a
+/// field).
+const FIELD_ROLE_ATTRS: &str =
+    "#[body]/#[path_params]/#[query_params]/#[query]/#[forward]/#[header]/#[cookie]";
+
 /// Returns whether `name` is an attribute used by this custom derive.
 fn known_attr(name: &Ident) -> bool {
     our_attrs().find(|s| name == s).is_some()
@@ -33,19 +54,78 @@ fn is_method(name: &Ident) -> bool {
 
 /// Parsed attributes attached to the item that does `#[derive(FromRequest)]`.
 pub struct ItemData {
-    name: Ident,
     context: Option<syn::Type>,
+    /// Whether `#[routing(fallthrough)]` was specified.
+    fallthrough: bool,
+    /// The `#[routing(trailing_slash = "...")]` policy.
+    trailing_slash: Option<TrailingSlash>,
+    /// The host pattern given via `#[host("...")]`, used by all routes without a `host` argument.
+    host: Option<HostPattern>,
+}
+
+/// How request paths that aren't in their canonical form are handled.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum TrailingSlash {
+    /// `trailing_slash = "strict"`: Normalize the path, but require an exact match of trailing
+    /// slashes.
+    Strict,
+    /// `trailing_slash = "ignore"`: Normalize the path, and match routes regardless of trailing
+    /// slashes.
+    Ignore,
+    /// `trailing_slash = "redirect"`: Redirect to the canonical form of the path.
+    Redirect,
 }
 
 impl ItemData {
-    pub fn parse(name: Ident, attrs: &[Attribute], is_struct: bool) -> Self {
+    pub fn parse(attrs: &[Attribute], is_struct: bool) -> Self {
         let mut context = None;
+        let mut fallthrough = false;
+        let mut trailing_slash = None;
+        let mut host = None;
 
         for attr in attrs {
-            let name = attr.parse_meta().unwrap().name();
+            let meta = attr.parse_meta().unwrap();
+            let name = meta.name();
             if name == "context" {
                 let ty = syn::parse2(attr.tts.clone()).expect("#[context] must be given a type");
                 insert("#[context]", &mut context, ty);
+            } else if name == "routing" {
+                let list = match &meta {
+                    Meta::List(list) => list,
+                    _ => panic!("#[routing] must be given a list of options"),
+                };
+
+                for nested in &list.nested {
+                    match nested {
+                        NestedMeta::Meta(Meta::Word(word)) if word == "fallthrough" => {
+                            fallthrough = true;
+                        }
+                        NestedMeta::Meta(Meta::NameValue(nv)) if nv.ident == "trailing_slash" => {
+                            let policy = match &nv.lit {
+                                Lit::Str(s) if s.value() == "strict" => TrailingSlash::Strict,
+                                Lit::Str(s) if s.value() == "ignore" => TrailingSlash::Ignore,
+                                Lit::Str(s) if s.value() == "redirect" => TrailingSlash::Redirect,
+                                _ => panic!(
+                                    "`trailing_slash` must be one of \"strict\", \"ignore\" or \"redirect\""
+                                ),
+                            };
+                            insert("`trailing_slash`", &mut trailing_slash, policy);
+                        }
+                        _ => panic!(
+                            "unknown #[routing] option `{}`",
+                            nested.into_token_stream()
+                        ),
+                    }
+                }
+            } else if name == "host" {
+                let pattern = match &meta {
+                    Meta::List(list) if list.nested.len() == 1 => match &list.nested[0] {
+                        NestedMeta::Literal(Lit::Str(s)) => s.value(),
+                        _ => panic!("#[host] must be of the form `#[host(\"example.com\")]`"),
+                    },
+                    _ => panic!("#[host] must be of the form `#[host(\"example.com\")]`"),
+                };
+                insert("#[host]", &mut host, HostPattern::parse(pattern));
             } else if known_attr(&name) && !is_struct {
                 panic!(
                     "`#[{}]` is not valid on enums (did you mean to place it on a variant instead?)",
@@ -54,13 +134,37 @@ impl ItemData {
             }
         }
 
-        Self { name, context }
+        Self {
+            context,
+            fallthrough,
+            trailing_slash,
+            host,
+        }
     }
 
     /// Returns the custom context type (`None` if none was specified).
     pub fn context(&self) -> Option<&syn::Type> {
         self.context.as_ref()
     }
+
+    /// Returns whether the derived impl should try the next matching route (or the fallback
+    /// variant) when the path placeholders of a route fail to parse.
+    ///
+    /// This also allows routes to overlap.
+    pub fn fallthrough(&self) -> bool {
+        self.fallthrough
+    }
+
+    /// Returns the policy for normalizing request paths (`None` if paths should be matched as they
+    /// are).
+    pub fn trailing_slash(&self) -> Option<TrailingSlash> {
+        self.trailing_slash
+    }
+
+    /// Returns the host pattern routes should match by default (`None` if they match any host).
+    pub fn host(&self) -> Option<&HostPattern> {
+        self.host.as_ref()
+    }
 }
 
 /// Attribute data attached to an enum variant or struct.
@@ -72,36 +176,111 @@ pub struct VariantData {
     /// on the variant.
     ///
     /// If there are no routes, but a `forward_field`, then the variant acts as a fallback and is
-    /// chosen when no other variant matches. There must only be a single fallback variant for the
-    /// type.
+    /// chosen when no other variant matches. Multiple fallback variants are tried in order.
     ///
     /// If this is empty and there's no `forward_field`, then this variant will not be created by
     /// the derived `FromRequest` implementation.
     routes: Vec<Route>,
     body_field: Option<Field>,
+    /// The size limit for buffering the body (`#[body(buffer_limit = N)]`), which allows sharing
+    /// it with the `forward_field`.
+    body_buffer_limit: Option<u64>,
     forward_field: Option<Field>,
+    /// The path prefix under which the `forward_field` is mounted (`#[forward(prefix = "...")]`).
+    forward_prefix: Option<String>,
+    /// The field all placeholders are deserialized into (`#[path_params]`).
+    path_params_field: Option<Field>,
     query_params_field: Option<Field>,
+    /// Whether the `query_params_field` supports repeated and nested parameters
+    /// (`#[query_params(nested)]`).
+    query_params_nested: bool,
+    /// Fields marked with `#[query]`.
+    query_fields: Vec<QueryField>,
+    /// Fields marked with `#[header]`, along with the (lowercase) name of the header.
+    header_fields: Vec<(Field, String)>,
+    /// Fields marked with `#[cookie]`, along with the name of the cookie.
+    cookie_fields: Vec<(Field, String)>,
     guard_fields: Vec<Field>,
     path_segment_fields: Vec<Field>,
+    /// All fields, in declaration order. Unnamed fields are given the identifier returned by
+    /// `positional_ident`.
+    fields: Vec<Field>,
+    /// Whether the fields are unnamed (in a tuple variant or struct).
+    unnamed: bool,
 }
 
 /// Describes where a field is decoded from.
 #[derive(PartialEq)]
 pub enum FieldKind {
-    /// Field is decoded from `{placeholders}` in the URL.
+    /// Field is decoded from `{placeholders}` in the URL (in the path or the host).
     PathSegment,
+    /// Field is `Deserialize`d from all `{placeholders}` in the URL.
+    PathParams,
     /// Field is `Deserialize`d from query parameters.
     QueryParams,
+    /// Field is decoded from a single query parameter using `FromStr`.
+    Query,
     /// Field is decoded from request body using `FromBody`.
     Body,
     /// Field is decoded from entire request using `FromRequest`.
     Forward,
+    /// Field is decoded from a request header using `FromStr`.
+    Header,
+    /// Field is decoded from a cookie using `FromStr`.
+    Cookie,
     /// Field is decoded from request metadata using `Guard`.
     Guard,
 }
 
+/// A field extracting a single query parameter (`#[query]`).
+#[derive(Clone)]
+pub struct QueryField {
+    field: Field,
+    /// The name of the query parameter.
+    name: String,
+    /// Whether `Default` is used when the parameter is missing (`#[query(default)]`).
+    default: bool,
+}
+
+impl QueryField {
+    pub fn field(&self) -> &Field {
+        &self.field
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the type converted via `FromStr`, and how the field is filled from it.
+    pub fn kind(&self) -> (&syn::Type, QueryKind) {
+        let ty = &self.field.ty;
+        if let Some(inner) = option_inner_type(ty) {
+            (inner, QueryKind::Optional)
+        } else if let Some(inner) = vec_inner_type(ty) {
+            (inner, QueryKind::Repeated)
+        } else if self.default {
+            (ty, QueryKind::Default)
+        } else {
+            (ty, QueryKind::Required)
+        }
+    }
+}
+
+/// How a `#[query]` field is filled from the query parameter.
+#[derive(Clone, Copy, PartialEq)]
+pub enum QueryKind {
+    /// The parameter must be present.
+    Required,
+    /// A missing parameter results in `Default::default()`.
+    Default,
+    /// `Option<T>`: A missing parameter results in `None`.
+    Optional,
+    /// `Vec<T>`: All values of the parameter are collected.
+    Repeated,
+}
+
 impl VariantData {
-    pub fn parse(ast: &VariantAst<'_>, is_struct: bool) -> Self {
+    pub fn parse(ast: &VariantAst<'_>, item: &ItemData, is_struct: bool) -> Self {
         // Collect all the route attributes on the variant
         let mut routes = Vec::new();
         for attr in ast.attrs {
@@ -113,6 +292,11 @@ impl VariantData {
                         &list.nested.iter().collect::<Vec<_>>(),
                     ));
                 }
+                Meta::List(list) if meta.name() == "route" => {
+                    routes.push(Route::parse_generic(
+                        &list.nested.iter().collect::<Vec<_>>(),
+                    ));
+                }
                 _ if known_attr(&meta.name()) && !is_struct => {
                     panic!("`#[{}]` is not valid on enum variants", meta.name())
                 }
@@ -120,28 +304,46 @@ impl VariantData {
             }
         }
 
+        // Routes without a `host` argument use the one given via `#[host]` (if any)
+        for route in &mut routes {
+            if route.host.is_none() {
+                route.host = item.host().cloned();
+            }
+
+            for placeholder in route.host_placeholders() {
+                if route.placeholders().contains(placeholder) {
+                    panic!(
+                        "placeholder `{{{}}}` is used in both the host and the path of `{}`",
+                        placeholder_name(placeholder),
+                        route
+                    );
+                }
+            }
+        }
+
         // Since you're allowed to put multiple routes on a variant, they all
         // must have the same placeholders.
         // They also have to be in the same order because we want to access the
         // captures by index.
         if let Some((first, rest)) = routes.split_first() {
             for route in rest {
-                if first.placeholders() != route.placeholders() {
-                    let first = first
-                        .placeholders()
-                        .iter()
-                        .map(|ident| ident.to_string())
-                        .collect::<Vec<_>>()
-                        .join(", ");
-                    let other = route
-                        .placeholders()
-                        .iter()
-                        .map(|ident| ident.to_string())
-                        .collect::<Vec<_>>()
-                        .join(", ");
+                if first.placeholders() != route.placeholders()
+                    || first.host_placeholders() != route.host_placeholders()
+                {
+                    let list = |route: &Route| {
+                        route
+                            .host_placeholders()
+                            .iter()
+                            .chain(route.placeholders())
+                            .map(placeholder_name)
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    };
                     panic!(
                         "different placeholders used on variant `{}`: `{}` vs. `{}` (they have to be in the same order)",
-                        ast.ident, first, other
+                        ast.ident,
+                        list(first),
+                        list(route)
                     );
                 }
             }
@@ -149,81 +351,159 @@ impl VariantData {
 
         let placeholders = routes
             .first()
-            .map(|route| route.placeholders())
-            .unwrap_or(&[]);
+            .map(|route| {
+                route
+                    .host_placeholders()
+                    .iter()
+                    .chain(route.placeholders())
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_else(Vec::new);
+
+        // Unnamed fields are referred to by their index (`{0}`), give them matching identifiers
+        let unnamed = ast.fields.iter().any(|field| field.ident.is_none());
+        let fields = ast
+            .fields
+            .iter()
+            .enumerate()
+            .map(|(i, field)| {
+                let mut field = field.clone();
+                if field.ident.is_none() {
+                    field.ident = Some(positional_ident(i));
+                }
+                field
+            })
+            .collect::<Vec<_>>();
 
-        // All placeholders must have fields with that name in the variant
-        for placeholder in placeholders {
-            if ast
-                .fields
+        // All placeholders must have fields with that name in the variant, unless they're
+        // deserialized into a `#[path_params]` field
+        let has_path_params = fields.iter().any(|field| {
+            field.attrs.iter().any(|attr| {
+                attr.path.segments.len() == 1 && attr.path.segments[0].ident == "path_params"
+            })
+        });
+        for placeholder in placeholders.iter().filter(|_| !has_path_params) {
+            if fields
                 .iter()
                 .find(|field| field.ident.as_ref() == Some(placeholder))
                 .is_none()
             {
                 panic!(
                     "placeholder `{{{}}}` does not refer to an existing field on variant `{}`",
-                    placeholder, ast.ident,
+                    placeholder_name(placeholder),
+                    ast.ident,
                 );
             }
         }
 
         // Now check all attributes on the variant's fields
         let mut body_field = None;
+        let mut body_buffer_limit = None;
         let mut forward_field = None;
+        let mut forward_prefix = None;
+        let mut path_params_field = None;
         let mut query_params_field = None;
+        let mut query_params_nested = false;
+        let mut query_fields = Vec::new();
+        let mut header_fields = Vec::new();
+        let mut cookie_fields = Vec::new();
         let mut guard_fields = Vec::new();
         let mut path_segment_fields = Vec::new();
-        for field in ast.fields.iter() {
+        for field in &fields {
+            let ident = field.ident.as_ref().unwrap();
+
             // Every field must have a role
-            let mut field_kind = match &field.ident {
-                Some(ident) if placeholders.contains(ident) => {
-                    path_segment_fields.push(ident.clone());
-                    Some(FieldKind::PathSegment)
-                }
-                _ => None,
+            let mut field_kind = if placeholders.contains(ident) {
+                path_segment_fields.push(ident.clone());
+                Some(FieldKind::PathSegment)
+            } else {
+                None
             };
 
             for attr in &field.attrs {
                 let meta = attr.parse_meta().unwrap();
                 match &meta {
-                    Meta::Word(ident) if ident == "body" => {
-                        if let Some(ident) = &field.ident {
-                            insert("#[body]", &mut body_field, ident.clone());
-                        } else {
-                            panic!("#[body] is not supported on unnamed fields");
-                        }
-
-                        insert(
-                            "#[body]/#[query_params]/#[forward]",
-                            &mut field_kind,
-                            FieldKind::Body,
-                        );
+                    _ if meta.name() == "body" => {
+                        insert("#[body]", &mut body_field, ident.clone());
+                        body_buffer_limit = parse_body_buffer_limit(&meta);
+                        insert(FIELD_ROLE_ATTRS, &mut field_kind, FieldKind::Body);
                     }
-                    Meta::Word(ident) if ident == "query_params" => {
-                        if let Some(ident) = &field.ident {
-                            insert("#[query_params]", &mut query_params_field, ident.clone());
-                        } else {
-                            panic!("#[query_params] is not supported on unnamed fields");
-                        }
-
-                        insert(
-                            "#[body]/#[query_params]/#[forward]",
-                            &mut field_kind,
-                            FieldKind::QueryParams,
-                        );
+                    Meta::Word(word) if word == "path_params" => {
+                        insert("#[path_params]", &mut path_params_field, ident.clone());
+                        insert(FIELD_ROLE_ATTRS, &mut field_kind, FieldKind::PathParams);
+                    }
+                    _ if meta.name() == "path_params" => {
+                        panic!("#[path_params] attributes must be of the form `#[path_params]`");
                     }
-                    Meta::Word(ident) if ident == "forward" => {
-                        if let Some(ident) = &field.ident {
-                            insert("#[forward]", &mut forward_field, ident.clone());
-                        } else {
-                            panic!("#[forward] is not supported on unnamed fields");
+                    _ if meta.name() == "query_params" => {
+                        insert("#[query_params]", &mut query_params_field, ident.clone());
+                        query_params_nested = parse_query_params_nested(&meta);
+                        insert(FIELD_ROLE_ATTRS, &mut field_kind, FieldKind::QueryParams);
+                    }
+                    _ if meta.name() == "forward" => {
+                        insert("#[forward]", &mut forward_field, ident.clone());
+                        forward_prefix = parse_forward_prefix(&meta);
+                        insert(FIELD_ROLE_ATTRS, &mut field_kind, FieldKind::Forward);
+                    }
+                    _ if meta.name() == "query" => {
+                        // Default to the field name
+                        let (name, default) = parse_query_args(&meta);
+                        let name = match name {
+                            Some(name) => name,
+                            None if unnamed => panic!(
+                                "#[query] on an unnamed field must specify the parameter name (`#[query(\"name\")]`)"
+                            ),
+                            None => ident.to_string().trim_start_matches("r#").to_string(),
+                        };
+                        if default
+                            && (option_inner_type(&field.ty).is_some()
+                                || vec_inner_type(&field.ty).is_some())
+                        {
+                            panic!(
+                                "#[query(default)] has no effect on `Option` and `Vec` fields (on query parameter `{}`)",
+                                name
+                            );
                         }
+                        if query_fields
+                            .iter()
+                            .any(|query: &QueryField| query.name == name)
+                        {
+                            panic!("query parameter `{}` is extracted by multiple fields", name);
+                        }
+                        query_fields.push(QueryField {
+                            field: field.clone(),
+                            name,
+                            default,
+                        });
 
-                        insert(
-                            "#[body]/#[query_params]/#[forward]",
-                            &mut field_kind,
-                            FieldKind::Forward,
-                        );
+                        insert(FIELD_ROLE_ATTRS, &mut field_kind, FieldKind::Query);
+                    }
+                    _ if meta.name() == "header" => {
+                        // Default to the kebab-cased field name
+                        let name = match parse_name_arg(&meta) {
+                            Some(name) => name,
+                            None if unnamed => panic!(
+                                "#[header] on an unnamed field must specify the header name (`#[header(\"Name\")]`)"
+                            ),
+                            None => ident.to_string().trim_start_matches("r#").replace('_', "-"),
+                        };
+                        header_fields.push((ident.clone(), name));
+
+                        insert(FIELD_ROLE_ATTRS, &mut field_kind, FieldKind::Header);
+                    }
+                    _ if meta.name() == "cookie" => {
+                        // Default to the field name
+                        let name = match parse_name_arg(&meta) {
+                            Some(name) => name,
+                            None if unnamed => panic!(
+                                "#[cookie] on an unnamed field must specify the cookie name (`#[cookie(\"name\")]`)"
+                            ),
+                            None => ident.to_string().trim_start_matches("r#").to_string(),
+                        };
+                        cookie_fields.push((ident.clone(), name));
+
+                        insert(FIELD_ROLE_ATTRS, &mut field_kind, FieldKind::Cookie);
                     }
                     _ if known_attr(&meta.name()) => {
                         panic!("#[{}] is not valid on fields", meta.name());
@@ -232,40 +512,51 @@ impl VariantData {
                 }
             }
 
-            // If there's no #[body]/#[query_params] on the field and it doesn't appear as a path
-            // segment placeholder, it's a guard.
+            // If there's no role attribute (#[body], #[header], etc.) on the field and it doesn't
+            // appear as a path segment placeholder, it's a guard.
             let field_kind = field_kind.unwrap_or(FieldKind::Guard);
 
             if field_kind == FieldKind::Guard {
-                guard_fields.push(
-                    field
-                        .ident
-                        .clone()
-                        .expect("#[derive(FromRequest)] requires named fields"),
-                );
+                guard_fields.push(ident.clone());
             }
         }
 
-        if body_field.is_some() && forward_field.is_some() {
-            panic!("#[body] and #[forward] cannot be combined in the same variant/struct");
+        if body_field.is_some() && forward_field.is_some() && body_buffer_limit.is_none() {
+            panic!(
+                "#[body] and #[forward] can only be combined in the same variant/struct if the body \
+                 is buffered using `#[body(buffer_limit = N)]`"
+            );
+        }
+
+        if forward_prefix.is_some() && !routes.is_empty() {
+            panic!("#[forward(prefix = \"...\")] cannot be used in a variant/struct with a route attribute");
         }
 
-        // If there's no route, deny all attributes on fields as well
+        // If there's no route, deny all attributes on fields as well (except for a buffered body,
+        // which is read alongside the `#[forward]` field)
         if routes.is_empty() {
-            if body_field.is_some() {
+            if body_field.is_some() && forward_field.is_none() {
                 panic!("cannot mark a field with #[body] when the variant doesn't have a route attribute");
             }
 
+            if path_params_field.is_some() {
+                panic!("cannot mark a field with #[path_params] when the variant doesn't have a route attribute");
+            }
+
             if query_params_field.is_some() {
                 panic!("cannot mark a field with #[query_params] when the variant doesn't have a route attribute");
             }
+
+            if !query_fields.is_empty() {
+                panic!("cannot mark a field with #[query] when the variant doesn't have a route attribute");
+            }
         }
 
         // Given a field name, returns the whole `Field`
         let fld = |ident: Ident| -> Field {
-            ast.fields
+            fields
                 .iter()
-                .find(|fld| fld.ident.clone().unwrap() == ident)
+                .find(|fld| fld.ident.as_ref() == Some(&ident))
                 .unwrap()
                 .clone()
         };
@@ -274,10 +565,25 @@ impl VariantData {
             name: ast.ident.clone(),
             routes,
             body_field: body_field.map(fld),
+            body_buffer_limit,
             forward_field: forward_field.map(fld),
+            forward_prefix,
+            path_params_field: path_params_field.map(fld),
             query_params_field: query_params_field.map(fld),
+            query_params_nested,
+            query_fields,
+            header_fields: header_fields
+                .into_iter()
+                .map(|(ident, name)| (fld(ident), name.to_lowercase()))
+                .collect(),
+            cookie_fields: cookie_fields
+                .into_iter()
+                .map(|(ident, name)| (fld(ident), name))
+                .collect(),
             guard_fields: guard_fields.into_iter().map(fld).collect(),
             path_segment_fields: path_segment_fields.into_iter().map(fld).collect(),
+            fields,
+            unnamed,
         }
     }
 
@@ -286,6 +592,12 @@ impl VariantData {
         !self.routes.is_empty() || self.forward_field().is_some()
     }
 
+    /// Returns whether this is a fallback variant, which uses `#[forward]` without a route
+    /// attribute or mount prefix.
+    pub fn is_fallback(&self) -> bool {
+        self.routes.is_empty() && self.forward_field.is_some() && self.forward_prefix.is_none()
+    }
+
     pub fn variant_name(&self) -> &Ident {
         &self.name
     }
@@ -307,6 +619,14 @@ impl VariantData {
             .map(|fld| fld.ident.as_ref().unwrap())
     }
 
+    /// Returns the size limit of the buffered body, if `#[body(buffer_limit = N)]` was used.
+    ///
+    /// A buffered body is read into memory before it's passed to the `FromBody` impl (and the
+    /// `#[forward]` field, if any).
+    pub fn body_buffer_limit(&self) -> Option<u64> {
+        self.body_buffer_limit
+    }
+
     /// Returns the name of the field marked with `#[forward]`.
     ///
     /// If this is `None`, no `FromRequest`-forwarding takes place.
@@ -316,6 +636,17 @@ impl VariantData {
             .map(|fld| fld.ident.as_ref().unwrap())
     }
 
+    /// Returns the type of the field marked with `#[forward]`.
+    pub fn forward_type(&self) -> Option<&syn::Type> {
+        self.forward_field.as_ref().map(|fld| &fld.ty)
+    }
+
+    /// Returns the path prefix the `#[forward]` field is mounted under (`None` if no prefix was
+    /// specified).
+    pub fn forward_prefix(&self) -> Option<&str> {
+        self.forward_prefix.as_ref().map(|prefix| &prefix[..])
+    }
+
     /// Returns the name of the field marked with `#[query_params]`.
     ///
     /// If this is `None`, the query parameters are ignored.
@@ -325,6 +656,65 @@ impl VariantData {
             .map(|fld| fld.ident.as_ref().unwrap())
     }
 
+    /// Returns whether the `#[query_params]` field is decoded with support for repeated and nested
+    /// parameters (`#[query_params(nested)]`).
+    pub fn query_params_nested(&self) -> bool {
+        self.query_params_nested
+    }
+
+    /// Returns the fields marked with `#[query]`.
+    pub fn query_fields(&self) -> &[QueryField] {
+        &self.query_fields
+    }
+
+    /// Returns the fields marked with `#[header]`, along with the lowercase header name.
+    pub fn header_fields(&self) -> &[(Field, String)] {
+        &self.header_fields
+    }
+
+    /// Returns the fields marked with `#[cookie]`, along with the cookie name.
+    pub fn cookie_fields(&self) -> &[(Field, String)] {
+        &self.cookie_fields
+    }
+
+    /// Returns the fields that are parsed from path placeholders.
+    pub fn path_segment_fields(&self) -> &[Field] {
+        &self.path_segment_fields
+    }
+
+    /// Returns the field parsed from the placeholder `ident`.
+    ///
+    /// If this is `None`, the placeholder is only used by the `#[path_params]` field.
+    pub fn placeholder_field(&self, ident: &Ident) -> Option<&Field> {
+        self.path_segment_fields
+            .iter()
+            .find(|field| field.ident.as_ref() == Some(ident))
+    }
+
+    /// Returns the field marked with `#[path_params]`.
+    pub fn path_params_field(&self) -> Option<&Field> {
+        self.path_params_field.as_ref()
+    }
+
+    /// Returns the field with the given identifier (see `positional_ident` for unnamed fields).
+    pub fn field(&self, ident: &Ident) -> &Field {
+        self.fields
+            .iter()
+            .find(|field| field.ident.as_ref() == Some(ident))
+            .expect("internal error: couldn't find field by name")
+    }
+
+    /// Returns the index of the field `ident` if the fields of this variant are unnamed.
+    pub fn unnamed_field_index(&self, ident: &Ident) -> Option<usize> {
+        if self.unnamed {
+            self.fields
+                .iter()
+                .position(|field| field.ident.as_ref() == Some(ident))
+        } else {
+            None
+        }
+    }
+
     /// Returns the list of fields that store guard objects.
     pub fn guard_fields(&self) -> &[Field] {
         &self.guard_fields
@@ -340,59 +730,323 @@ impl VariantData {
                     .iter()
                     .map(|fld| (fld, FieldKind::PathSegment)),
             )
+            .chain(
+                self.path_params_field
+                    .as_ref()
+                    .map(|fld| (fld, FieldKind::PathParams)),
+            )
             .chain(self.body_field.as_ref().map(|fld| (fld, FieldKind::Body)))
             .chain(
                 self.query_params_field
                     .as_ref()
                     .map(|fld| (fld, FieldKind::QueryParams)),
             )
+            .chain(
+                self.query_fields
+                    .iter()
+                    .map(|query| (&query.field, FieldKind::Query)),
+            )
             .chain(
                 self.forward_field
                     .as_ref()
                     .map(|fld| (fld, FieldKind::Forward)),
             )
+            .chain(
+                self.header_fields
+                    .iter()
+                    .map(|(fld, _)| (fld, FieldKind::Header)),
+            )
+            .chain(
+                self.cookie_fields
+                    .iter()
+                    .map(|(fld, _)| (fld, FieldKind::Cookie)),
+            )
     }
 }
 
 /// A parsed HTTP route attribute (eg. `#[get("/path/{placeholder}/bla/{rest...}")]`).
 #[derive(Clone)]
 pub struct Route {
-    /// Name of the associated constant on `http::Method`.
-    method: Ident,
+    /// The HTTP method matched by this route (eg. `GET` or `PROPFIND`).
+    ///
+    /// This is passed to `http::Method::from_bytes` by the generated code.
+    method: String,
+    /// Whether this route was declared using the generic `#[route]` attribute.
+    generic: bool,
     path: RoutePath,
+    /// The rank given via `rank = N`, if any.
+    ///
+    /// When multiple routes match a request, the one with the lowest rank is chosen. Routes with
+    /// different ranks are allowed to overlap.
+    rank: Option<u32>,
+    /// Whether this is a `HEAD` route that was implied by a `GET` route.
+    implied: bool,
+    /// The host pattern given via `host = "..."` (or `#[host]` on the type), if any.
+    ///
+    /// Routes with a host only match requests for a matching host name. Routes with disjoint hosts
+    /// are allowed to overlap.
+    host: Option<HostPattern>,
+    /// The media type given via `consumes = "..."`, which the request's `Content-Type` must match.
+    consumes: Option<MediaType>,
+    /// The media type given via `produces = "..."`, which the request's `Accept` header must allow.
+    produces: Option<MediaType>,
 }
 
 impl Route {
-    fn parse(method: Ident, args: &[&NestedMeta]) -> Self {
-        match args {
-            [NestedMeta::Literal(Lit::Str(path))] => {
-                let path = path.value();
+    fn new(method: String, generic: bool, path: String) -> Self {
+        Self {
+            method,
+            generic,
+            path: RoutePath::parse(path),
+            rank: None,
+            implied: false,
+            host: None,
+            consumes: None,
+            produces: None,
+        }
+    }
 
-                Self {
-                    method: Ident::new(&method.to_string().to_uppercase(), Span::call_site()),
-                    path: RoutePath::parse(path),
-                }
-            }
+    fn parse(method: Ident, args: &[&NestedMeta]) -> Self {
+        let (path, options) = match args.split_first() {
+            Some((NestedMeta::Literal(Lit::Str(path)), options)) => (path.value(), options),
             _ => {
                 panic!("route attributes must be of the form `#[method(\"/path/to/match\")]`");
             }
+        };
+
+        let mut route = Self::new(method.to_string().to_uppercase(), false, path);
+        for option in options {
+            match option {
+                NestedMeta::Meta(Meta::NameValue(nv)) => route.parse_option(nv),
+                _ => panic!(
+                    "route attributes must be of the form `#[method(\"/path/to/match\")]`, optionally followed by `key = value` arguments"
+                ),
+            }
+        }
+        route
+    }
+
+    /// Parses the arguments of a `#[route(method = "METHOD", path = "/path")]` attribute.
+    fn parse_generic(args: &[&NestedMeta]) -> Self {
+        let mut method = None;
+        let mut path = None;
+        let mut options = Vec::new();
+
+        for arg in args {
+            match arg {
+                NestedMeta::Meta(Meta::NameValue(nv)) => match &nv.lit {
+                    Lit::Str(s) if nv.ident == "method" => {
+                        insert("`method` in #[route]", &mut method, s.value())
+                    }
+                    Lit::Str(s) if nv.ident == "path" => {
+                        insert("`path` in #[route]", &mut path, s.value())
+                    }
+                    _ => options.push(nv),
+                },
+                _ => panic!(
+                    "#[route] attributes must be of the form `#[route(method = \"METHOD\", path = \"/path/to/match\")]`"
+                ),
+            }
+        }
+
+        let method = method.expect("#[route] attribute is missing the `method` argument");
+        let path = path.expect("#[route] attribute is missing the `path` argument");
+        if !valid_token(&method) {
+            panic!("`{}` is not a valid HTTP method", method);
+        }
+
+        let mut route = Self::new(method, true, path);
+        for option in options {
+            route.parse_option(option);
+        }
+        route
+    }
+
+    /// Parses an optional `key = value` argument that is accepted by all route attributes.
+    fn parse_option(&mut self, nv: &MetaNameValue) {
+        match &nv.lit {
+            Lit::Int(rank) if nv.ident == "rank" => {
+                let rank = u32::try_from(rank.value())
+                    .unwrap_or_else(|_| panic!("route rank {} is too large", rank.value()));
+
+                insert("`rank`", &mut self.rank, rank);
+            }
+            Lit::Str(host) if nv.ident == "host" => {
+                insert("`host`", &mut self.host, HostPattern::parse(host.value()));
+            }
+            Lit::Str(media) if nv.ident == "consumes" => {
+                let media = MediaType::parse("consumes", media.value());
+                insert("`consumes`", &mut self.consumes, media);
+            }
+            Lit::Str(media) if nv.ident == "produces" => {
+                let media = MediaType::parse("produces", media.value());
+                insert("`produces`", &mut self.produces, media);
+            }
+            _ => panic!("unknown or invalid route argument `{}`", nv.ident),
         }
     }
 
+    /// Returns the HTTP method matched by this route.
+    pub fn method(&self) -> &str {
+        &self.method
+    }
+
+    /// Returns the rank of this route (lower ranks take precedence).
+    pub fn rank(&self) -> u32 {
+        self.rank.unwrap_or(0)
+    }
+
+    /// Returns the path pattern as written in the attribute.
+    pub fn path(&self) -> &str {
+        &self.path.raw
+    }
+
     pub fn placeholders(&self) -> &[Ident] {
         &self.path.placeholders
     }
+
+    /// Returns the host pattern this route is restricted to, if any.
+    pub fn host(&self) -> Option<&HostPattern> {
+        self.host.as_ref()
+    }
+
+    /// Returns the placeholders in the host pattern, in order of appearance.
+    pub fn host_placeholders(&self) -> &[Ident] {
+        self.host
+            .as_ref()
+            .map(|host| &host.placeholders[..])
+            .unwrap_or(&[])
+    }
+
+    /// Returns the media type the request body must have, if any.
+    pub fn consumes(&self) -> Option<&MediaType> {
+        self.consumes.as_ref()
+    }
+
+    /// Returns the media type of the response, which the request must accept, if any.
+    pub fn produces(&self) -> Option<&MediaType> {
+        self.produces.as_ref()
+    }
+
+    /// Returns whether a request might satisfy the host and media type conditions of both `self`
+    /// and `other` (ignoring the path).
+    fn conditions_overlap(&self, other: &Route) -> bool {
+        let host = match (&self.host, &other.host) {
+            (Some(a), Some(b)) => a.find_overlap(b).is_some(),
+            _ => true,
+        };
+        host && MediaType::overlap(&self.consumes, &other.consumes)
+            && MediaType::overlap(&self.produces, &other.produces)
+    }
+
+    /// Returns whether `self` and `other` have the same host pattern and media types.
+    fn same_conditions(&self, other: &Route) -> bool {
+        let host = match (&self.host, &other.host) {
+            (Some(a), Some(b)) => a.regex() == b.regex(),
+            (None, None) => true,
+            _ => false,
+        };
+        host && self.consumes == other.consumes && self.produces == other.produces
+    }
+
+    /// Tries to find a request (ignoring the method) matched by both `self` and `other`, and
+    /// describes it.
+    fn find_overlap(&self, other: &Route) -> Option<String> {
+        if !MediaType::overlap(&self.consumes, &other.consumes)
+            || !MediaType::overlap(&self.produces, &other.produces)
+        {
+            return None;
+        }
+
+        let path = self.path.find_overlap(&other.path)?;
+        let host = match (&self.host, &other.host) {
+            (Some(a), Some(b)) => a.find_overlap(b)?,
+            (Some(host), None) | (None, Some(host)) => host.matching_string(),
+            (None, None) => return Some(format!("path `{}`", path)),
+        };
+        Some(format!("path `{}` on host `{}`", path, host))
+    }
+
+    /// Returns whether this is a `HEAD` route that was implied by a `GET` route.
+    pub fn is_implied(&self) -> bool {
+        self.implied
+    }
+
+    /// Returns the path split into literal text and placeholders.
+    ///
+    /// Adjacent literal text is merged into a single `PathPart::Literal`.
+    pub fn path_parts(&self) -> Vec<PathPart> {
+        if self.path.segments.is_empty() {
+            return vec![PathPart::Literal("*".to_string())];
+        }
+
+        let mut parts = Vec::new();
+        for segment in &self.path.segments {
+            PathPart::push_literal(&mut parts, "/");
+            segment.push_parts(&mut parts);
+        }
+        parts
+    }
+
+    /// Returns the path as a template containing only `/{placeholder}` segments and literal text
+    /// (as used by OpenAPI), or `None` for the asterisk path `*`.
+    #[cfg(feature = "openapi")]
+    pub fn template(&self) -> Option<String> {
+        if self.path.segments.is_empty() {
+            return None;
+        }
+
+        Some(
+            self.path_parts()
+                .iter()
+                .map(|part| match part {
+                    PathPart::Literal(lit) => lit.clone(),
+                    PathPart::Placeholder(ident) | PathPart::Rest(ident) => {
+                        format!("{{{}}}", placeholder_name(ident))
+                    }
+                })
+                .collect(),
+        )
+    }
+
+    /// Returns the regex constraint placed on the placeholder `name`, if any.
+    #[cfg(feature = "openapi")]
+    pub fn constraint(&self, name: &Ident) -> Option<&str> {
+        self.path
+            .segments
+            .iter()
+            .flat_map(|segment| segment.constraint(name))
+            .next()
+    }
 }
 
 impl fmt::Display for Route {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let method = self.method.to_string().to_lowercase();
-        if is_method(&self.method) {
-            write!(f, "#[{}(\"{}\")]", method, self.path.raw)
+        if self.generic {
+            write!(
+                f,
+                "#[route(method = \"{}\", path = \"{}\"",
+                self.method, self.path.raw
+            )?;
         } else {
-            // XXX this isn't yet implemented
-            write!(f, "#[route({}, \"{}\")]", method, self.path.raw)
+            let method = self.method.to_lowercase();
+            write!(f, "#[{}(\"{}\"", method, self.path.raw)?;
+        }
+
+        if let Some(rank) = self.rank {
+            write!(f, ", rank = {}", rank)?;
         }
+        if let Some(host) = &self.host {
+            write!(f, ", host = \"{}\"", host.raw)?;
+        }
+        if let Some(consumes) = &self.consumes {
+            write!(f, ", consumes = \"{}\"", consumes)?;
+        }
+        if let Some(produces) = &self.produces {
+            write!(f, ", produces = \"{}\"", produces)?;
+        }
+
+        f.write_str(")]")
     }
 }
 
@@ -440,34 +1094,24 @@ impl RoutePath {
             panic!("paths of route attributes must start with `/`");
         }
 
-        let segments = path
-            .split('/')
+        let segments = split_segments(&path, '/')
+            .into_iter()
             .skip(1)
-            .map(|s| PathSegment::parse(s.into()))
+            .map(PathSegment::parse)
             .collect::<Vec<_>>();
 
         let mut regex = String::new();
         let mut placeholders = Vec::new();
         for (i, segment) in segments.iter().enumerate() {
-            match segment {
-                PathSegment::Rest(ident) => {
-                    // "Rest" placeholder capturing *everything*. Only valid at the end.
-                    if i != segments.len() - 1 {
-                        panic!("...-placeholders must not be followed by anything");
-                    }
-
-                    placeholders.push(ident.clone());
-                    regex.push_str("/(.*)");
-                }
-                PathSegment::Placeholder(ident) => {
-                    placeholders.push(ident.clone());
-                    regex.push_str("/([^/]+)");
-                }
-                PathSegment::Literal(literal) => {
-                    regex.push('/');
-                    regex_syntax::escape_into(literal, &mut regex);
+            if let PathSegment::Rest(_) = segment {
+                // "Rest" placeholder capturing *everything*. Only valid at the end.
+                if i != segments.len() - 1 {
+                    panic!("...-placeholders must not be followed by anything");
                 }
             }
+
+            regex.push('/');
+            segment.push_regex(&mut regex, &mut placeholders, '/');
         }
 
         // Need to check that no duplicate placeholders were used
@@ -498,9 +1142,9 @@ impl RoutePath {
     pub fn find_overlap(&self, other: &Self) -> Option<String> {
         use self::PathSegment::*;
 
-        if self.segments.is_empty() {
-            // self is "*"
-            if other.segments.is_empty() {
+        if self.segments.is_empty() || other.segments.is_empty() {
+            // `*` only overlaps with itself
+            if self.segments.is_empty() && other.segments.is_empty() {
                 return Some("*".into());
             } else {
                 return None;
@@ -525,23 +1169,9 @@ impl RoutePath {
                     saw_rest = true;
                 }
 
-                (Placeholder(a), Placeholder(_)) => {
-                    overlap.push('/');
-                    overlap.push_str(&a.to_string());
-                }
-
-                (Placeholder(_), Literal(lit)) | (Literal(lit), Placeholder(_)) => {
+                (a, b) => {
                     overlap.push('/');
-                    overlap.push_str(&lit);
-                }
-
-                (Literal(a), Literal(b)) => {
-                    if a == b {
-                        overlap.push('/');
-                        overlap.push_str(&a);
-                    } else {
-                        return None;
-                    }
+                    overlap.push_str(&a.find_overlap(b)?);
                 }
             }
         }
@@ -567,6 +1197,26 @@ impl RoutePath {
     }
 }
 
+/// Splits `path` at every `separator` that is not part of a `{placeholder}` (whose regex
+/// constraint might contain it).
+fn split_segments(path: &str, separator: char) -> Vec<String> {
+    let mut segments = vec![String::new()];
+    let mut depth = 0usize;
+    for c in path.chars() {
+        match c {
+            _ if c == separator && depth == 0 => segments.push(String::new()),
+            '{' => depth += 1,
+            '}' => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+
+        if c != separator || depth != 0 {
+            segments.last_mut().unwrap().push(c);
+        }
+    }
+    segments
+}
+
 enum SegmentsFused<'a> {
     Unfused(slice::Iter<'a, PathSegment>),
     Fused(&'a PathSegment),
@@ -590,126 +1240,753 @@ impl<'a> Iterator for SegmentsFused<'a> {
     }
 }
 
-/// Segment of a request path pattern.
-#[derive(Clone)]
-pub enum PathSegment {
-    /// `{ident}`
-    Placeholder(Ident),
-    /// `{ident...}`
-    Rest(Ident),
-    /// `anything else`
-    Literal(String),
+/// A media type given via `consumes = "..."` or `produces = "..."`.
+///
+/// The subtype may be `*`, which matches any subtype (as in `text/*`).
+#[derive(Clone, PartialEq)]
+pub struct MediaType {
+    /// The lowercase type (eg. `application`).
+    ty: String,
+    /// The lowercase subtype (eg. `json`), or `*`.
+    subtype: String,
 }
 
-impl PathSegment {
-    fn parse(segment: String) -> Self {
-        if segment.starts_with('{') && segment.ends_with('}') {
-            let inner = &segment[1..segment.len() - 1];
-            if inner.ends_with("...") {
-                let ident = &inner[..inner.len() - 3];
-                if !valid_ident(ident) {
-                    panic!("placeholder `{}` must be a valid identifier", inner);
-                }
+impl MediaType {
+    fn parse(arg: &str, media: String) -> Self {
+        let (ty, subtype) = match media.find('/') {
+            Some(pos) => (&media[..pos], &media[pos + 1..]),
+            None => ("", ""),
+        };
+        if !valid_token(ty) || ty == "*" || (!valid_token(subtype) && subtype != "*") {
+            panic!(
+                "`{}` must be a media type of the form `type/subtype` or `type/*` (got `{}`)",
+                arg, media
+            );
+        }
 
-                PathSegment::Rest(Ident::new(ident, Span::call_site()))
-            } else {
-                // Else the placeholder must be a valid ident that will store a segment
-                if !valid_ident(inner) {
-                    panic!("placeholder `{}` must be a valid identifier", inner);
-                }
+        Self {
+            ty: ty.to_lowercase(),
+            subtype: subtype.to_lowercase(),
+        }
+    }
 
-                PathSegment::Placeholder(Ident::new(inner, Span::call_site()))
+    /// Returns whether a request might match both media type conditions (`None` matches any
+    /// request).
+    fn overlap(a: &Option<Self>, b: &Option<Self>) -> bool {
+        match (a, b) {
+            (Some(a), Some(b)) => {
+                a.ty == b.ty && (a.subtype == b.subtype || a.subtype == "*" || b.subtype == "*")
             }
-        } else {
-            // literal
-            PathSegment::Literal(segment)
+            _ => true,
         }
     }
+}
 
-    /// Creates an example path segment that would match `self`.
-    fn matching_string(&self) -> String {
-        match self {
-            PathSegment::Placeholder(ident) => ident.to_string(),
-            PathSegment::Rest(ident) => format!("{}...", ident),
-            PathSegment::Literal(lit) => lit.clone(),
-        }
+impl fmt::Display for MediaType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.ty, self.subtype)
     }
 }
 
-/// Maps generated path regexes to method->variant maps.
-pub struct PathMap {
-    regex_map: IndexMap<ByProxy<Regex, str>, IndexMap<Ident, (VariantData, Route)>>,
-    fallback: Option<VariantData>,
+/// A parsed host name pattern (`host = "{tenant}.example.com"` or `#[host("example.com")]`).
+#[derive(Clone)]
+pub struct HostPattern {
+    /// The pattern as written in the attribute.
+    raw: String,
+    /// The dot-separated labels of the host name. Literal text is lowercase, since host names are
+    /// case-insensitive.
+    labels: Vec<PathSegment>,
+    /// Placeholder field names, in order of appearance.
+    placeholders: Vec<Ident>,
 }
 
-impl PathMap {
-    pub fn build(item: &ItemData, variants: &[VariantData]) -> Self {
-        let mut this = Self {
-            regex_map: IndexMap::new(),
-            fallback: None,
-        };
+impl HostPattern {
+    fn parse(host: String) -> Self {
+        let labels = split_segments(&host, '.')
+            .into_iter()
+            .map(|label| {
+                if label.is_empty() {
+                    panic!("host pattern `{}` contains an empty label", host);
+                }
+                Self::parse_label(PathSegment::parse(label), &host)
+            })
+            .collect::<Vec<_>>();
 
-        for variant in variants {
-            if variant.routes.is_empty() && variant.forward_field.is_some() {
-                if let Some(prev) = this.fallback {
+        let mut placeholders = Vec::new();
+        for label in &labels {
+            label.push_regex(&mut String::new(), &mut placeholders, '.');
+        }
+        let mut sorted = placeholders.clone();
+        sorted.sort();
+        sorted.dedup();
+        if sorted.len() != placeholders.len() {
+            panic!("duplicate placeholders in host pattern `{}`", host);
+        }
+
+        Self {
+            raw: host,
+            labels,
+            placeholders,
+        }
+    }
+
+    fn parse_label(label: PathSegment, host: &str) -> PathSegment {
+        match label {
+            PathSegment::Literal(lit) => {
+                if lit.contains(&[':', '/'][..]) {
                     panic!(
-                        "cannot define multiple fallback variants – `{ty}::{v1}` and `{ty}::{v2}` \
-                         both use `#[forward]` without a route attribute",
-                        ty = item.name,
-                        v1 = prev.name,
-                        v2 = variant.name,
+                        "host pattern `{}` must only contain a host name (no port or path)",
+                        host
                     );
+                }
+                PathSegment::Literal(lit.to_lowercase())
+            }
+            PathSegment::Rest(_) => panic!(
+                "...-placeholders are not allowed in host patterns (in `{}`)",
+                host
+            ),
+            PathSegment::Mixed(parts) => PathSegment::Mixed(
+                parts
+                    .into_iter()
+                    .map(|part| Self::parse_label(part, host))
+                    .collect(),
+            ),
+            placeholder @ PathSegment::Placeholder(..) => placeholder,
+        }
+    }
+
+    /// Returns the anchored regex matching (lowercase) host names matched by this pattern. Captures
+    /// the placeholders in order of appearance.
+    pub fn regex(&self) -> String {
+        let mut regex = String::from("^");
+        for (i, label) in self.labels.iter().enumerate() {
+            if i != 0 {
+                regex.push_str("\\.");
+            }
+            label.push_regex(&mut regex, &mut Vec::new(), '.');
+        }
+        regex.push('$');
+        regex
+    }
+
+    /// Returns the host pattern split into literal text and placeholders.
+    pub fn parts(&self) -> Vec<PathPart> {
+        let mut parts = Vec::new();
+        for (i, label) in self.labels.iter().enumerate() {
+            if i != 0 {
+                PathPart::push_literal(&mut parts, ".");
+            }
+            label.push_parts(&mut parts);
+        }
+        parts
+    }
+
+    /// Tries to find a host name matched by both `self` and `other`.
+    fn find_overlap(&self, other: &Self) -> Option<String> {
+        if self.labels.len() != other.labels.len() {
+            return None;
+        }
+
+        let mut labels = Vec::new();
+        for (a, b) in self.labels.iter().zip(&other.labels) {
+            labels.push(a.find_overlap(b)?);
+        }
+        Some(labels.join("."))
+    }
+
+    /// Creates an example host name matched by `self`.
+    fn matching_string(&self) -> String {
+        self.labels
+            .iter()
+            .map(PathSegment::matching_string)
+            .collect::<Vec<_>>()
+            .join(".")
+    }
+}
+
+/// Segment of a request path pattern.
+#[derive(Clone)]
+pub enum PathSegment {
+    /// `{ident}` or `{ident:regex}`
+    Placeholder(Ident, Option<Constraint>),
+    /// `{ident...}`
+    Rest(Ident),
+    /// `anything else`
+    Literal(String),
+    /// Literal text mixed with placeholders, eg. `{id}.{format}` or `v{version}`.
+    ///
+    /// Contains only `Literal` and `Placeholder` parts, and no two placeholders are adjacent.
+    Mixed(Vec<PathSegment>),
+}
+
+impl PathSegment {
+    fn parse(segment: String) -> Self {
+        // Split the segment into literal text and `{placeholders}`
+        let mut parts = Vec::new();
+        let mut rest = segment.as_str();
+        while let Some(start) = rest.find('{') {
+            let end = match closing_brace(&rest[start..]) {
+                Some(end) => start + end,
+                None => break, // unterminated, treat as literal text
+            };
+
+            if start > 0 {
+                parts.push(PathSegment::Literal(rest[..start].to_string()));
+            }
+            parts.push(Self::parse_placeholder(&rest[start..=end]));
+            rest = &rest[end + 1..];
+        }
+        if !rest.is_empty() || parts.is_empty() {
+            parts.push(PathSegment::Literal(rest.to_string()));
+        }
+
+        if parts.len() == 1 {
+            return parts.pop().unwrap();
+        }
+
+        // Segment mixing literal text and placeholders
+        for (i, part) in parts.iter().enumerate() {
+            match part {
+                PathSegment::Rest(_) => panic!(
+                    "...-placeholders must make up a whole path segment (in `{}`)",
+                    segment
+                ),
+                PathSegment::Placeholder(..) => {
+                    if let Some(PathSegment::Placeholder(..)) = parts.get(i + 1) {
+                        panic!(
+                            "placeholders must be separated by literal text (in `{}`)",
+                            segment
+                        );
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        PathSegment::Mixed(parts)
+    }
+
+    /// Parses a `{placeholder}`, including the braces.
+    fn parse_placeholder(placeholder: &str) -> Self {
+        let inner = &placeholder[1..placeholder.len() - 1];
+        if let Some(colon) = inner.find(':') {
+            // Placeholder with a regex constraint
+            let (ident, constraint) = (&inner[..colon], &inner[colon + 1..]);
+            if ident.ends_with("...") {
+                panic!(
+                    "...-placeholders cannot have a regex constraint (in `{}`)",
+                    placeholder
+                );
+            }
+            PathSegment::Placeholder(
+                placeholder_ident(ident, ident),
+                Some(Constraint::parse(ident, constraint)),
+            )
+        } else if inner.ends_with("...") {
+            let ident = &inner[..inner.len() - 3];
+            PathSegment::Rest(placeholder_ident(ident, inner))
+        } else {
+            // Else the placeholder must be a valid ident (or field index) that will store a
+            // segment
+            PathSegment::Placeholder(placeholder_ident(inner, inner), None)
+        }
+    }
+
+    /// Appends the regex matching this segment to `regex`, and the names of the placeholders it
+    /// captures to `placeholders`.
+    ///
+    /// Placeholders without a constraint match anything but `separator` (`/` for path segments and
+    /// `.` for host labels).
+    fn push_regex(&self, regex: &mut String, placeholders: &mut Vec<Ident>, separator: char) {
+        match self {
+            PathSegment::Rest(ident) => {
+                placeholders.push(ident.clone());
+                regex.push_str("(.*)");
+            }
+            PathSegment::Placeholder(ident, None) => {
+                placeholders.push(ident.clone());
+                regex.push_str("([^");
+                regex_syntax::escape_into(&separator.to_string(), regex);
+                regex.push_str("]+)");
+            }
+            PathSegment::Placeholder(ident, Some(constraint)) => {
+                placeholders.push(ident.clone());
+                regex.push_str("((?:");
+                regex.push_str(&constraint.source);
+                regex.push_str("))");
+            }
+            PathSegment::Literal(literal) => {
+                regex_syntax::escape_into(literal, regex);
+            }
+            PathSegment::Mixed(parts) => {
+                for part in parts {
+                    part.push_regex(regex, placeholders, separator);
+                }
+            }
+        }
+    }
+
+    /// Returns whether `literal` is a path segment matched by `self`.
+    ///
+    /// Must not be called on `Rest` segments.
+    fn matches_literal(&self, literal: &str) -> bool {
+        match self {
+            PathSegment::Literal(lit) => lit == literal,
+            PathSegment::Placeholder(_, None) => true,
+            PathSegment::Placeholder(_, Some(constraint)) => constraint.regex.is_match(literal),
+            PathSegment::Mixed(_) => {
+                let mut regex = String::from("^");
+                self.push_regex(&mut regex, &mut Vec::new(), '/');
+                regex.push('$');
+                Regex::new(&regex)
+                    .expect("FromRequest derive created invalid regex")
+                    .is_match(literal)
+            }
+            PathSegment::Rest(_) => unreachable!("`Rest` segment in `matches_literal`"),
+        }
+    }
+
+    /// Returns `true` if it can be proven that no path segment is matched by both `self` and
+    /// `other`.
+    ///
+    /// This is conservative and must not be called on `Literal` or `Rest` segments (use
+    /// `matches_literal` for the former).
+    fn is_disjoint(&self, other: &Self) -> bool {
+        use self::PathSegment::*;
+
+        match (self, other) {
+            (Placeholder(_, Some(a)), Placeholder(_, Some(b))) => a.is_disjoint(b),
+            (Mixed(parts), Placeholder(_, Some(constraint)))
+            | (Placeholder(_, Some(constraint)), Mixed(parts)) => match &parts[0] {
+                // A mixed segment is never empty, so checking the first character is enough
+                Literal(lit) => !class_contains(&constraint.first_chars, lit.chars().next().unwrap()),
+                _ => false,
+            },
+            (Mixed(a), Mixed(b)) => {
+                let (prefix_a, suffix_a) = literal_affixes(a);
+                let (prefix_b, suffix_b) = literal_affixes(b);
+                !(prefix_a.starts_with(prefix_b) || prefix_b.starts_with(prefix_a))
+                    || !(suffix_a.ends_with(suffix_b) || suffix_b.ends_with(suffix_a))
+            }
+            _ => false,
+        }
+    }
+
+    /// Tries to find a path segment matched by both `self` and `other`.
+    ///
+    /// Must not be called on `Rest` segments.
+    fn find_overlap(&self, other: &Self) -> Option<String> {
+        use self::PathSegment::*;
+
+        match (self, other) {
+            (Literal(lit), other) | (other, Literal(lit)) => {
+                if other.matches_literal(lit) {
+                    Some(lit.clone())
                 } else {
-                    this.fallback = Some(variant.clone());
+                    None
+                }
+            }
+            (a, b) => {
+                if a.is_disjoint(b) {
+                    return None;
+                }
+
+                // Mixed segments are more specific than placeholders
+                let example = match b {
+                    Mixed(_) => b,
+                    _ => a,
+                };
+                Some(example.matching_string())
+            }
+        }
+    }
+
+    /// Creates an example path segment that would match `self`.
+    fn matching_string(&self) -> String {
+        match self {
+            PathSegment::Placeholder(ident, _) => ident.to_string(),
+            PathSegment::Rest(ident) => format!("{}...", ident),
+            PathSegment::Literal(lit) => lit.clone(),
+            PathSegment::Mixed(parts) => parts.iter().map(Self::matching_string).collect(),
+        }
+    }
+
+    /// Appends the literal text and placeholders making up `self` to `parts`.
+    fn push_parts(&self, parts: &mut Vec<PathPart>) {
+        match self {
+            PathSegment::Placeholder(ident, _) => parts.push(PathPart::Placeholder(ident.clone())),
+            PathSegment::Rest(ident) => parts.push(PathPart::Rest(ident.clone())),
+            PathSegment::Literal(lit) => PathPart::push_literal(parts, lit),
+            PathSegment::Mixed(segment_parts) => {
+                for part in segment_parts {
+                    part.push_parts(parts);
+                }
+            }
+        }
+    }
+
+    /// Returns the regex constraint of the placeholder `name`, if `self` contains it.
+    #[cfg(feature = "openapi")]
+    fn constraint(&self, name: &Ident) -> Option<&str> {
+        match self {
+            PathSegment::Placeholder(ident, Some(constraint)) if ident == name => {
+                Some(&constraint.source)
+            }
+            PathSegment::Mixed(parts) => parts.iter().flat_map(|part| part.constraint(name)).next(),
+            _ => None,
+        }
+    }
+}
+
+/// A piece of a route path, as returned by `Route::path_parts`.
+pub enum PathPart {
+    /// Literal text, including `/` separators.
+    Literal(String),
+    /// `{ident}` or `{ident:regex}`, matching (part of) a single path segment.
+    Placeholder(Ident),
+    /// `{ident...}`, matching the rest of the path.
+    Rest(Ident),
+}
+
+impl PathPart {
+    fn push_literal(parts: &mut Vec<PathPart>, literal: &str) {
+        if let Some(PathPart::Literal(last)) = parts.last_mut() {
+            last.push_str(literal);
+            return;
+        }
+        parts.push(PathPart::Literal(literal.to_string()));
+    }
+}
+
+/// Returns the byte index of the `}` closing the `{` that starts `s`.
+fn closing_brace(s: &str) -> Option<usize> {
+    let mut depth = 0;
+    for (i, c) in s.char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Returns the literal text at the start and at the end of a mixed segment (or `""` if it starts or
+/// ends with a placeholder).
+fn literal_affixes(parts: &[PathSegment]) -> (&str, &str) {
+    fn affix(part: &PathSegment) -> &str {
+        match part {
+            PathSegment::Literal(lit) => lit,
+            _ => "",
+        }
+    }
+
+    (affix(&parts[0]), affix(&parts[parts.len() - 1]))
+}
+
+/// A regex constraint on a path placeholder (`{ident:regex}`).
+#[derive(Clone)]
+pub struct Constraint {
+    /// The regex as written by the user.
+    source: String,
+    /// `source`, anchored to match the whole path segment.
+    regex: Regex,
+    /// A superset of the characters a nonempty match can start with.
+    first_chars: ClassUnicode,
+    /// Whether the constraint matches the empty string.
+    nullable: bool,
+}
+
+impl Constraint {
+    fn parse(ident: &str, source: &str) -> Self {
+        let regex = Regex::new(&format!("^(?:{})$", source)).unwrap_or_else(|e| {
+            panic!("invalid regex constraint for placeholder `{}`: {}", ident, e)
+        });
+        if regex.captures_len() > 1 {
+            panic!(
+                "regex constraint for placeholder `{}` must not contain capture groups (use `(?:...)` instead)",
+                ident
+            );
+        }
+
+        let hir = regex_syntax::Parser::new()
+            .parse(source)
+            .expect("internal error: regex crate accepted regex that regex-syntax rejects");
+        if class_contains(&hir_chars(&hir, false), '/') {
+            panic!(
+                "regex constraint for placeholder `{}` must not match `/`",
+                ident
+            );
+        }
+
+        Self {
+            source: source.to_string(),
+            regex,
+            first_chars: hir_chars(&hir, true),
+            nullable: hir_nullable(&hir),
+        }
+    }
+
+    /// Returns the regex as written by the user.
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Returns `true` if it can be proven that `self` and `other` do not match a common string.
+    ///
+    /// This is conservative: Some disjoint constraints are not detected as such.
+    fn is_disjoint(&self, other: &Self) -> bool {
+        let mut common = self.first_chars.clone();
+        common.intersect(&other.first_chars);
+        common.ranges().is_empty() && !(self.nullable && other.nullable)
+    }
+}
+
+/// Computes a superset of the characters matched by `hir`.
+///
+/// If `first_only` is `true`, only characters that can start a match are included.
+fn hir_chars(hir: &Hir, first_only: bool) -> ClassUnicode {
+    let any = || ClassUnicode::new(vec![ClassUnicodeRange::new('\0', std::char::MAX)]);
+    match hir.kind() {
+        HirKind::Empty | HirKind::Anchor(_) | HirKind::WordBoundary(_) => ClassUnicode::empty(),
+        HirKind::Literal(Literal::Unicode(c)) => {
+            ClassUnicode::new(vec![ClassUnicodeRange::new(*c, *c)])
+        }
+        HirKind::Class(Class::Unicode(class)) => class.clone(),
+        // Byte-based matching is rare in path constraints, so be maximally conservative
+        HirKind::Literal(Literal::Byte(_)) | HirKind::Class(Class::Bytes(_)) => any(),
+        HirKind::Repetition(rep) => hir_chars(&rep.hir, first_only),
+        HirKind::Group(group) => hir_chars(&group.hir, first_only),
+        HirKind::Concat(hirs) => {
+            let mut chars = ClassUnicode::empty();
+            for hir in hirs {
+                chars.union(&hir_chars(hir, first_only));
+                if first_only && !hir_nullable(hir) {
+                    break;
                 }
             }
+            chars
+        }
+        HirKind::Alternation(hirs) => {
+            let mut chars = ClassUnicode::empty();
+            for hir in hirs {
+                chars.union(&hir_chars(hir, first_only));
+            }
+            chars
+        }
+    }
+}
+
+/// Returns whether `hir` matches the empty string.
+fn hir_nullable(hir: &Hir) -> bool {
+    match hir.kind() {
+        HirKind::Empty | HirKind::Anchor(_) | HirKind::WordBoundary(_) => true,
+        HirKind::Literal(_) | HirKind::Class(_) => false,
+        HirKind::Repetition(rep) => {
+            let min = match &rep.kind {
+                RepetitionKind::ZeroOrOne | RepetitionKind::ZeroOrMore => 0,
+                RepetitionKind::OneOrMore => 1,
+                RepetitionKind::Range(RepetitionRange::Exactly(n))
+                | RepetitionKind::Range(RepetitionRange::AtLeast(n))
+                | RepetitionKind::Range(RepetitionRange::Bounded(n, _)) => *n,
+            };
+            min == 0 || hir_nullable(&rep.hir)
+        }
+        HirKind::Group(group) => hir_nullable(&group.hir),
+        HirKind::Concat(hirs) => hirs.iter().all(hir_nullable),
+        HirKind::Alternation(hirs) => hirs.iter().any(hir_nullable),
+    }
+}
+
+fn class_contains(class: &ClassUnicode, c: char) -> bool {
+    class
+        .ranges()
+        .iter()
+        .any(|range| range.start() <= c && c <= range.end())
+}
+
+/// Maps HTTP methods to the routes (and the variants they belong to) using them.
+type MethodMap = IndexMap<String, Vec<(VariantData, Route)>>;
+
+/// Maps generated path regexes to method->variant maps.
+///
+/// Every method normally maps to a single variant. Only in fallthrough mode, multiple routes with
+/// the same path and method can be defined (and are tried in order).
+pub struct PathMap {
+    regex_map: IndexMap<ByProxy<Regex, str>, MethodMap>,
+    /// All distinct host patterns used by routes, keyed by their regex.
+    hosts: IndexMap<String, HostPattern>,
+    /// Whether any route has a `consumes` or `produces` argument.
+    media_types: bool,
+    /// `(path index, method, position)` of every route, in the order they were added.
+    order: Vec<(usize, String, usize)>,
+    /// Variants with a `#[forward(prefix = "...")]` field, in declaration order.
+    mounts: Vec<Mount>,
+    /// Fallback variants (see `VariantData::is_fallback`), in declaration order.
+    fallbacks: Vec<VariantData>,
+    fallthrough: bool,
+}
+
+/// A variant whose `#[forward]` field is mounted under a path prefix.
+pub struct Mount {
+    prefix: String,
+    variant: VariantData,
+    /// The paths matched by the mount: the prefix itself, and everything below it.
+    paths: [RoutePath; 2],
+}
+
+impl Mount {
+    fn new(variant: VariantData) -> Self {
+        let prefix = variant.forward_prefix().unwrap().to_string();
+        let paths = [
+            RoutePath::parse(prefix.clone()),
+            RoutePath::parse(format!("{}/{{rest...}}", prefix)),
+        ];
+
+        Self {
+            prefix,
+            variant,
+            paths,
+        }
+    }
+
+    /// Returns the path prefix this variant is mounted under.
+    pub fn prefix(&self) -> &str {
+        &self.prefix
+    }
+
+    /// Returns the variant containing the mounted `#[forward]` field.
+    pub fn variant(&self) -> &VariantData {
+        &self.variant
+    }
+
+    /// Tries to find a path matched by both the mount and `path`.
+    fn find_overlap(&self, path: &RoutePath) -> Option<String> {
+        self.paths.iter().filter_map(|p| p.find_overlap(path)).next()
+    }
+}
+
+impl fmt::Display for Mount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#[forward(prefix = {:?})]", self.prefix)
+    }
+}
+
+impl PathMap {
+    pub fn build(item: &ItemData, variants: &[VariantData]) -> Self {
+        let mut this = Self {
+            regex_map: IndexMap::new(),
+            hosts: IndexMap::new(),
+            media_types: false,
+            order: Vec::new(),
+            mounts: Vec::new(),
+            fallbacks: Vec::new(),
+            fallthrough: item.fallthrough(),
+        };
+
+        for variant in variants {
+            if variant.forward_prefix.is_some() {
+                this.mounts.push(Mount::new(variant.clone()));
+            } else if variant.is_fallback() {
+                this.fallbacks.push(variant.clone());
+            }
 
             for route in &variant.routes {
                 // Check for overlap with all previously registered routes
+                // (routes with different ranks or disjoint hosts are allowed to overlap, and in
+                // fallthrough mode, all routes are).
                 for prev_route in this
                     .regex_map
                     .values()
-                    .flat_map(|m| m.values().map(|(_, r)| r))
+                    .flat_map(|m| m.values().flatten().map(|(_, r)| r))
+                    .filter(|_| !item.fallthrough())
                     .filter(|r| !r.path.matches_same_paths(&route.path))
+                    .filter(|r| r.rank() == route.rank())
                 {
-                    if let Some(overlap) = prev_route.path.find_overlap(&route.path) {
+                    if let Some(overlap) = prev_route.find_overlap(route) {
                         panic!(
-                            "route `{}` overlaps with previously defined route `{}` (both would match path `{}`)",
+                            "route `{}` overlaps with previously defined route `{}` (both would match {})",
                             route, prev_route, overlap
                         );
                     }
                 }
 
+                if let Some(host) = route.host() {
+                    this.hosts.entry(host.regex()).or_insert_with(|| host.clone());
+                }
+                if route.consumes.is_some() || route.produces.is_some() {
+                    this.media_types = true;
+                }
+
                 this.add_route(variant.clone(), route.clone());
             }
         }
 
-        // For each GET route, register a matching HEAD route if none exists
+        // Mounted `#[forward]` fields must not overlap with any route or with other mounts (unless
+        // we're in fallthrough mode, in which case routes are tried first)
+        if !item.fallthrough() {
+            for (i, mount) in this.mounts.iter().enumerate() {
+                for route in this
+                    .regex_map
+                    .values()
+                    .flat_map(|m| m.values().flatten().map(|(_, r)| r))
+                {
+                    if let Some(overlap) = mount.find_overlap(&route.path) {
+                        panic!(
+                            "route `{}` overlaps with `{}` on `{}` (both would match path `{}`)",
+                            route, mount, mount.variant.name, overlap
+                        );
+                    }
+                }
+
+                for prev in &this.mounts[..i] {
+                    if let Some(overlap) = mount.find_overlap(&prev.paths[1]) {
+                        panic!(
+                            "`{}` on `{}` overlaps with `{}` on `{}` (both would match path `{}`)",
+                            mount, mount.variant.name, prev, prev.variant.name, overlap
+                        );
+                    }
+                }
+            }
+        }
+
+        // For each GET route, register a matching HEAD route if none exists (an explicit HEAD route
+        // with a different rank, or in fallthrough mode, only prevents this if it matches the exact
+        // same paths).
         let any_head_overlaps_with = |new_route: &Route| {
             this.regex_map
                 .values()
-                .flat_map(|map| {
-                    map.iter().filter_map(|(method, (_, route))| {
-                        if method.to_string() == "HEAD" {
-                            Some(route)
-                        } else {
-                            None
-                        }
-                    })
+                .flat_map(|map| map.get("HEAD"))
+                .flatten()
+                .map(|(_, route)| route)
+                .any(|route| {
+                    route.conditions_overlap(new_route)
+                        && (route.path.matches_same_paths(&new_route.path)
+                            || (!item.fallthrough()
+                                && route.rank() == new_route.rank()
+                                && route.path.find_overlap(&new_route.path).is_some()))
                 })
-                .any(|route| route.path.find_overlap(&new_route.path).is_some())
         };
         let mut implied_head_routes = Vec::new();
         for route_map in this.regex_map.values() {
-            for (method, (variant, route)) in route_map.iter() {
-                if method.to_string() == "GET" {
-                    let head = Route {
-                        method: Ident::new("HEAD", Span::call_site()),
-                        path: route.path.clone(),
-                    };
-                    if !any_head_overlaps_with(&head) {
-                        implied_head_routes.push((variant.clone(), head));
-                    }
+            for (variant, route) in route_map.get("GET").into_iter().flatten() {
+                let head = Route {
+                    method: "HEAD".to_string(),
+                    generic: false,
+                    path: route.path.clone(),
+                    rank: route.rank,
+                    implied: true,
+                    host: route.host.clone(),
+                    consumes: route.consumes.clone(),
+                    produces: route.produces.clone(),
+                };
+                if !any_head_overlaps_with(&head) {
+                    implied_head_routes.push((variant.clone(), head));
                 }
             }
         }
@@ -724,51 +2001,265 @@ impl PathMap {
     fn add_route(&mut self, variant: VariantData, route: Route) {
         let reg = ByProxy::new(route.path.regex.clone(), Regex::as_str);
         let entry = self.regex_map.entry(reg);
+        let index = entry.index();
         let route_map = entry.or_insert_with(IndexMap::new);
         match route_map.entry(route.method.clone()) {
             Entry::Vacant(v) => {
                 // Map this path regex and method to the variant it was placed on:
-                v.insert((variant, route));
+                self.order.push((index, route.method.clone(), 0));
+                v.insert(vec![(variant, route)]);
             }
-            Entry::Occupied(old) => {
-                // duplicate path declaration
-                let old = old.get();
-                panic!(
-                    "duplicate route: `{}` on `{}` matches the same requests as `{}` on `{}`",
-                    old.1, old.0.name, route, variant.name
-                );
+            Entry::Occupied(mut routes) => {
+                // Routes for the same path may only overlap when their hosts or media types
+                // differ and they have different ranks.
+                let duplicate = routes.get().iter().find(|(_, old)| {
+                    old.conditions_overlap(&route)
+                        && (old.rank() == route.rank() || old.same_conditions(&route))
+                });
+                match duplicate {
+                    Some((old_variant, old_route)) if !self.fallthrough => {
+                        // duplicate path declaration
+                        panic!(
+                            "duplicate route: `{}` on `{}` matches the same requests as `{}` on `{}`",
+                            old_route, old_variant.name, route, variant.name
+                        );
+                    }
+                    _ => {
+                        // Routes are tried in order (or only match different hosts), so this is
+                        // fine
+                        self.order
+                            .push((index, route.method.clone(), routes.get().len()));
+                        routes.get_mut().push((variant, route));
+                    }
+                }
             }
         }
     }
 
     /// Returns an iterator over all unique paths in this map.
     pub fn paths(&self) -> impl Iterator<Item = PathInfo<'_>> {
-        self.regex_map.iter().map(|(regex, method_map)| PathInfo {
-            regex: regex.as_ref(),
-            method_map,
+        self.regex_map
+            .values()
+            .map(|method_map| PathInfo { method_map })
+    }
+
+    /// Returns an iterator over all routes in this map, along with the index of their path (as
+    /// yielded by `paths()`) and the variant they belong to.
+    ///
+    /// Routes are yielded in declaration order, followed by implied `HEAD` routes.
+    pub fn routes(&self) -> impl Iterator<Item = (usize, &Route, &VariantData)> {
+        self.order.iter().map(move |(index, method, pos)| {
+            let (variant, route) = &self.regex_map[*index][method][*pos];
+            (*index, route, variant)
+        })
+    }
+
+    /// Returns an iterator over all distinct HTTP methods used by the routes in this map.
+    pub fn methods(&self) -> impl Iterator<Item = &str> {
+        self.regex_map
+            .values()
+            .flat_map(|map| map.keys())
+            .map(|method| method.as_str())
+            .collect::<IndexSet<_>>()
+            .into_iter()
+    }
+
+    /// Returns the distinct host patterns used by the routes in this map.
+    pub fn hosts(&self) -> impl Iterator<Item = &HostPattern> {
+        self.hosts.values()
+    }
+
+    /// Returns the index of the host pattern of `route` (as yielded by `hosts()`), or `None` if
+    /// the route matches any host.
+    pub fn host_index(&self, route: &Route) -> Option<usize> {
+        route.host().map(|host| {
+            self.hosts
+                .get_full(&host.regex())
+                .expect("internal error: host pattern not in map")
+                .0
         })
     }
 
-    /// Returns the fallback variant, a variant using `#[forward]`, without a route attribute.
-    pub fn fallback(&self) -> Option<&VariantData> {
-        self.fallback.as_ref()
+    /// Returns whether any route in this map is restricted to media types (which means that routes
+    /// may overlap).
+    pub fn uses_media_types(&self) -> bool {
+        self.media_types
+    }
+
+    /// Returns whether any route in this map is restricted to a host (which means that routes may
+    /// overlap).
+    pub fn uses_hosts(&self) -> bool {
+        !self.hosts.is_empty()
+    }
+
+    /// Returns whether any route in this map has a non-default rank (which means that routes may
+    /// overlap).
+    pub fn uses_ranks(&self) -> bool {
+        self.regex_map
+            .values()
+            .flat_map(|map| map.values().flatten())
+            .any(|(_, route)| route.rank() != 0)
+    }
+
+    /// Returns the variants with a mounted `#[forward(prefix = "...")]` field.
+    pub fn mounts(&self) -> &[Mount] {
+        &self.mounts
+    }
+
+    /// Returns the fallback variants, which use `#[forward]` without a route attribute. They are
+    /// tried in declaration order.
+    pub fn fallbacks(&self) -> &[VariantData] {
+        &self.fallbacks
     }
 }
 
 pub struct PathInfo<'a> {
-    regex: &'a Regex,
-    method_map: &'a IndexMap<Ident, (VariantData, Route)>,
+    method_map: &'a MethodMap,
 }
 
 impl<'a> PathInfo<'a> {
-    /// Returns the regex used to match this path.
-    pub fn regex(&self) -> &'a Regex {
-        &self.regex
+    /// Returns the segments of this path, or `None` if this is the asterisk path `*`.
+    pub fn segments(&self) -> Option<&'a [PathSegment]> {
+        let (_, route) = &self
+            .method_map
+            .values()
+            .flatten()
+            .next()
+            .expect("internal error: path without routes");
+        if route.path.segments.is_empty() {
+            None
+        } else {
+            Some(&route.path.segments)
+        }
+    }
+
+    /// Returns an iterator over the `Method => (Variant, Route)` mappings for this path.
+    ///
+    /// In fallthrough mode, or when routes are restricted to different hosts, a method might map
+    /// to more than one variant.
+    pub fn method_map(&self) -> impl Iterator<Item = (&'a str, &'a VariantData, &'a Route)> {
+        self.method_map.iter().flat_map(|(method, routes)| {
+            routes
+                .iter()
+                .map(move |(variant, route)| (method.as_str(), variant, route))
+        })
+    }
+}
+
+/// Parses the optional name argument of a `#[header("Name")]` or `#[cookie("name")]` attribute.
+///
+/// Returns `None` if the attribute was used without an argument.
+fn parse_name_arg(meta: &Meta) -> Option<String> {
+    let attr = meta.name();
+    let name = match meta {
+        Meta::Word(_) => return None,
+        Meta::List(list) => match list.nested.iter().collect::<Vec<_>>().as_slice() {
+            [NestedMeta::Literal(Lit::Str(name))] => name.value(),
+            _ => panic!(
+                "#[{attr}] attributes must be of the form `#[{attr}]` or `#[{attr}(\"name\")]`",
+                attr = attr
+            ),
+        },
+        Meta::NameValue(_) => panic!(
+            "#[{attr}] attributes must be of the form `#[{attr}]` or `#[{attr}(\"name\")]`",
+            attr = attr
+        ),
+    };
+
+    if !valid_token(&name) {
+        panic!("`{}` is not a valid {} name", name, attr);
+    }
+
+    Some(name)
+}
+
+/// Parses the arguments of a `#[query]`, `#[query("name")]`, `#[query(default)]` or
+/// `#[query("name", default)]` attribute.
+fn parse_query_args(meta: &Meta) -> (Option<String>, bool) {
+    const FORMAT: &str =
+        "#[query] attributes must be of the form `#[query]`, `#[query(\"name\")]`, \
+         `#[query(default)]` or `#[query(\"name\", default)]`";
+
+    let list = match meta {
+        Meta::Word(_) => return (None, false),
+        Meta::List(list) => list,
+        Meta::NameValue(_) => panic!("{}", FORMAT),
+    };
+
+    let mut name = None;
+    let mut default = false;
+    for (i, nested) in list.nested.iter().enumerate() {
+        match nested {
+            NestedMeta::Literal(Lit::Str(s)) if i == 0 => name = Some(s.value()),
+            NestedMeta::Meta(Meta::Word(word)) if word == "default" && !default => default = true,
+            _ => panic!("{}", FORMAT),
+        }
     }
 
-    /// Returns an iterator over the `Method => Variant` mappings for this path.
-    pub fn method_map(&self) -> impl Iterator<Item = (&'a Ident, &'a VariantData)> {
-        self.method_map.iter().map(|(k, v)| (k, &v.0))
+    if name.as_ref().map(String::is_empty) == Some(true) {
+        panic!("query parameter names must not be empty");
+    }
+
+    (name, default)
+}
+
+/// Parses the optional `prefix` argument of a `#[forward(prefix = "/path")]` attribute.
+fn parse_forward_prefix(meta: &Meta) -> Option<String> {
+    let prefix = match meta {
+        Meta::Word(_) => return None,
+        Meta::List(list) => match list.nested.iter().collect::<Vec<_>>().as_slice() {
+            [NestedMeta::Meta(Meta::NameValue(MetaNameValue {
+                ident,
+                lit: Lit::Str(prefix),
+                ..
+            }))] if ident == "prefix" => prefix.value(),
+            _ => panic!("#[forward] attributes must be of the form `#[forward]` or `#[forward(prefix = \"/path\")]`"),
+        },
+        Meta::NameValue(_) => panic!("#[forward] attributes must be of the form `#[forward]` or `#[forward(prefix = \"/path\")]`"),
+    };
+
+    if !prefix.starts_with('/') || prefix.ends_with('/') {
+        panic!(
+            "forward prefix `{}` must start with `/` and must not end with `/`",
+            prefix
+        );
+    }
+    if prefix.contains(&['{', '}'][..]) {
+        panic!("forward prefix `{}` must not contain placeholders", prefix);
+    }
+
+    Some(prefix)
+}
+
+fn parse_body_buffer_limit(meta: &Meta) -> Option<u64> {
+    const FORMAT: &str =
+        "#[body] attributes must be of the form `#[body]` or `#[body(buffer_limit = N)]`";
+
+    match meta {
+        Meta::Word(_) => None,
+        Meta::List(list) => match list.nested.iter().collect::<Vec<_>>().as_slice() {
+            [NestedMeta::Meta(Meta::NameValue(MetaNameValue {
+                ident,
+                lit: Lit::Int(limit),
+                ..
+            }))] if ident == "buffer_limit" => Some(limit.value()),
+            _ => panic!("{}", FORMAT),
+        },
+        Meta::NameValue(_) => panic!("{}", FORMAT),
+    }
+}
+
+fn parse_query_params_nested(meta: &Meta) -> bool {
+    const FORMAT: &str =
+        "#[query_params] attributes must be of the form `#[query_params]` or `#[query_params(nested)]`";
+
+    match meta {
+        Meta::Word(_) => false,
+        Meta::List(list) => match list.nested.iter().collect::<Vec<_>>().as_slice() {
+            [NestedMeta::Meta(Meta::Word(word))] if word == "nested" => true,
+            _ => panic!("{}", FORMAT),
+        },
+        Meta::NameValue(_) => panic!("{}", FORMAT),
     }
 }
 
@@ -780,6 +2271,43 @@ fn insert<T>(name: &str, slot: &mut Option<T>, value: T) {
     *slot = Some(value);
 }
 
+/// Returns the identifier of the field referred to by the placeholder `name`.
+///
+/// Positional placeholders (`{0}`) refer to unnamed fields, see `positional_ident`.
+fn placeholder_ident(name: &str, placeholder: &str) -> Ident {
+    match name.parse::<usize>() {
+        Ok(index) if index.to_string() == name => positional_ident(index),
+        _ if valid_ident(name) => Ident::new(name, Span::call_site()),
+        _ => panic!(
+            "placeholder `{}` must be a valid identifier or field index",
+            placeholder
+        ),
+    }
+}
+
+/// Returns the identifier used for the unnamed field at `index` (`_0`, `_1`, ...).
+///
+/// Unnamed fields are given these identifiers when parsing a variant, so the rest of the derive
+/// can treat all fields alike.
+pub fn positional_ident(index: usize) -> Ident {
+    Ident::new(&format!("_{}", index), Span::call_site())
+}
+
+/// Returns the name of the placeholder referring to the field `ident` (the inverse of
+/// `placeholder_ident`).
+pub fn placeholder_name(ident: &Ident) -> String {
+    let name = ident.to_string();
+    let mut chars = name.chars();
+    if chars.next() == Some('_') {
+        let digits = chars.as_str();
+        match digits.parse::<usize>() {
+            Ok(index) if index.to_string() == digits => return index.to_string(),
+            _ => {}
+        }
+    }
+    name
+}
+
 fn valid_ident(s: &str) -> bool {
     if s.is_empty() || s == "_" {
         return false;
@@ -796,6 +2324,16 @@ fn valid_ident(s: &str) -> bool {
     })
 }
 
+/// Returns whether `s` is a valid HTTP token (RFC 7230, section 3.2.6).
+///
+/// Tokens are used as HTTP method and header names.
+fn valid_token(s: &str) -> bool {
+    !s.is_empty()
+        && s
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(c))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -813,6 +2351,20 @@ mod tests {
         assert!(!valid_ident("0abc"));
     }
 
+    #[test]
+    fn token() {
+        assert!(valid_token("GET"));
+        assert!(valid_token("PROPFIND"));
+        assert!(valid_token("VERSION-CONTROL"));
+        assert!(valid_token("purge"));
+        assert!(valid_token("X-Request-Id"));
+        assert!(!valid_token(""));
+        assert!(!valid_token("GET POST"));
+        assert!(!valid_token("GET/1"));
+        assert!(!valid_token("X-Request-Id:"));
+        assert!(!valid_token("MÖVE"));
+    }
+
     #[test]
     fn overlap() {
         macro_rules! intersect {
@@ -842,6 +2394,113 @@ mod tests {
         assert_eq!(intersect!("/lit/bla", "/blit/{b...}"), None);
         assert_eq!(intersect!("*", "/{b...}"), None);
         assert_eq!(intersect!("*", "/"), None);
+        assert_eq!(intersect!("/", "*"), None);
+        assert_eq!(intersect!("/{b...}", "*"), None);
         assert_eq!(intersect!("*", "*"), Some("*"));
+
+        // Regex constraints
+        assert_eq!(intersect!("/{a:[0-9]+}", "/{b:[a-z]+}"), None);
+        assert_eq!(intersect!("/{a:[0-9]+}", "/{b:[0-9a-f]+}"), Some("/a"));
+        assert_eq!(intersect!("/{a:[0-9]*}", "/{b:[a-z]*}"), Some("/a"));
+        assert_eq!(intersect!("/{a:[0-9]+}", "/{b}"), Some("/a"));
+        assert_eq!(intersect!("/{a:[0-9]+}", "/latest"), None);
+        assert_eq!(intersect!("/{a:[0-9]+}", "/123"), Some("/123"));
+        assert_eq!(intersect!("/{a:v[0-9]+}", "/{b:(?:x|y)[0-9]+}"), None);
+        assert_eq!(intersect!("/{a:[0-9]{4}}", "/{b:[a-z]+}"), None);
+        assert_eq!(intersect!("/{a:[^/]+}/x", "/{b...}"), Some("/a/x"));
+
+        // Mixed segments
+        assert_eq!(intersect!("/{id}.{fmt}", "/{x}"), Some("/id.fmt"));
+        assert_eq!(intersect!("/{x}", "/{id}.{fmt}"), Some("/id.fmt"));
+        assert_eq!(intersect!("/{id}.{fmt}", "/a.json"), Some("/a.json"));
+        assert_eq!(intersect!("/{id}.{fmt}", "/json"), None);
+        assert_eq!(intersect!("/{id}.json", "/{id}.xml"), None);
+        assert_eq!(intersect!("/{id}.json", "/{id}.{fmt}"), Some("/id.fmt"));
+        assert_eq!(intersect!("/v{v}", "/w{w}"), None);
+        assert_eq!(intersect!("/v{v}", "/{x}.json"), Some("/x.json"));
+        assert_eq!(intersect!("/v{v}", "/{x:[0-9]+}"), None);
+        assert_eq!(intersect!("/v{v}", "/{x:[a-z]+}"), Some("/vv"));
+        assert_eq!(intersect!("/v{v}/items", "/{x...}"), Some("/vv/items"));
+    }
+
+    #[test]
+    fn host_overlap() {
+        macro_rules! intersect {
+            ($a:literal, $b:literal) => {{
+                HostPattern::parse($a.to_string())
+                    .find_overlap(&HostPattern::parse($b.to_string()))
+                    .as_ref()
+                    .map(|s| s.as_str())
+            }};
+        }
+
+        assert_eq!(
+            intersect!("example.com", "example.com"),
+            Some("example.com")
+        );
+        assert_eq!(
+            intersect!("Example.COM", "example.com"),
+            Some("example.com")
+        );
+        assert_eq!(intersect!("example.com", "example.org"), None);
+        assert_eq!(intersect!("api.example.com", "example.com"), None);
+        assert_eq!(
+            intersect!("{tenant}.example.com", "api.example.com"),
+            Some("api.example.com")
+        );
+        assert_eq!(
+            intersect!("{a}.example.com", "{b}.example.com"),
+            Some("a.example.com")
+        );
+        assert_eq!(
+            intersect!("{a:[0-9]+}.example.com", "api.example.com"),
+            None
+        );
+        assert_eq!(
+            intersect!("api-{v}.example.com", "{x}.example.com"),
+            Some("api-v.example.com")
+        );
+    }
+
+    #[test]
+    fn host_regex() {
+        let host = HostPattern::parse("{tenant}.Example.com".to_string());
+        assert_eq!(host.regex(), r"^([^\.]+)\.example\.com$");
+        assert_eq!(host.placeholders, vec!["tenant"]);
+
+        let host = HostPattern::parse("{region:[a-z]{2}}.{tenant}.example.com".to_string());
+        assert_eq!(host.regex(), r"^((?:[a-z]{2}))\.([^\.]+)\.example\.com$");
+        assert_eq!(host.placeholders, vec!["region", "tenant"]);
+    }
+
+    #[test]
+    fn segments() {
+        assert_eq!(split_segments("/", '/'), vec!["", ""]);
+        assert_eq!(split_segments("/a/{b}", '/'), vec!["", "a", "{b}"]);
+        assert_eq!(
+            split_segments("/a/{b:[^/]+}/c", '/'),
+            vec!["", "a", "{b:[^/]+}", "c"]
+        );
+        assert_eq!(
+            split_segments("/{y:[0-9]{4}}/{m}", '/'),
+            vec!["", "{y:[0-9]{4}}", "{m}"]
+        );
+        assert_eq!(
+            split_segments("{a:[a-z]{2}.x}.example.com", '.'),
+            vec!["{a:[a-z]{2}.x}", "example", "com"]
+        );
+    }
+
+    #[test]
+    fn mixed_regex() {
+        let path = RoutePath::parse("/reports/{id}.{format:[a-z]+}".to_string());
+        assert_eq!(
+            path.regex.as_str(),
+            r"^/reports/([^/]+)\.((?:[a-z]+))$"
+        );
+        assert_eq!(path.placeholders, vec!["id", "format"]);
+
+        let path = RoutePath::parse("/v{version}/{{literal".to_string());
+        assert_eq!(path.regex.as_str(), r"^/v([^/]+)/\{\{literal$");
     }
 }
@@ -0,0 +1,107 @@
+//! `ToUri` derive, which builds the URI of a route value.
+
+use super::parse::{FieldKind, ItemData, VariantData};
+use super::{bind_placeholders, build_uri, field_bindings};
+use crate::utils::{option_inner_type, vec_inner_type};
+use proc_macro2::TokenStream;
+use quote::quote;
+use synstructure::{AddBounds, Structure};
+
+pub fn derive_to_uri(mut s: Structure<'_>) -> TokenStream {
+    let is_struct = match &s.ast().data {
+        syn::Data::Union(_) => panic!("#[derive(ToUri)] is not allowed on unions"),
+        syn::Data::Struct(_) => true,
+        syn::Data::Enum(_) => false,
+    };
+
+    // Only the routes are used, the rest is parsed for validation
+    let item_data = ItemData::parse(&s.ast().attrs, is_struct);
+
+    let variant_data = s
+        .variants()
+        .iter()
+        .map(|variant| VariantData::parse(&variant.ast(), &item_data, is_struct))
+        .collect::<Vec<_>>();
+
+    let name = &s.ast().ident;
+    let arms = s
+        .variants()
+        .iter()
+        .zip(&variant_data)
+        .map(|(variant, data)| {
+            let path = if is_struct {
+                quote!(#name)
+            } else {
+                let variant = variant.ast().ident;
+                quote!(#name::#variant)
+            };
+
+            // The first route of the variant is used
+            let route = data.routes().first().unwrap_or_else(|| {
+                panic!(
+                    "#[derive(ToUri)] requires a route attribute on every variant, but `{}` has none",
+                    data.variant_name()
+                )
+            });
+
+            let (mut bindings, steps) = bind_placeholders(route.placeholders(), data);
+            bindings.extend(data.query_params_field().cloned());
+            bindings.extend(
+                data.query_fields()
+                    .iter()
+                    .map(|query| query.field().ident.clone().unwrap()),
+            );
+            let bindings = field_bindings(&bindings, data);
+            let uri = build_uri(route, data);
+
+            quote! {
+                #path { #(#bindings,)* .. } => {
+                    #(#steps)*
+                    #uri
+                }
+            }
+        })
+        .collect::<Vec<_>>();
+
+    // Like for `FromRequest`, bounds are only needed when the type is generic
+    s.add_bounds(AddBounds::None);
+    let is_type_generic = s.ast().generics.type_params().next().is_some();
+    let where_clause = if is_type_generic {
+        let bounds = variant_data
+            .iter()
+            .flat_map(|v| v.field_uses())
+            .filter_map(|(field, kind)| {
+                let ty = &field.ty;
+                match kind {
+                    FieldKind::PathSegment => Some(quote!(#ty: ::std::fmt::Display)),
+                    FieldKind::Query => {
+                        let ty = option_inner_type(ty)
+                            .or_else(|| vec_inner_type(ty))
+                            .unwrap_or(ty);
+                        Some(quote!(#ty: ::std::fmt::Display))
+                    }
+                    FieldKind::PathParams | FieldKind::QueryParams => {
+                        Some(quote!(#ty: ::hyperdrive::serde::Serialize))
+                    }
+                    _ => None,
+                }
+            })
+            .collect::<Vec<_>>();
+        quote!(where #(#bounds),*)
+    } else {
+        TokenStream::new()
+    };
+
+    s.gen_impl(quote! {
+        extern crate hyperdrive;
+        use hyperdrive::{ToUri, http};
+
+        gen impl ToUri for @Self #where_clause {
+            fn to_uri(&self) -> http::Uri {
+                match self {
+                    #(#arms)*
+                }
+            }
+        }
+    })
+}
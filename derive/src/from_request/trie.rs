@@ -0,0 +1,307 @@
+//! Compiles the paths of all routes into a segment trie, which is turned into a function matching
+//! a request path against every path in a single pass.
+
+use super::parse::{PathMap, PathSegment};
+use indexmap::{IndexMap, IndexSet};
+use proc_macro2::TokenStream;
+use quote::quote;
+
+/// A trie over the segments of all paths in a `PathMap`.
+pub struct PathTrie {
+    root: Node,
+    /// Indices of the asterisk paths (`*`). There's at most one.
+    asterisk: Vec<usize>,
+    /// Regex sources of all distinct placeholder constraints.
+    constraints: IndexSet<String>,
+    /// The number of paths in the `PathMap`.
+    paths: usize,
+    /// The highest number of placeholders in any path.
+    captures: usize,
+}
+
+/// A trie node, reached after matching a number of path segments.
+///
+/// All paths passing through a node have the same number of placeholders before it, so each
+/// placeholder gets a fixed index in the array of captures.
+#[derive(Default)]
+struct Node {
+    /// Indices of the paths ending at this node.
+    ends: Vec<usize>,
+    /// Indices of the paths ending with a `{rest...}` placeholder that starts at this node.
+    rest: Vec<usize>,
+    /// Child nodes reached by a literal segment.
+    literals: IndexMap<String, Node>,
+    /// Child nodes reached by a segment containing placeholders.
+    patterns: Vec<(Pattern, Node)>,
+}
+
+/// A path segment containing placeholders.
+#[derive(PartialEq)]
+enum Pattern {
+    /// `{ident}` or `{ident:regex}`, with the index of its constraint.
+    Placeholder(Option<usize>),
+    /// Literal text mixed with placeholders.
+    Mixed(Vec<Part>),
+}
+
+#[derive(PartialEq)]
+enum Part {
+    Literal(String),
+    Placeholder(Option<usize>),
+}
+
+impl Pattern {
+    /// Returns the number of placeholders captured by this pattern.
+    fn captures(&self) -> usize {
+        match self {
+            Pattern::Placeholder(_) => 1,
+            Pattern::Mixed(parts) => parts
+                .iter()
+                .filter(|part| match part {
+                    Part::Placeholder(_) => true,
+                    Part::Literal(_) => false,
+                })
+                .count(),
+        }
+    }
+}
+
+impl PathTrie {
+    pub fn build(pathmap: &PathMap) -> Self {
+        let mut this = Self {
+            root: Node::default(),
+            asterisk: Vec::new(),
+            constraints: IndexSet::new(),
+            paths: 0,
+            captures: 0,
+        };
+
+        'paths: for (i, path) in pathmap.paths().enumerate() {
+            this.paths += 1;
+            let segments = match path.segments() {
+                Some(segments) => segments,
+                None => {
+                    this.asterisk.push(i);
+                    continue;
+                }
+            };
+
+            let constraints = &mut this.constraints;
+            let mut constraint_index = |segment: &PathSegment| match segment {
+                PathSegment::Placeholder(_, constraint) => constraint
+                    .as_ref()
+                    .map(|c| constraints.insert_full(c.source().to_string()).0),
+                _ => unreachable!("constraint of non-placeholder segment"),
+            };
+
+            let mut node = &mut this.root;
+            let mut captures = 0;
+            for segment in segments {
+                let pattern = match segment {
+                    PathSegment::Literal(lit) => {
+                        node = node.literals.entry(lit.clone()).or_default();
+                        continue;
+                    }
+                    PathSegment::Rest(_) => {
+                        // Always the last segment
+                        node.rest.push(i);
+                        this.captures = this.captures.max(captures + 1);
+                        continue 'paths;
+                    }
+                    PathSegment::Placeholder(..) => Pattern::Placeholder(constraint_index(segment)),
+                    PathSegment::Mixed(parts) => Pattern::Mixed(
+                        parts
+                            .iter()
+                            .map(|part| match part {
+                                PathSegment::Literal(lit) => Part::Literal(lit.clone()),
+                                _ => Part::Placeholder(constraint_index(part)),
+                            })
+                            .collect(),
+                    ),
+                };
+
+                captures += pattern.captures();
+                let pos = match node.patterns.iter().position(|(p, _)| *p == pattern) {
+                    Some(pos) => pos,
+                    None => {
+                        node.patterns.push((pattern, Node::default()));
+                        node.patterns.len() - 1
+                    }
+                };
+                node = &mut node.patterns[pos].1;
+            }
+
+            node.ends.push(i);
+            this.captures = this.captures.max(captures);
+        }
+
+        this
+    }
+
+    /// Generates the `match_path` function.
+    ///
+    /// It takes the request path and returns an array containing an entry for every path in the
+    /// `PathMap`, which is `Some` if that path matches. The entry contains the path placeholders
+    /// in order of appearance, followed by unused empty strings.
+    pub fn generate(&self) -> TokenStream {
+        let (paths, captures) = (self.paths, self.captures);
+
+        let constraints = if self.constraints.is_empty() {
+            quote!()
+        } else {
+            let regexes = self
+                .constraints
+                .iter()
+                .map(|source| format!("^(?:{})$", source));
+            quote! {
+                lazy_static! {
+                    static ref CONSTRAINTS: Vec<Regex> = vec![
+                        #(
+                            Regex::new(#regexes)
+                                .expect("invalid regex constraint from FromRequest derive"),
+                        )*
+                    ];
+                }
+            }
+        };
+
+        let asterisk = &self.asterisk;
+        let root = if self.root.is_empty() {
+            // Only the asterisk path
+            quote!()
+        } else {
+            let root = self.root.generate(0);
+            quote! {
+                else if path.get(..1) == Some("/") {
+                    let rest = path.get(1..);
+                    #root
+                }
+            }
+        };
+
+        quote! {
+            fn match_path(path: &str) -> [Option<[&str; #captures]>; #paths] {
+                #constraints
+
+                let mut matches = [None; #paths];
+                let caps = [""; #captures];
+                if path == "*" {
+                    #( matches[#asterisk] = Some(caps); )*
+                } #root
+                matches
+            }
+        }
+    }
+}
+
+impl Node {
+    fn is_empty(&self) -> bool {
+        self.ends.is_empty()
+            && self.rest.is_empty()
+            && self.literals.is_empty()
+            && self.patterns.is_empty()
+    }
+
+    /// Generates the code matching the rest of the path against the paths below this node.
+    ///
+    /// The code has access to `matches`, the array of `match_path` results, `caps`, the values of
+    /// all `index` placeholders seen so far, and `rest`, the part of the path after the `/`
+    /// following the last matched segment (`None` if the whole path was matched).
+    fn generate(&self, index: usize) -> TokenStream {
+        let ends = if self.ends.is_empty() {
+            quote!()
+        } else {
+            let ends = &self.ends;
+            quote! {
+                if rest.is_none() {
+                    #( matches[#ends] = Some(caps); )*
+                }
+            }
+        };
+
+        if self.rest.is_empty() && self.literals.is_empty() && self.patterns.is_empty() {
+            return ends;
+        }
+
+        let rest = self.rest.iter().map(|i| {
+            quote! {{
+                let mut caps = caps;
+                caps[#index] = rest;
+                matches[#i] = Some(caps);
+            }}
+        });
+
+        let next_segment = if self.literals.is_empty() && self.patterns.is_empty() {
+            quote!()
+        } else {
+            quote! {
+                let (segment, rest) = match rest.find('/') {
+                    Some(pos) => (&rest[..pos], Some(&rest[pos + 1..])),
+                    None => (rest, None),
+                };
+            }
+        };
+
+        let literals = if self.literals.is_empty() {
+            quote!()
+        } else {
+            let (literals, nodes): (Vec<_>, Vec<_>) = self
+                .literals
+                .iter()
+                .map(|(lit, node)| (lit, node.generate(index)))
+                .unzip();
+            quote! {
+                match segment {
+                    #( #literals => { #nodes } )*
+                    _ => {}
+                }
+            }
+        };
+
+        let patterns = self.patterns.iter().map(|(pattern, node)| {
+            let next = node.generate(index + pattern.captures());
+            match pattern {
+                Pattern::Placeholder(None) => quote! {
+                    if !segment.is_empty() {
+                        let mut caps = caps;
+                        caps[#index] = segment;
+                        #next
+                    }
+                },
+                Pattern::Placeholder(Some(constraint)) => quote! {
+                    if CONSTRAINTS[#constraint].is_match(segment) {
+                        let mut caps = caps;
+                        caps[#index] = segment;
+                        #next
+                    }
+                },
+                Pattern::Mixed(parts) => {
+                    let parts = parts.iter().map(|part| match part {
+                        Part::Literal(lit) => quote!(SegmentPart::Literal(#lit)),
+                        Part::Placeholder(None) => quote!(SegmentPart::Placeholder(None)),
+                        Part::Placeholder(Some(constraint)) => {
+                            quote!(SegmentPart::Placeholder(Some(&CONSTRAINTS[#constraint])))
+                        }
+                    });
+                    let end = index + pattern.captures();
+                    quote! {{
+                        let mut caps = caps;
+                        if match_segment(segment, &[#(#parts),*], &mut caps[#index..#end]) {
+                            #next
+                        }
+                    }}
+                }
+            }
+        });
+
+        quote! {
+            #ends
+            if let Some(rest) = rest {
+                #(#rest)*
+                #next_segment
+                #literals
+                #(#patterns)*
+            }
+        }
+    }
+}
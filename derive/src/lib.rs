@@ -10,23 +10,50 @@
 
 use synstructure::decl_derive;
 
+/// Invokes `$mac!` with the attributes shared by `FromRequest`, `IntoRequest`, `ToUri` and
+/// `Describe` (since they're usually derived together), appended as a bracketed list to the given arguments.
+///
+/// The list needs to be kept in sync with `our_attrs` in from_request/parse.rs (checked by the
+/// `route_attrs_in_sync` test).
+macro_rules! with_route_attrs {
+    ($mac:ident!($($args:tt)*)) => {
+        $mac! { $($args)* [
+            context, routing, host, body, forward, query_params, query, path_params, header, cookie,
+
+            // We support all HTTP verbs from RFC 7231 as well as PATCH
+            get, head, post, put, delete, connect, options, trace, patch,
+
+            // Arbitrary HTTP verbs (eg. for WebDAV) can be used via `#[route]`
+            route
+        ] }
+    };
+}
+
+/// Declares a derive accepting the route attributes passed by `with_route_attrs!`.
+macro_rules! route_derive {
+    ($derive:ident => $func:ident [$($attr:ident),*]) => {
+        decl_derive!([$derive, attributes($($attr),*)] => $func);
+    };
+}
+
 mod from_request;
 mod request_context;
 mod utils;
 
-use from_request::derive_from_request;
+use from_request::{derive_from_request, derive_into_request, derive_to_uri};
 use request_context::derive_request_context;
 
-decl_derive!([FromRequest, attributes(
-    // Attributes need to be kept in sync with from_request/parse.rs
+with_route_attrs!(route_derive!(FromRequest => derive_from_request));
+
+#[cfg(feature = "openapi")]
+use from_request::derive_describe;
 
-    context, body, forward, query_params,
+#[cfg(feature = "openapi")]
+with_route_attrs!(route_derive!(Describe => derive_describe));
 
-    // We support all HTTP verbs from RFC 7231 as well as PATCH
-    get, head, post, put, delete, connect, options, trace, patch
+with_route_attrs!(route_derive!(IntoRequest => derive_into_request));
 
-    // FIXME support arbitrary HTTP verbs (eg. for WebDAV)
-)] => derive_from_request);
+with_route_attrs!(route_derive!(ToUri => derive_to_uri));
 
 decl_derive!([RequestContext, attributes(
     as_ref
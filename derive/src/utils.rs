@@ -1,4 +1,5 @@
 use std::hash::{Hash, Hasher};
+use syn::{GenericArgument, PathArguments, Type};
 
 /// Stores an object of type `T` and implements traits by calling a function
 /// returning a proxy `H`.
@@ -46,3 +47,47 @@ impl<T, H: PartialEq + ?Sized> PartialEq for ByProxy<T, H> {
 }
 
 impl<T, H: Eq + ?Sized> Eq for ByProxy<T, H> {}
+
+/// If `ty` is an `Option<T>`, returns `T`.
+///
+/// This works on a purely syntactical level, so type aliases or renamed imports
+/// of `Option` are not detected.
+pub fn option_inner_type(ty: &Type) -> Option<&Type> {
+    wrapped_type(ty, "Option")
+}
+
+/// If `ty` is a `Vec<T>`, returns `T`.
+///
+/// Like `option_inner_type`, this only looks at the last path segment.
+pub fn vec_inner_type(ty: &Type) -> Option<&Type> {
+    wrapped_type(ty, "Vec")
+}
+
+/// If `ty` is a `hyperdrive::Raw<T>`, returns `T`.
+///
+/// Like `option_inner_type`, this only looks at the last path segment.
+pub fn raw_inner_type(ty: &Type) -> Option<&Type> {
+    wrapped_type(ty, "Raw")
+}
+
+/// If `ty` is a path to a type named `wrapper` with a single type argument,
+/// returns that argument.
+fn wrapped_type<'a>(ty: &'a Type, wrapper: &str) -> Option<&'a Type> {
+    let path = match ty {
+        Type::Path(path) if path.qself.is_none() => &path.path,
+        _ => return None,
+    };
+
+    let last = path.segments.iter().last()?;
+    if last.ident != wrapper {
+        return None;
+    }
+
+    match &last.arguments {
+        PathArguments::AngleBracketed(args) if args.args.len() == 1 => match &args.args[0] {
+            GenericArgument::Type(inner) => Some(inner),
+            _ => None,
+        },
+        _ => None,
+    }
+}
@@ -1,6 +1,8 @@
 //! Provides wrappers that deserialize a request body.
 //!
-//! All wrappers provided here implement [`FromBody`].
+//! All wrappers provided here implement [`FromBody`]. They also implement
+//! [`IntoBody`] when the wrapped type can be serialized, which is used by
+//! `#[derive(IntoRequest)]`.
 //!
 //! Note that the wrapper types will not inspect the `Content-Type` header and
 //! instead assume that the body has the right format. You can add a [`Guard`]
@@ -10,15 +12,17 @@
 //! limit the maximum request size, you can do that in a [`Guard`] as well.
 //!
 //! [`FromBody`]: ../trait.FromBody.html
+//! [`IntoBody`]: trait.IntoBody.html
 //! [`Guard`]: ../trait.Guard.html
 
 // TODO: Add many more types here and make them optional
 
-use crate::{BoxedError, DefaultFuture, FromBody, NoContext};
-use futures::{Future, Stream};
-use serde::de::DeserializeOwned;
+use crate::{BoxedError, DefaultFuture, Error, FromBody, NoContext};
+use futures::{try_ready, Async, Future, Poll, Stream};
+use http::StatusCode;
+use serde::{de::DeserializeOwned, Serialize};
 use std::ops::{Deref, DerefMut};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
 macro_rules! deref {
     ($t:ty) => {
@@ -38,12 +42,113 @@ macro_rules! deref {
     };
 }
 
+/// A request body that is read into memory when it is first polled, and can
+/// then be replayed any number of times.
+///
+/// Used by `#[body(buffer_limit = N)]`. Since the body is only read once a
+/// `#[body]` or `#[forward]` field polls it, a forwarded-to type that rejects
+/// the request without reading the body leaves it untouched. Bodies larger
+/// than the limit fail to read, and [`BufferedBody::error`] turns the
+/// resulting error into `413 Payload Too Large`.
+///
+/// [`BufferedBody::error`]: #method.error
+#[doc(hidden)]
+#[derive(Debug, Clone)]
+pub struct BufferedBody {
+    buffer: Arc<Mutex<Buffer>>,
+}
+
+#[derive(Debug)]
+struct Buffer {
+    /// The body still being read, `None` once it is complete (or too large).
+    body: Option<hyper::Body>,
+    bytes: Vec<u8>,
+    limit: u64,
+    too_large: bool,
+}
+
+impl BufferedBody {
+    pub fn new(body: hyper::Body, limit: u64) -> Self {
+        Self {
+            buffer: Arc::new(Mutex::new(Buffer {
+                body: Some(body),
+                bytes: Vec::new(),
+                limit,
+                too_large: false,
+            })),
+        }
+    }
+
+    /// Returns a body yielding the buffered request body, reading it first if
+    /// that hasn't happened yet.
+    pub fn replay(&self) -> hyper::Body {
+        hyper::Body::wrap_stream(Replay {
+            buffer: Arc::clone(&self.buffer),
+            done: false,
+        })
+    }
+
+    /// Returns the error to respond with after a replayed body was read and
+    /// the request failed with `error`.
+    ///
+    /// This is `413 Payload Too Large` if the body exceeded the limit, and
+    /// `error` otherwise.
+    pub fn error(&self, error: BoxedError) -> BoxedError {
+        if self.buffer.lock().unwrap().too_large {
+            Box::new(Error::from_status(StatusCode::PAYLOAD_TOO_LARGE))
+        } else {
+            error
+        }
+    }
+}
+
+/// A body replayed from a `BufferedBody`.
+struct Replay {
+    buffer: Arc<Mutex<Buffer>>,
+    done: bool,
+}
+
+impl Stream for Replay {
+    type Item = hyper::Chunk;
+    type Error = BoxedError;
+
+    fn poll(&mut self) -> Poll<Option<hyper::Chunk>, BoxedError> {
+        if self.done {
+            return Ok(Async::Ready(None));
+        }
+
+        let mut buffer = self.buffer.lock().unwrap();
+        while let Some(body) = &mut buffer.body {
+            match try_ready!(body.poll()) {
+                Some(chunk) => {
+                    if (buffer.bytes.len() + chunk.len()) as u64 > buffer.limit {
+                        buffer.body = None;
+                        buffer.too_large = true;
+                    } else {
+                        buffer.bytes.extend_from_slice(&chunk);
+                    }
+                }
+                None => buffer.body = None,
+            }
+        }
+
+        if buffer.too_large {
+            return Err(Error::from_status(StatusCode::PAYLOAD_TOO_LARGE).into());
+        }
+
+        self.done = true;
+        Ok(Async::Ready(Some(buffer.bytes.clone().into())))
+    }
+}
+
 /// Decodes an `x-www-form-urlencoded` request body (eg. sent by an HTML form).
 ///
 /// This uses [`serde_urlencoded`] to deserialize the request body.
-/// The `Content-Type` and `Content-Length` headers are ignored.
+/// The `Content-Type` and `Content-Length` headers are ignored. Use
+/// [`NestedForm`] for forms with repeated or nested parameters.
 ///
 /// [`serde_urlencoded`]: https://github.com/nox/serde_urlencoded
+/// [`NestedForm`]: struct.NestedForm.html
 ///
 /// # Examples
 ///
@@ -113,8 +218,105 @@ impl<T: DeserializeOwned + Send + 'static> FromBody for HtmlForm<T> {
     }
 }
 
+impl<T: DeserializeOwned + Serialize + Send + 'static> IntoBody for HtmlForm<T> {
+    fn into_body(self) -> (&'static str, hyper::Body) {
+        let body = serde_urlencoded::to_string(&self.0)
+            .unwrap_or_else(|e| panic!("couldn't serialize form data: {}", e));
+        ("application/x-www-form-urlencoded", body.into())
+    }
+}
+
 deref!(HtmlForm<T>);
 
+/// Decodes an `x-www-form-urlencoded` request body containing repeated or
+/// nested parameters.
+///
+/// Unlike [`HtmlForm`], this supports the encoding used by most frontend
+/// libraries for sequences and nested structures:
+///
+/// * Repeated parameters (`ids=1&ids=2` or `ids[]=1&ids[]=2`) are decoded as
+///   sequences, such as `Vec`s.
+/// * Parameters with bracketed names (`filter[status]=open`) are decoded as
+///   nested structs or maps. Sequences of structs use indices, as in
+///   `items[0][name]=a&items[1][name]=b`. Names may be nested at most 32
+///   levels deep.
+///
+/// Empty sequences and maps are left out when encoding the form, so fields of
+/// these types should use `#[serde(default)]`.
+///
+/// Values are converted using `FromStr`. If the body can not be decoded, a
+/// `400 Bad Request` error is returned, whose source names the offending
+/// parameter. The `Content-Type` and `Content-Length` headers are ignored.
+///
+/// [`HtmlForm`]: struct.HtmlForm.html
+///
+/// # Examples
+///
+/// ```
+/// # use hyperdrive::{FromRequest, body::NestedForm, serde::Deserialize, NoContext};
+/// #[derive(Deserialize)]
+/// struct Order {
+///     ids: Vec<u32>,
+///     shipping: Address,
+/// }
+///
+/// #[derive(Deserialize)]
+/// struct Address {
+///     city: String,
+///     zip: String,
+/// }
+///
+/// #[derive(FromRequest)]
+/// enum Route {
+///     #[post("/orders")]
+///     Order {
+///         #[body]
+///         order: NestedForm<Order>,
+///     },
+/// }
+///
+/// let data = "ids=1&ids=2&shipping[city]=Berlin&shipping[zip]=10115";
+///
+/// let Route::Order { order: NestedForm(order) } = Route::from_request_sync(
+///     http::Request::post("/orders").body(data.into()).unwrap(),
+///     NoContext,
+/// ).unwrap();
+///
+/// assert_eq!(order.ids, vec![1, 2]);
+/// assert_eq!(order.shipping.city, "Berlin");
+/// ```
+#[derive(Debug, PartialEq, Eq)]
+pub struct NestedForm<T: DeserializeOwned + Send + 'static>(pub T);
+
+impl<T: DeserializeOwned + Send + 'static> FromBody for NestedForm<T> {
+    type Context = NoContext;
+
+    type Result = DefaultFuture<Self, BoxedError>;
+
+    fn from_body(
+        _request: &Arc<http::Request<()>>,
+        body: hyper::Body,
+        _context: &Self::Context,
+    ) -> Self::Result {
+        Box::new(body.concat2().map_err(Into::into).and_then(|body| {
+            match crate::decode_nested(&body) {
+                Ok(t) => Ok(NestedForm(t)),
+                Err(e) => Err(Error::with_source(StatusCode::BAD_REQUEST, e).into()),
+            }
+        }))
+    }
+}
+
+impl<T: DeserializeOwned + Serialize + Send + 'static> IntoBody for NestedForm<T> {
+    fn into_body(self) -> (&'static str, hyper::Body) {
+        let body = crate::encode_nested(&self.0)
+            .unwrap_or_else(|e| panic!("couldn't serialize form data: {}", e));
+        ("application/x-www-form-urlencoded", body.into())
+    }
+}
+
+deref!(NestedForm<T>);
+
 /// Decodes a JSON-encoded request body.
 ///
 /// The [`FromBody`] implementation of this type will retrieve the request body
@@ -187,4 +389,29 @@ impl<T: DeserializeOwned + Send + 'static> FromBody for Json<T> {
     }
 }
 
+impl<T: DeserializeOwned + Serialize + Send + 'static> IntoBody for Json<T> {
+    fn into_body(self) -> (&'static str, hyper::Body) {
+        let body = serde_json::to_vec(&self.0)
+            .unwrap_or_else(|e| panic!("couldn't serialize JSON body: {}", e));
+        ("application/json", body.into())
+    }
+}
+
 deref!(Json<T>);
+
+/// Trait for `#[body]` types that can be turned back into a request body.
+///
+/// This is the inverse of [`FromBody`], and is used by the code generated by
+/// `#[derive(IntoRequest)]`.
+///
+/// [`FromBody`]: ../trait.FromBody.html
+pub trait IntoBody {
+    /// Encodes `self`, returning the value of the `Content-Type` header and the
+    /// request body.
+    ///
+    /// # Panics
+    ///
+    /// Implementations may panic if `self` can not be encoded (for example,
+    /// because it can't be represented in the body's format).
+    fn into_body(self) -> (&'static str, hyper::Body);
+}
@@ -8,35 +8,82 @@ use std::{borrow::Cow, error, fmt};
 /// This type can be turned into an HTTP response by calling [`Error::response`]
 /// and then sent back to the client.
 ///
+/// Most errors describe a request that failed with a client or server error
+/// status. Some are not failures at all, but answer the request directly: a
+/// redirect to the canonical path (see [`Error::redirect`]) and the automatic
+/// response to an `OPTIONS` request (see [`Error::options`]). These should be
+/// sent to the client unchanged instead of being handled (eg. logged) like a
+/// failure. Use [`Error::is_response`] to tell them apart.
+///
 /// [`Error::response`]: #method.response
+/// [`Error::redirect`]: #method.redirect
+/// [`Error::options`]: #method.options
+/// [`Error::is_response`]: #method.is_response
 #[derive(Debug)]
 pub struct Error {
     status: StatusCode,
-    /// In case of a `405 Method Not Allowed` error, stores the allowed HTTP
-    /// methods.
-    allowed_methods: Cow<'static, [&'static http::Method]>,
+    kind: Kind,
     source: Option<BoxedError>,
 }
 
+/// Distinguishes the responses that carry more data than just their status.
+#[derive(Debug)]
+enum Kind {
+    /// Any other response, consisting of just the status code.
+    Plain,
+    /// A `405 Method Not Allowed` error, listing the allowed HTTP methods.
+    WrongMethod(Cow<'static, [&'static http::Method]>),
+    /// A `204 No Content` response to an `OPTIONS` request, listing the
+    /// allowed HTTP methods.
+    Options(Cow<'static, [&'static http::Method]>),
+    /// A `308 Permanent Redirect` to the stored URI.
+    Redirect(Box<http::Uri>),
+}
+
+impl Kind {
+    /// Returns whether this kind answers the request directly instead of
+    /// describing a failure.
+    fn is_response(&self) -> bool {
+        match self {
+            Kind::Options(_) | Kind::Redirect(_) => true,
+            Kind::Plain | Kind::WrongMethod(_) => false,
+        }
+    }
+}
+
 impl Error {
-    fn new(
-        status: StatusCode,
-        allowed_methods: Cow<'static, [&'static http::Method]>,
-        source: Option<BoxedError>,
-    ) -> Self {
+    fn new(status: StatusCode, kind: Kind, source: Option<BoxedError>) -> Self {
         assert!(
             status.is_client_error() || status.is_server_error(),
             "hyperdrive::Error must be created with an error status, not {}",
             status,
         );
+        debug_assert!(!kind.is_response());
 
         Self {
             status,
-            allowed_methods,
+            kind,
             source,
         }
     }
 
+    /// Creates an `Error` that answers the request directly with a successful
+    /// or redirection `status`.
+    fn answer(status: StatusCode, kind: Kind) -> Self {
+        assert!(
+            status.is_success() || status.is_redirection(),
+            "a direct answer must have a success or redirection status, not {}",
+            status,
+        );
+        debug_assert!(kind.is_response());
+
+        Self {
+            status,
+            kind,
+            source: None,
+        }
+    }
+
     /// Creates an error that contains just the given `StatusCode`.
     ///
     /// # Panics
@@ -44,7 +91,7 @@ impl Error {
     /// This will panic when called with a `status` that does not indicate a
     /// client or server error.
     pub fn from_status(status: StatusCode) -> Self {
-        Self::new(status, (&[][..]).into(), None)
+        Self::new(status, Kind::Plain, None)
     }
 
     /// Creates an error from an HTTP error code and an underlying error that
@@ -93,7 +140,7 @@ impl Error {
     where
         S: Into<BoxedError>,
     {
-        Self::new(status, (&[][..]).into(), Some(source.into()))
+        Self::new(status, Kind::Plain, Some(source.into()))
     }
 
     /// Creates an error with status code `405 Method Not Allowed` and includes
@@ -118,14 +165,89 @@ impl Error {
     where
         M: Into<Cow<'static, [&'static http::Method]>>,
     {
-        Self::new(StatusCode::METHOD_NOT_ALLOWED, allowed_methods.into(), None)
+        Self::new(
+            StatusCode::METHOD_NOT_ALLOWED,
+            Kind::WrongMethod(allowed_methods.into()),
+            None,
+        )
+    }
+
+    /// Creates a `308 Permanent Redirect` to `location`.
+    ///
+    /// This isn't an error in the HTTP sense, but is used by the code
+    /// generated by `#[derive(FromRequest)]` to redirect clients to the
+    /// canonical form of the request path, when
+    /// `#[routing(trailing_slash = "redirect")]` is used.
+    ///
+    /// Calling `Error::response` on the returned error will include a
+    /// `Location` header. [`Error::is_response`] returns `true` for it.
+    ///
+    /// [`Error::is_response`]: #method.is_response
+    pub fn redirect(location: http::Uri) -> Self {
+        Self::answer(
+            StatusCode::PERMANENT_REDIRECT,
+            Kind::Redirect(Box::new(location)),
+        )
+    }
+
+    /// Creates a `204 No Content` response to an `OPTIONS` request, listing the
+    /// allowed set of HTTP methods.
+    ///
+    /// Like [`Error::redirect`], this isn't an error in the HTTP sense. It is
+    /// used by the code generated by `#[derive(FromRequest)]` to answer
+    /// `OPTIONS` requests to paths that have no explicit `OPTIONS` route.
+    ///
+    /// Calling `Error::response` on the returned error will include an `Allow`
+    /// header listing all allowed methods. [`Error::is_response`] returns
+    /// `true` for it.
+    ///
+    /// [`Error::redirect`]: #method.redirect
+    /// [`Error::is_response`]: #method.is_response
+    pub fn options<M>(allowed_methods: M) -> Self
+    where
+        M: Into<Cow<'static, [&'static http::Method]>>,
+    {
+        Self::answer(
+            StatusCode::NO_CONTENT,
+            Kind::Options(allowed_methods.into()),
+        )
     }
 
     /// Returns the HTTP status code that describes this error.
+    ///
+    /// This is a client or server error status, unless [`Error::is_response`]
+    /// returns `true`.
+    ///
+    /// [`Error::is_response`]: #method.is_response
     pub fn http_status(&self) -> StatusCode {
         self.status
     }
 
+    /// Returns whether `self` answers the request directly instead of
+    /// describing a failure.
+    ///
+    /// This is the case for redirects created by [`Error::redirect`] and
+    /// responses to `OPTIONS` requests created by [`Error::options`]. Their
+    /// [`response`] should be sent to the client as-is, and they should not be
+    /// reported as errors.
+    ///
+    /// [`Error::redirect`]: #method.redirect
+    /// [`Error::options`]: #method.options
+    /// [`response`]: #method.response
+    pub fn is_response(&self) -> bool {
+        self.kind.is_response()
+    }
+
+    /// Returns whether `self` describes a failed request, ie. has a client or
+    /// server error status.
+    ///
+    /// This is the opposite of [`Error::is_response`].
+    ///
+    /// [`Error::is_response`]: #method.is_response
+    pub fn is_error(&self) -> bool {
+        !self.is_response()
+    }
+
     /// Returns the original cause of this error.
     ///
     /// If no source was provided when `self` was created, returns `None`.
@@ -176,11 +298,11 @@ impl Error {
         let mut builder = http::Response::builder();
         builder.status(self.http_status());
 
-        if self.status == StatusCode::METHOD_NOT_ALLOWED {
+        if let Some(allowed_methods) = self.allowed_methods() {
             // The spec mandates that "405 Method Not Allowed" always sends an
-            // `Allow` header (it may be empty, though).
-            let allowed = self
-                .allowed_methods
+            // `Allow` header (it may be empty, though). Responses to `OPTIONS`
+            // requests use it to list the supported methods.
+            let allowed = allowed_methods
                 .iter()
                 .map(|method| method.as_str())
                 .collect::<Vec<_>>()
@@ -188,6 +310,10 @@ impl Error {
             builder.header(http::header::ALLOW, allowed);
         }
 
+        if let Kind::Redirect(location) = &self.kind {
+            builder.header(http::header::LOCATION, location.to_string());
+        }
+
         builder
             .body(())
             .expect("could not build HTTP response for error")
@@ -202,15 +328,30 @@ impl Error {
         Box::new(Err(BoxedError::from(self)).into_future())
     }
 
-    /// If `self` is a `405 Method Not Allowed` error, returns the list of
+    /// If `self` is a redirect created by [`Error::redirect`], returns the
+    /// URI to redirect to.
+    ///
+    /// [`Error::redirect`]: #method.redirect
+    pub fn location(&self) -> Option<&http::Uri> {
+        match &self.kind {
+            Kind::Redirect(location) => Some(location),
+            _ => None,
+        }
+    }
+
+    /// If `self` is a `405 Method Not Allowed` error, or a response to an
+    /// `OPTIONS` request created by [`Error::options`], returns the list of
     /// allowed methods.
     ///
     /// Returns `None` if `self` is a different kind of error.
+    ///
+    /// [`Error::options`]: #method.options
     pub fn allowed_methods(&self) -> Option<&[&'static http::Method]> {
-        if self.status == StatusCode::METHOD_NOT_ALLOWED {
-            Some(&self.allowed_methods)
-        } else {
-            None
+        match &self.kind {
+            Kind::WrongMethod(methods) | Kind::Options(methods) => Some(methods),
+            // A 405 created via `from_status` still has to send an `Allow` header
+            Kind::Plain if self.status == StatusCode::METHOD_NOT_ALLOWED => Some(&[]),
+            _ => None,
         }
     }
 }
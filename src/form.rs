@@ -0,0 +1,720 @@
+//! Decoding and encoding of `x-www-form-urlencoded` data with repeated and
+//! nested parameters.
+//!
+//! `serde_urlencoded` only supports flat structures, so this module implements
+//! the conventions used by most frontend libraries on top of it:
+//!
+//! * Repeated parameters (`ids=1&ids=2`, or `ids[]=1&ids[]=2`) are decoded as
+//!   sequences.
+//! * Bracketed names (`filter[status]=open`) are decoded as nested structs or
+//!   maps. Sequences can also be written with indices (`ids[0]=1&ids[1]=2`),
+//!   which is required for sequences of structs.
+//!
+//! Empty sequences and maps can't be represented in this format, so they are
+//! left out when encoding. Fields of these types need `#[serde(default)]` to
+//! decode them again.
+//!
+//! This is used by `#[query_params(nested)]`, [`body::NestedForm`] and
+//! `#[path_params]`.
+//!
+//! [`body::NestedForm`]: ../body/struct.NestedForm.html
+
+use serde::de::value::{MapDeserializer, SeqDeserializer};
+use serde::de::{self, DeserializeOwned, IntoDeserializer, Visitor};
+use serde::{forward_to_deserialize_any, Serialize};
+use serde_json::Value;
+use std::fmt;
+use std::str::FromStr;
+
+/// Decodes nested `x-www-form-urlencoded` data.
+#[doc(hidden)]
+pub fn decode_nested<T: DeserializeOwned>(input: &[u8]) -> Result<T, FormError> {
+    let pairs: Vec<(String, String)> =
+        serde_urlencoded::from_bytes(input).map_err(|e| FormError::new(e.to_string()))?;
+
+    let mut root = Node::Values(Vec::new());
+    for (name, value) in pairs {
+        let keys = parse_name(&name)?;
+        root.insert(&name, &keys, value)?;
+    }
+
+    T::deserialize(NodeDeserializer {
+        path: String::new(),
+        node: root,
+    })
+}
+
+/// Decodes a list of parameters with a single value each, without nesting.
+///
+/// This is used to deserialize the path placeholders of a route into a
+/// `#[path_params]` field.
+pub fn decode_values<T: DeserializeOwned>(values: Vec<(String, String)>) -> Result<T, FormError> {
+    let entries = values
+        .into_iter()
+        .map(|(name, value)| (name, Node::Values(vec![value])))
+        .collect();
+
+    T::deserialize(NodeDeserializer {
+        path: String::new(),
+        node: Node::Map(entries),
+    })
+}
+
+/// Encodes `value` as nested `x-www-form-urlencoded` data.
+///
+/// This is the inverse of `decode_nested`. Sequences of plain values are
+/// encoded as repeated parameters, all other nested values use bracketed
+/// names. `None` values and empty sequences and maps are left out, and map and
+/// struct entries are sorted by name.
+#[doc(hidden)]
+pub fn encode_nested<T: Serialize + ?Sized>(value: &T) -> Result<String, FormError> {
+    let value = serde_json::to_value(value).map_err(|e| FormError::new(e.to_string()))?;
+    let mut pairs = Vec::new();
+    match &value {
+        Value::Null => {}
+        Value::Object(map) => {
+            for (key, value) in map {
+                flatten(key.clone(), value, &mut pairs);
+            }
+        }
+        _ => {
+            return Err(FormError::new(
+                "only structs and maps can be encoded as form data",
+            ))
+        }
+    }
+
+    serde_urlencoded::to_string(pairs).map_err(|e| FormError::new(e.to_string()))
+}
+
+/// Returns the value of the placeholder `name` stored in a `#[path_params]`
+/// field, for use in a URI.
+///
+/// # Panics
+///
+/// This will panic if `value` doesn't serialize to a struct or map with a
+/// plain value (a string, number or boolean) for `name`.
+#[doc(hidden)]
+pub fn encode_path_param<T: Serialize + ?Sized>(value: &T, name: &str) -> String {
+    let value = serde_json::to_value(value)
+        .unwrap_or_else(|e| panic!("couldn't serialize path parameters: {}", e));
+    match value.get(name) {
+        Some(Value::String(s)) => s.clone(),
+        Some(Value::Number(n)) => n.to_string(),
+        Some(Value::Bool(b)) => b.to_string(),
+        _ => panic!("path parameters have no plain value for placeholder `{}`", name),
+    }
+}
+
+fn flatten(name: String, value: &Value, pairs: &mut Vec<(String, String)>) {
+    match value {
+        Value::Null => {}
+        Value::Bool(b) => pairs.push((name, b.to_string())),
+        Value::Number(n) => pairs.push((name, n.to_string())),
+        Value::String(s) => pairs.push((name, s.clone())),
+        Value::Array(items) => {
+            let plain = items
+                .iter()
+                .all(|item| !item.is_array() && !item.is_object());
+            for (i, item) in items.iter().enumerate() {
+                if plain {
+                    flatten(name.clone(), item, pairs);
+                } else {
+                    flatten(format!("{}[{}]", name, i), item, pairs);
+                }
+            }
+        }
+        Value::Object(map) => {
+            for (key, value) in map {
+                flatten(format!("{}[{}]", name, key), value, pairs);
+            }
+        }
+    }
+}
+
+/// Error returned when nested form data can not be decoded.
+///
+/// The message names the offending parameter, if there is one.
+#[derive(Debug)]
+pub struct FormError {
+    message: String,
+    /// The full name of the parameter the error occurred in.
+    path: Option<String>,
+}
+
+impl FormError {
+    fn new<S: Into<String>>(message: S) -> Self {
+        Self {
+            message: message.into(),
+            path: None,
+        }
+    }
+
+    /// Attributes the error to the parameter `path`, unless it was already
+    /// attributed to a more deeply nested one.
+    fn at(mut self, path: &str) -> Self {
+        if self.path.is_none() && !path.is_empty() {
+            self.path = Some(path.to_string());
+        }
+        self
+    }
+}
+
+impl fmt::Display for FormError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.path {
+            Some(path) => write!(f, "invalid parameter `{}`: {}", path, self.message),
+            None => f.write_str(&self.message),
+        }
+    }
+}
+
+impl std::error::Error for FormError {}
+
+impl de::Error for FormError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Self::new(msg.to_string())
+    }
+}
+
+/// The maximum number of bracketed keys in a parameter name.
+///
+/// Decoding recurses once per key, so this prevents clients from exhausting
+/// the stack.
+const MAX_DEPTH: usize = 32;
+
+/// Splits a parameter name like `a[b][]` into its keys (`["a", "b", ""]`).
+fn parse_name(name: &str) -> Result<Vec<&str>, FormError> {
+    let malformed = || FormError::new(format!("malformed parameter name `{}`", name));
+
+    let (first, mut rest) = match name.find('[') {
+        Some(pos) => (&name[..pos], &name[pos..]),
+        None => (name, ""),
+    };
+    if first.is_empty() || first.contains(']') {
+        return Err(malformed());
+    }
+
+    let mut keys = vec![first];
+    while !rest.is_empty() {
+        let end = match rest.find(']') {
+            Some(end) if rest.starts_with('[') => end,
+            _ => return Err(malformed()),
+        };
+        let key = &rest[1..end];
+        if key.contains('[') {
+            return Err(malformed());
+        }
+        if keys.len() > MAX_DEPTH {
+            return Err(FormError::new(format!(
+                "parameter `{}` is nested more than {} levels deep",
+                name, MAX_DEPTH
+            )));
+        }
+        keys.push(key);
+        rest = &rest[end + 1..];
+    }
+
+    // `[]` appends to a sequence, so it can only be used last
+    if keys[..keys.len() - 1].iter().any(|key| key.is_empty()) {
+        return Err(malformed());
+    }
+
+    Ok(keys)
+}
+
+/// A parsed parameter, or the set of all parameters (at the root).
+#[derive(Debug)]
+enum Node {
+    /// The values of a parameter without nested keys (several if it was
+    /// repeated). Newly created nodes start out empty.
+    Values(Vec<String>),
+    /// Nested parameters, in order of their first appearance.
+    Map(Vec<(String, Node)>),
+}
+
+impl Node {
+    fn insert(&mut self, name: &str, keys: &[&str], value: String) -> Result<(), FormError> {
+        let conflict = || {
+            FormError::new(format!(
+                "parameter `{}` is used both with and without nested keys",
+                name
+            ))
+        };
+
+        let (key, rest) = match keys.split_first() {
+            Some((key, rest)) if !key.is_empty() => (*key, rest),
+            // `name` or `name[]`
+            _ => match self {
+                Node::Values(values) => {
+                    values.push(value);
+                    return Ok(());
+                }
+                Node::Map(_) => return Err(conflict()),
+            },
+        };
+
+        if let Node::Values(values) = self {
+            if !values.is_empty() {
+                return Err(conflict());
+            }
+            *self = Node::Map(Vec::new());
+        }
+
+        let entries = match self {
+            Node::Map(entries) => entries,
+            Node::Values(_) => unreachable!(),
+        };
+        let pos = match entries.iter().position(|(k, _)| k == key) {
+            Some(pos) => pos,
+            None => {
+                entries.push((key.to_string(), Node::Values(Vec::new())));
+                entries.len() - 1
+            }
+        };
+        entries[pos].1.insert(name, rest, value)
+    }
+}
+
+/// Deserializes a `Node` reached by the parameter name `path`.
+struct NodeDeserializer {
+    path: String,
+    node: Node,
+}
+
+impl NodeDeserializer {
+    fn child(&self, key: &str) -> String {
+        if self.path.is_empty() {
+            key.to_string()
+        } else {
+            format!("{}[{}]", self.path, key)
+        }
+    }
+
+    /// Returns the deserializer for a parameter that must have a single value.
+    fn single(self) -> Result<ValueDeserializer, FormError> {
+        let path = self.path;
+        match self.node {
+            Node::Values(mut values) => {
+                if values.len() == 1 {
+                    Ok(ValueDeserializer {
+                        path,
+                        value: values.remove(0),
+                    })
+                } else {
+                    let message = format!("expected a single value, found {}", values.len());
+                    Err(FormError::new(message).at(&path))
+                }
+            }
+            Node::Map(_) => {
+                Err(FormError::new("expected a value, found nested parameters").at(&path))
+            }
+        }
+    }
+
+    fn deserialize_map_entries<'de, V: Visitor<'de>>(
+        self,
+        visitor: V,
+    ) -> Result<V::Value, FormError> {
+        let entries = match self.node {
+            Node::Map(entries) => entries,
+            Node::Values(ref values) if values.is_empty() => Vec::new(),
+            Node::Values(_) => {
+                return Err(FormError::new(format!(
+                    "expected nested parameters (like `{}`)",
+                    self.child("key")
+                ))
+                .at(&self.path))
+            }
+        };
+
+        let path = self.path;
+        let mut children = Vec::with_capacity(entries.len());
+        for (key, node) in entries {
+            let path = if path.is_empty() {
+                key.clone()
+            } else {
+                format!("{}[{}]", path, key)
+            };
+            children.push((key, NodeDeserializer { path, node }));
+        }
+
+        let mut map = MapDeserializer::new(children.into_iter());
+        let value = visitor.visit_map(&mut map).map_err(|e| e.at(&path))?;
+        map.end().map_err(|e| e.at(&path))?;
+        Ok(value)
+    }
+}
+
+impl<'de> IntoDeserializer<'de, FormError> for NodeDeserializer {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+macro_rules! forward_to_single {
+    ($($method:ident)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, FormError> {
+                let path = self.path.clone();
+                self.single()?.$method(visitor).map_err(|e| e.at(&path))
+            }
+        )*
+    };
+}
+
+impl<'de> de::Deserializer<'de> for NodeDeserializer {
+    type Error = FormError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, FormError> {
+        match &self.node {
+            Node::Values(values) if values.len() == 1 => self.single()?.deserialize_any(visitor),
+            Node::Values(_) => self.deserialize_seq(visitor),
+            Node::Map(_) => self.deserialize_map_entries(visitor),
+        }
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, FormError> {
+        let path = self.path;
+        let items = match self.node {
+            Node::Values(values) => values
+                .into_iter()
+                .map(|value| NodeDeserializer {
+                    path: path.clone(),
+                    node: Node::Values(vec![value]),
+                })
+                .collect::<Vec<_>>(),
+            // Indexed sequence (`name[0]=a&name[1]=b`)
+            Node::Map(entries) => {
+                let mut indexed = Vec::with_capacity(entries.len());
+                for (key, node) in entries {
+                    let index = usize::from_str(&key).map_err(|_| {
+                        FormError::new(format!("expected a sequence index, found `{}`", key))
+                            .at(&path)
+                    })?;
+                    indexed.push((index, key, node));
+                }
+                indexed.sort_by_key(|(index, _, _)| *index);
+                indexed
+                    .into_iter()
+                    .map(|(_, key, node)| NodeDeserializer {
+                        path: if path.is_empty() {
+                            key
+                        } else {
+                            format!("{}[{}]", path, key)
+                        },
+                        node,
+                    })
+                    .collect()
+            }
+        };
+
+        let mut seq = SeqDeserializer::new(items.into_iter());
+        let value = visitor.visit_seq(&mut seq).map_err(|e| e.at(&path))?;
+        seq.end().map_err(|e| e.at(&path))?;
+        Ok(value)
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, FormError> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, FormError> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, FormError> {
+        self.deserialize_map_entries(visitor)
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, FormError> {
+        self.deserialize_map_entries(visitor)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, FormError> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, FormError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, FormError> {
+        self.single()?.deserialize_enum(name, variants, visitor)
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, FormError> {
+        visitor.visit_unit()
+    }
+
+    forward_to_single! {
+        deserialize_bool deserialize_i8 deserialize_i16 deserialize_i32 deserialize_i64
+        deserialize_u8 deserialize_u16 deserialize_u32 deserialize_u64 deserialize_f32
+        deserialize_f64 deserialize_char deserialize_str deserialize_string deserialize_bytes
+        deserialize_byte_buf deserialize_identifier
+    }
+
+    forward_to_deserialize_any! {
+        unit unit_struct
+    }
+}
+
+/// Deserializes a single parameter value.
+struct ValueDeserializer {
+    path: String,
+    value: String,
+}
+
+macro_rules! parse_value {
+    ($($method:ident => $visit:ident,)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, FormError> {
+                match FromStr::from_str(&self.value) {
+                    Ok(value) => visitor.$visit(value),
+                    Err(e) => Err(FormError::new(format!("invalid value `{}`: {}", self.value, e))
+                        .at(&self.path)),
+                }
+            }
+        )*
+    };
+}
+
+impl<'de> de::Deserializer<'de> for ValueDeserializer {
+    type Error = FormError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, FormError> {
+        visitor.visit_string(self.value)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, FormError> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, FormError> {
+        let path = self.path;
+        let value: de::value::StringDeserializer<FormError> = self.value.into_deserializer();
+        value
+            .deserialize_enum(name, variants, visitor)
+            .map_err(|e| e.at(&path))
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, FormError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    parse_value! {
+        deserialize_bool => visit_bool,
+        deserialize_i8 => visit_i8,
+        deserialize_i16 => visit_i16,
+        deserialize_i32 => visit_i32,
+        deserialize_i64 => visit_i64,
+        deserialize_u8 => visit_u8,
+        deserialize_u16 => visit_u16,
+        deserialize_u32 => visit_u32,
+        deserialize_u64 => visit_u64,
+        deserialize_f32 => visit_f32,
+        deserialize_f64 => visit_f64,
+        deserialize_char => visit_char,
+    }
+
+    forward_to_deserialize_any! {
+        str string bytes byte_buf unit unit_struct seq tuple tuple_struct map struct
+        identifier ignored_any
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use std::collections::BTreeMap;
+
+    #[derive(Deserialize, Serialize, PartialEq, Debug)]
+    struct Query {
+        ids: Vec<u32>,
+        filter: Option<Filter>,
+        items: Vec<Item>,
+        #[serde(default)]
+        extra: BTreeMap<String, String>,
+    }
+
+    #[derive(Deserialize, Serialize, PartialEq, Debug)]
+    struct Filter {
+        status: String,
+        #[serde(default)]
+        labels: Vec<String>,
+    }
+
+    #[derive(Deserialize, Serialize, PartialEq, Debug)]
+    struct Item {
+        name: String,
+        count: u8,
+    }
+
+    fn roundtrip<T: Serialize + DeserializeOwned>(value: &T) -> (String, T) {
+        let encoded = encode_nested(value).unwrap();
+        let decoded = decode_nested(encoded.as_bytes()).unwrap();
+        (encoded, decoded)
+    }
+
+    #[test]
+    fn nested_roundtrip() {
+        let mut extra = BTreeMap::new();
+        extra.insert("b".to_string(), "x y".to_string());
+        extra.insert("a".to_string(), "&".to_string());
+        let query = Query {
+            ids: vec![3, 1, 2],
+            filter: Some(Filter {
+                status: "open".to_string(),
+                labels: vec!["bug".to_string()],
+            }),
+            items: vec![
+                Item {
+                    name: "a".to_string(),
+                    count: 1,
+                },
+                Item {
+                    name: "b".to_string(),
+                    count: 2,
+                },
+            ],
+            extra,
+        };
+
+        let (encoded, decoded) = roundtrip(&query);
+        assert_eq!(
+            encoded,
+            "extra%5Ba%5D=%26&extra%5Bb%5D=x+y&filter%5Blabels%5D=bug&filter%5Bstatus%5D=open\
+             &ids=3&ids=1&ids=2&items%5B0%5D%5Bcount%5D=1&items%5B0%5D%5Bname%5D=a\
+             &items%5B1%5D%5Bcount%5D=2&items%5B1%5D%5Bname%5D=b"
+        );
+        assert_eq!(decoded, query);
+    }
+
+    #[test]
+    fn empty_values() {
+        let query = Query {
+            ids: vec![7],
+            filter: Some(Filter {
+                status: String::new(),
+                labels: Vec::new(),
+            }),
+            items: vec![Item {
+                name: String::new(),
+                count: 0,
+            }],
+            extra: BTreeMap::new(),
+        };
+
+        // `None` and empty sequences are left out, `#[serde(default)]` restores them
+        let (encoded, decoded) = roundtrip(&query);
+        assert_eq!(
+            encoded,
+            "filter%5Bstatus%5D=&ids=7&items%5B0%5D%5Bcount%5D=0&items%5B0%5D%5Bname%5D="
+        );
+        assert_eq!(decoded, query);
+
+        let query = Query {
+            filter: None,
+            ..query
+        };
+        assert_eq!(roundtrip(&query).1, query);
+
+        // Without `#[serde(default)]`, empty sequences can't be decoded again
+        let query = Query {
+            ids: Vec::new(),
+            ..query
+        };
+        let encoded = encode_nested(&query).unwrap();
+        let err = decode_nested::<Query>(encoded.as_bytes()).unwrap_err();
+        assert_eq!(err.to_string(), "missing field `ids`");
+    }
+
+    #[test]
+    fn values_roundtrip() {
+        #[derive(Deserialize, PartialEq, Debug)]
+        struct Params {
+            id: u32,
+            slug: String,
+        }
+
+        let params: Params = decode_values(vec![
+            ("id".to_string(), "5".to_string()),
+            ("slug".to_string(), "a/b".to_string()),
+        ])
+        .unwrap();
+        assert_eq!(
+            params,
+            Params {
+                id: 5,
+                slug: "a/b".to_string()
+            }
+        );
+
+        let params = serde_json::json!({ "id": 5, "slug": "a/b" });
+        assert_eq!(encode_path_param(&params, "id"), "5");
+        assert_eq!(encode_path_param(&params, "slug"), "a/b");
+    }
+
+    #[test]
+    fn names() {
+        assert_eq!(parse_name("a").unwrap(), ["a"]);
+        assert_eq!(parse_name("a[b][]").unwrap(), ["a", "b", ""]);
+        assert_eq!(parse_name("a[0][c]").unwrap(), ["a", "0", "c"]);
+        for name in &["", "[a]", "a]", "a[b", "a[b]c", "a[[b]]", "a[][b]"] {
+            assert_eq!(
+                parse_name(name).unwrap_err().to_string(),
+                format!("malformed parameter name `{}`", name)
+            );
+        }
+    }
+
+    #[test]
+    fn depth_limit() {
+        let name = format!("a{}", "[b]".repeat(MAX_DEPTH));
+        assert_eq!(parse_name(&name).unwrap().len(), MAX_DEPTH + 1);
+
+        let name = format!("a{}", "[b]".repeat(MAX_DEPTH + 1));
+        assert_eq!(
+            parse_name(&name).unwrap_err().to_string(),
+            format!("parameter `{}` is nested more than 32 levels deep", name)
+        );
+
+        let input = format!("a{}=1", "[b]".repeat(100_000));
+        assert!(decode_nested::<BTreeMap<String, Value>>(input.as_bytes()).is_err());
+    }
+}
@@ -0,0 +1,244 @@
+//! Provides commonly used [`Guard`] implementations.
+//!
+//! [`Guard`]: ../trait.Guard.html
+
+use crate::{BoxedError, Guard, NoContext};
+use http::header::{HeaderMap, COOKIE};
+use http::uri::{Parts, Uri};
+use std::sync::Arc;
+
+/// Provides access to the cookies sent with a request.
+///
+/// This type implements [`Guard`] and parses all `Cookie` headers of the
+/// request into a list of name-value pairs. Cookie values are not decoded in
+/// any way, except that surrounding double quotes are removed. Header values
+/// that are not valid visible ASCII and malformed cookie pairs are ignored.
+///
+/// If you only need the value of a single cookie, you can also use the
+/// `#[cookie]` attribute instead of this guard (refer to the [`FromRequest`]
+/// documentation for details).
+///
+/// # Examples
+///
+/// ```
+/// use hyperdrive::{FromRequest, NoContext, guard::Cookies};
+///
+/// #[derive(FromRequest)]
+/// enum Route {
+///     #[get("/")]
+///     Index {
+///         cookies: Cookies,
+///     },
+/// }
+///
+/// let Route::Index { cookies } = Route::from_request_sync(
+///     http::Request::get("/")
+///         .header("Cookie", "session=abc123; theme=dark")
+///         .body(hyper::Body::empty())
+///         .unwrap(),
+///     NoContext,
+/// ).unwrap();
+///
+/// assert_eq!(cookies.get("session"), Some("abc123"));
+/// assert_eq!(cookies.get("theme"), Some("dark"));
+/// assert_eq!(cookies.get("lang"), None);
+/// ```
+///
+/// [`Guard`]: ../trait.Guard.html
+/// [`FromRequest`]: ../trait.FromRequest.html
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Cookies {
+    cookies: Vec<(String, String)>,
+}
+
+impl Cookies {
+    /// Parses all `Cookie` headers in `headers`.
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        let cookies = headers
+            .get_all(COOKIE)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(';'))
+            .filter_map(|pair| {
+                let mut split = pair.splitn(2, '=');
+                let name = split.next()?.trim();
+                let value = split.next()?.trim();
+                if name.is_empty() {
+                    return None;
+                }
+
+                let value = if value.len() >= 2 && value.starts_with('"') && value.ends_with('"') {
+                    &value[1..value.len() - 1]
+                } else {
+                    value
+                };
+
+                Some((name.to_string(), value.to_string()))
+            })
+            .collect();
+
+        Self { cookies }
+    }
+
+    /// Returns the value of the cookie called `name`.
+    ///
+    /// If the request contains multiple cookies with that name, the first one
+    /// is returned. Returns `None` if there is no such cookie.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.cookies
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Returns an iterator over all cookies as `(name, value)` pairs, in the
+    /// order they appear in the request.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.cookies
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+    }
+
+    /// Returns the number of cookies.
+    pub fn len(&self) -> usize {
+        self.cookies.len()
+    }
+
+    /// Returns `true` if the request did not contain any cookies.
+    pub fn is_empty(&self) -> bool {
+        self.cookies.is_empty()
+    }
+}
+
+impl Guard for Cookies {
+    type Context = NoContext;
+
+    type Result = Result<Self, BoxedError>;
+
+    fn from_request(request: &Arc<http::Request<()>>, _context: &Self::Context) -> Self::Result {
+        Ok(Self::from_headers(request.headers()))
+    }
+}
+
+/// The URI of a request, before any prefix was stripped from its path.
+///
+/// When a `#[forward(prefix = "...")]` field is used, the `FromRequest`
+/// implementation of the field's type sees a request whose path has the prefix
+/// removed. The same applies to the normalized path a `#[forward]`ed type sees
+/// if `#[routing(trailing_slash = "...")]` is used. This guard can be used to
+/// obtain the URI as it was originally received. For requests that were not
+/// forwarded this way, it is just the request URI.
+///
+/// # Examples
+///
+/// ```
+/// use hyperdrive::{FromRequest, NoContext, guard::OriginalUri};
+///
+/// #[derive(FromRequest)]
+/// enum Api {
+///     #[get("/users")]
+///     Users {
+///         uri: OriginalUri,
+///     },
+/// }
+///
+/// #[derive(FromRequest)]
+/// enum Routes {
+///     Api {
+///         #[forward(prefix = "/api/v1")]
+///         api: Api,
+///     },
+/// }
+///
+/// let Routes::Api { api: Api::Users { uri } } = Routes::from_request_sync(
+///     http::Request::get("/api/v1/users?page=2")
+///         .body(hyper::Body::empty())
+///         .unwrap(),
+///     NoContext,
+/// ).unwrap();
+///
+/// assert_eq!(uri.path(), "/api/v1/users");
+/// assert_eq!(uri.uri().query(), Some("page=2"));
+/// ```
+///
+/// [`FromRequest`]: ../trait.FromRequest.html
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OriginalUri {
+    uri: Uri,
+}
+
+impl OriginalUri {
+    /// Returns the original request URI.
+    pub fn uri(&self) -> &Uri {
+        &self.uri
+    }
+
+    /// Returns the path of the original request URI.
+    pub fn path(&self) -> &str {
+        self.uri.path()
+    }
+
+    /// Returns the original URI of `request`.
+    pub(crate) fn of(request: &http::Request<()>) -> Self {
+        request
+            .extensions()
+            .get::<OriginalUri>()
+            .cloned()
+            .unwrap_or_else(|| OriginalUri {
+                uri: request.uri().clone(),
+            })
+    }
+
+    /// Creates the request passed to a `FromRequest` implementation mounted
+    /// under `prefix`.
+    ///
+    /// The request path must start with `prefix`. The returned request has
+    /// the prefix removed from its path, and stores the original URI in its
+    /// extensions. Other extensions are not preserved.
+    #[doc(hidden)] // used by the custom derive
+    pub fn strip_prefix(request: &http::Request<()>, prefix: &str) -> http::Request<()> {
+        let path = &request.uri().path()[prefix.len()..];
+        let path = if path.is_empty() { "/" } else { path };
+
+        Self::with_path(request, path)
+    }
+
+    /// Creates a copy of `request` with its path replaced by `path`.
+    ///
+    /// The copy stores the original URI of `request` in its extensions. Other
+    /// extensions are not preserved, since they can't be cloned.
+    pub(crate) fn with_path(request: &http::Request<()>, path: &str) -> http::Request<()> {
+        // When paths are rewritten more than once, we keep the outermost URI
+        let original = Self::of(request);
+
+        let path_and_query = match request.uri().query() {
+            Some(query) => format!("{}?{}", path, query),
+            None => path.to_string(),
+        };
+
+        let mut parts = Parts::from(request.uri().clone());
+        parts.path_and_query = Some(
+            path_and_query
+                .parse()
+                .expect("replacing the path produced an invalid path"),
+        );
+
+        let mut new = http::Request::new(());
+        *new.method_mut() = request.method().clone();
+        *new.uri_mut() = Uri::from_parts(parts).expect("replacing the path produced an invalid URI");
+        *new.version_mut() = request.version();
+        *new.headers_mut() = request.headers().clone();
+        new.extensions_mut().insert(original);
+        new
+    }
+}
+
+impl Guard for OriginalUri {
+    type Context = NoContext;
+
+    type Result = Result<Self, BoxedError>;
+
+    fn from_request(request: &Arc<http::Request<()>>, _context: &Self::Context) -> Self::Result {
+        Ok(Self::of(request))
+    }
+}
@@ -0,0 +1,174 @@
+use crate::body::IntoBody;
+use http::header::{HeaderName, HeaderValue, CONTENT_TYPE, COOKIE};
+use std::fmt;
+
+/// Turns a value back into the HTTP request it was decoded from.
+///
+/// This is the inverse of [`FromRequest`], and is useful for writing tests and
+/// clients of services using Hyperdrive. It can be derived for any type that
+/// also derives `FromRequest`, and the generated implementation uses the same
+/// attributes:
+///
+/// * The method and path of the first route attribute of the variant are used.
+///   Placeholder values are formatted using `Display` and percent-encoded
+///   (except for [`Raw`] placeholders, which are inserted as they are).
+///   Placeholders without a field of their own are taken from the serialized
+///   `#[path_params]` field.
+/// * The host pattern of the route (if any) is used for the `Host` header.
+/// * The `consumes` and `produces` media types of the route (if any) are used
+///   for the `Content-Type` and `Accept` headers.
+/// * The `#[query_params]` field is serialized like for [`ToUri`], and
+///   `#[query]` fields are appended as query parameters after formatting them
+///   using `Display`. `None` values are left out, and every element of a
+///   `Vec` field is added as a separate parameter.
+/// * The `#[body]` field is encoded using its [`IntoBody`] implementation,
+///   which also sets the `Content-Type` header (and replaces the body created
+///   by a `#[forward]` field).
+/// * `#[header]` and `#[cookie]` fields are formatted using `Display`. They
+///   are left out if they are `Option`s set to `None`. Cookie values are not
+///   encoded, so they must not contain whitespace, `"`, `,`, `;` or `\`.
+/// * A `#[forward]` field creates the request using the nested `IntoRequest`
+///   implementation. If the field is mounted using
+///   `#[forward(prefix = "...")]`, the prefix is prepended to its path. If the
+///   variant also has a route attribute, the method and URI are then replaced
+///   by the outer route.
+/// * Guards are ignored, since they can't be turned back into request data.
+///
+/// For any value created by the derived `FromRequest` implementation, passing
+/// the request returned by `into_request` to `FromRequest` results in an equal
+/// value, as long as the `Display` implementations of the placeholder, query
+/// parameter, header and cookie types round-trip through `FromStr`, and the
+/// guards succeed.
+///
+/// # Panics
+///
+/// The derived implementation will panic when called on a variant that can
+/// not be created by `FromRequest` (since it has no route or `#[forward]`
+/// field), and when a field can not be encoded.
+///
+/// # Examples
+///
+/// ```
+/// use hyperdrive::{FromRequest, IntoRequest, NoContext};
+///
+/// #[derive(FromRequest, IntoRequest, Debug, PartialEq)]
+/// enum Routes {
+///     #[get("/")]
+///     Index,
+///
+///     #[post("/users/{id}")]
+///     User {
+///         id: u32,
+///
+///         #[header("X-Request-Id")]
+///         request_id: Option<String>,
+///     },
+/// }
+///
+/// let route = Routes::User { id: 5, request_id: Some("abc".to_string()) };
+/// let request = route.into_request();
+/// assert_eq!(request.method(), http::Method::POST);
+/// assert_eq!(request.uri(), "/users/5");
+/// assert_eq!(request.headers()["X-Request-Id"], "abc");
+///
+/// let route = Routes::from_request_sync(request, NoContext).unwrap();
+/// assert_eq!(route, Routes::User { id: 5, request_id: Some("abc".to_string()) });
+/// ```
+///
+/// [`FromRequest`]: trait.FromRequest.html
+/// [`IntoBody`]: body/trait.IntoBody.html
+/// [`Raw`]: struct.Raw.html
+/// [`ToUri`]: trait.ToUri.html
+pub trait IntoRequest {
+    /// Creates an HTTP request that decodes to `self`.
+    fn into_request(self) -> http::Request<hyper::Body>;
+}
+
+/// Incrementally builds the request returned by a derived `IntoRequest` impl.
+#[doc(hidden)]
+#[derive(Debug, Default)]
+pub struct RequestBuilder {
+    request: http::Request<hyper::Body>,
+    cookies: Vec<String>,
+}
+
+impl RequestBuilder {
+    pub fn new() -> Self {
+        Self {
+            request: http::Request::new(hyper::Body::empty()),
+            cookies: Vec::new(),
+        }
+    }
+
+    /// Starts with the request created by a forwarded-to type, which is mounted
+    /// under `prefix`.
+    pub fn forward<T: IntoRequest>(inner: T, prefix: &str) -> Self {
+        let mut request = inner.into_request();
+        if !prefix.is_empty() {
+            let uri = match request.uri().path_and_query() {
+                Some(pq) => format!("{}{}", prefix, pq),
+                None => prefix.to_string(),
+            };
+            *request.uri_mut() = uri
+                .parse()
+                .unwrap_or_else(|e| panic!("prefixed URI `{}` is invalid: {}", uri, e));
+        }
+
+        Self {
+            request,
+            cookies: Vec::new(),
+        }
+    }
+
+    pub fn route(&mut self, method: &str, uri: http::Uri) {
+        *self.request.method_mut() = http::Method::from_bytes(method.as_bytes())
+            .expect("FromRequest derive produced invalid method");
+        *self.request.uri_mut() = uri;
+    }
+
+    pub fn body<T: IntoBody>(&mut self, body: T) {
+        let (content_type, body) = body.into_body();
+        self.request
+            .headers_mut()
+            .insert(CONTENT_TYPE, HeaderValue::from_static(content_type));
+        *self.request.body_mut() = body;
+    }
+
+    pub fn header(&mut self, name: &'static str, value: &dyn fmt::Display) {
+        let value = value.to_string();
+        let value = HeaderValue::from_str(&value).unwrap_or_else(|_| {
+            panic!("value `{}` of header `{}` is not a valid header value", value, name)
+        });
+        self.request
+            .headers_mut()
+            .insert(HeaderName::from_static(name), value);
+    }
+
+    pub fn cookie(&mut self, name: &str, value: &dyn fmt::Display) {
+        // Only allow the `cookie-octet`s of RFC 6265 (visible ASCII characters except `"`, `,`, `;`
+        // and `\`), since whitespace, `;` and `,` would corrupt the header (or add more cookies)
+        let value = value.to_string();
+        let valid = value.bytes().all(|byte| match byte {
+            b'"' | b',' | b';' | b'\\' => false,
+            _ => byte.is_ascii_graphic(),
+        });
+        if !valid {
+            panic!(
+                "value `{}` of cookie `{}` is not a valid cookie value",
+                value, name
+            );
+        }
+
+        self.cookies.push(format!("{}={}", name, value));
+    }
+
+    pub fn build(mut self) -> http::Request<hyper::Body> {
+        if !self.cookies.is_empty() {
+            let cookies = self.cookies.join("; ");
+            let value = HeaderValue::from_str(&cookies)
+                .unwrap_or_else(|_| panic!("invalid cookie header value `{}`", cookies));
+            self.request.headers_mut().append(COOKIE, value);
+        }
+        self.request
+    }
+}
@@ -153,10 +153,23 @@ TODO:
 
 pub mod body;
 mod error;
+mod form;
+pub mod guard;
+mod into_request;
+#[cfg(feature = "openapi")]
+pub mod openapi;
+mod raw;
 mod readme;
+mod route_info;
+mod router;
 pub mod service;
+mod uri;
 
 pub use error::*;
+pub use into_request::IntoRequest;
+pub use raw::Raw;
+pub use route_info::*;
+pub use uri::ToUri;
 pub use hyperderive::*;
 
 // Reexport public deps for use by the custom derive
@@ -165,7 +178,20 @@ pub use {futures, http, hyper, serde};
 // These are hidden because the user never actually interacts with them. They're
 // only used by the generated code internally.
 #[doc(hidden)]
-pub use {lazy_static::lazy_static, regex};
+pub use {
+    body::BufferedBody,
+    form::{decode_nested, encode_nested, encode_path_param},
+    into_request::RequestBuilder,
+    lazy_static::lazy_static,
+    regex,
+    router::{
+        accept_preference, content_type_matches, decode_path, match_segment,
+        media_type_fallback_error, merge_fallback_errors, method_not_accepted, normalize_request,
+        percent_decode, request_host, BodySlot, MediaPreference, QueryParams, SegmentPart,
+        TrailingSlash,
+    },
+    uri::UriBuilder,
+};
 
 use futures::{Future, IntoFuture};
 use std::sync::Arc;
@@ -288,6 +314,57 @@ pub type BoxedError = Box<dyn std::error::Error + Send + Sync>;
 /// }
 /// ```
 ///
+/// Alternatively, overlapping routes can be given an explicit precedence by
+/// passing a `rank` argument to the route attribute. When more than one route
+/// matches a request, the route with the lowest rank that accepts the request
+/// method is used. Routes without a `rank` argument have rank 0. Overlapping
+/// routes with the same rank are still rejected.
+///
+/// ```
+/// use hyperdrive::FromRequest;
+///
+/// #[derive(FromRequest)]
+/// enum Routes {
+///     #[get("/users/me")]
+///     Me,
+///
+///     #[get("/users/{id}", rank = 1)]
+///     User { id: u32 },
+/// }
+/// ```
+///
+/// Note that the rank only decides which route is selected. If converting a
+/// placeholder of the selected route fails, the request is rejected, even if a
+/// route with a higher rank would accept it (unless `#[routing(fallthrough)]`
+/// is used, see [below](#trying-routes-in-sequence)).
+///
+/// ## Custom HTTP methods
+///
+/// Route attributes exist for all methods defined in RFC 7231 as well as
+/// `PATCH` (`#[get]`, `#[post]`, `#[patch]`, etc.). Other methods, such as
+/// those used by WebDAV, can be matched with the generic `#[route]` attribute:
+///
+/// ```
+/// use hyperdrive::FromRequest;
+///
+/// #[derive(FromRequest)]
+/// enum Routes {
+///     #[get("/files/{path...}")]
+///     Download { path: String },
+///
+///     #[route(method = "PROPFIND", path = "/files/{path...}")]
+///     Properties { path: String },
+///
+///     #[route(method = "PURGE", path = "/cache")]
+///     PurgeCache,
+/// }
+/// ```
+///
+/// Method names are case-sensitive, so `#[route(method = "get", ...)]` will
+/// *not* match `GET` requests. Routes declared this way behave exactly like
+/// the ones declared via method-specific attributes, and are included in the
+/// `Allow` header when a request uses the wrong method.
+///
 /// ## Implicit `HEAD` routes
 ///
 /// The custom derive will create a `HEAD` route for every defined `GET` route,
@@ -297,6 +374,17 @@ pub type BoxedError = Box<dyn std::error::Error + Send + Sync>;
 /// [`FromRequest::from_request`][`from_request`], you have to make sure no body
 /// is sent back for `HEAD` requests.
 ///
+/// ## Implicit `OPTIONS` responses
+///
+/// An `OPTIONS` request to a path that has no `OPTIONS` route is answered by
+/// failing with an [`Error`] that responds with `204 No Content`. Its `Allow`
+/// header lists the methods accepted by the routes of that path (see
+/// [`Error::allowed_methods`]), just like a `405 Method Not Allowed` error
+/// would.
+///
+/// [`AsyncService`] and [`SyncService`] can use this to answer CORS preflight
+/// requests, when configured with a [`service::Cors`] configuration.
+///
 /// ## Extracting Request Data
 ///
 /// The custom derive provides easy access to various kinds of data encoded in a
@@ -304,6 +392,7 @@ pub type BoxedError = Box<dyn std::error::Error + Send + Sync>;
 ///
 /// * The Request path (`/users/or/other/stuff`)
 /// * Query parameters (`?name=val`)
+/// * Request headers and cookies
 /// * The request body
 ///
 /// ### Extracting Path Segments (`{field}` syntax)
@@ -322,9 +411,274 @@ pub type BoxedError = Box<dyn std::error::Error + Send + Sync>;
 /// #[get("/static/{path...}")]
 /// ```
 ///
+/// Placeholders don't have to make up a whole path segment. They can be mixed
+/// with literal text, and a segment can contain more than one placeholder, as
+/// long as they are separated by literal text:
+///
+/// ```notrust
+/// #[get("/reports/{id}.{format}")]
+/// #[get("/v{version}/items")]
+/// ```
+///
+/// Placeholders always match as much as possible, so `/reports/a.b.json`
+/// would be routed with `id = "a.b"` and `format = "json"`.
+///
+/// A placeholder can also be restricted to path segments matching a regular
+/// expression by using the `{field:regex}` syntax:
+///
+/// ```notrust
+/// #[get("/files/{id:[0-9]+}")]
+/// ```
+///
+/// The regex has to match the whole path segment, and must neither match `/`
+/// nor contain capture groups (non-capturing groups like `(?:a|b)` are
+/// fine). Paths that don't match the regex are not routed to the variant at
+/// all, as if the route didn't exist. This also allows routes whose
+/// constraints don't overlap to coexist (the check is conservative, so
+/// constraints should differ in the characters their matches can start with):
+///
+/// ```
+/// use hyperdrive::FromRequest;
+///
+/// #[derive(FromRequest)]
+/// enum Routes {
+///     #[get("/files/{id:[0-9]+}")]
+///     ById { id: u32 },
+///
+///     #[get("/files/{name:[a-z]+}")]
+///     ByName { name: String },
+/// }
+/// ```
+///
+/// Placeholder values are percent-decoded before they are converted, so
+/// `/users/J%C3%BCrgen` matching `/users/{name}` results in `name` being
+/// `"Jürgen"`. If a value doesn't decode to valid UTF-8, the request is
+/// rejected with a `400 Bad Request` error. Literal path text and regex
+/// constraints are matched against the path as it was sent, before decoding.
+/// To get the value of a placeholder without decoding it, use the [`Raw`]
+/// wrapper as the field type.
+///
+/// Tuple variants and tuple structs are supported as well. Their fields are
+/// referred to by index, so `{0}` is converted to the type of the first
+/// field. All field attributes can be used on unnamed fields, but
+/// `#[header]` and `#[cookie]` then need an explicit name:
+///
+/// ```
+/// use hyperdrive::{body::Json, FromRequest};
+///
+/// #[derive(FromRequest)]
+/// enum Routes {
+///     #[get("/users/{0}/posts/{1}")]
+///     Post(u32, String),
+///
+///     #[put("/notes/{1}")]
+///     Note(#[body] Json<Vec<String>>, u32, #[header("X-Request-Id")] Option<String>),
+/// }
+/// ```
+///
 /// If the `FromStr` conversion fails, the generated `FromRequest`
-/// implementation will bail out with an error (in other words, this feature
-/// cannot be used to try multiple routes in sequence until one matches).
+/// implementation will bail out with an error (in other words, by default,
+/// this feature cannot be used to try multiple routes in sequence until one
+/// matches).
+///
+/// #### Trying routes in sequence
+///
+/// Putting `#[routing(fallthrough)]` on the type changes this: When the path
+/// placeholders of a route fail to parse, the next route matching the request
+/// is tried instead, followed by the fallback variant marked with `#[forward]`
+/// (if any). In this mode, routes are allowed to overlap. They are tried in
+/// order of their `rank`, and in declaration order when their ranks are equal:
+///
+/// ```
+/// use hyperdrive::FromRequest;
+///
+/// #[derive(FromRequest)]
+/// #[routing(fallthrough)]
+/// enum Routes {
+///     #[get("/users/{id}")]
+///     UserById { id: u32 },
+///
+///     #[get("/users/{name}")]
+///     UserByName { name: String },
+/// }
+/// ```
+///
+/// A request that matches no route this way is rejected with a `404 Not
+/// Found` error, or with `405 Method Not Allowed` if the routes of other
+/// methods would have accepted the path.
+///
+/// #### Path normalization
+///
+/// By default, the request path has to match a route exactly: `/users/`,
+/// `//users` and `/x/../users` don't match the route `/users`. The
+/// `#[routing(trailing_slash = "...")]` attribute makes the generated
+/// implementation normalize the path first, by collapsing duplicate slashes and
+/// resolving `.` and `..` segments. Paths whose `..` segments would lead
+/// outside of the root are rejected with `400 Bad Request`. The value of the
+/// attribute decides how trailing slashes are treated:
+///
+/// * `"strict"`: Trailing slashes have to match the route exactly.
+/// * `"ignore"`: If the path doesn't match any route, but would with a trailing
+///   slash added or removed, it is routed as such.
+/// * `"redirect"`: Instead of routing a path that isn't in its canonical form,
+///   the request is answered with a `308 Permanent Redirect` to the canonical
+///   path (see [`Error::location`]).
+///
+/// Only routing uses the normalized path. Guards and fields see the request as
+/// it was received, including its extensions. `#[forward]`ed types get a copy
+/// of the request with the normalized path. Like with a mount prefix, that
+/// copy only keeps the original URI (see [`guard::OriginalUri`]), but no other
+/// extensions.
+///
+/// ```
+/// use hyperdrive::FromRequest;
+///
+/// #[derive(FromRequest, Debug, PartialEq)]
+/// #[routing(trailing_slash = "ignore")]
+/// enum Routes {
+///     #[get("/users")]
+///     Users,
+/// }
+///
+/// let request = http::Request::get("//users/").body(hyper::Body::empty()).unwrap();
+/// let route = Routes::from_request_sync(request, hyperdrive::NoContext).unwrap();
+/// assert_eq!(route, Routes::Users);
+/// ```
+///
+/// #### Matching the host
+///
+/// Routes can be restricted to requests for a specific host by passing a
+/// `host` argument to the route attribute, or by putting `#[host("...")]` on
+/// the type, which applies to all routes without a `host` argument. Like
+/// paths, host patterns may contain placeholders, which are extracted into
+/// fields using `FromStr` (without percent-decoding). A placeholder matches a
+/// single, nonempty label of the host name.
+///
+/// The host is taken from the request URI if it is in absolute form, and from
+/// the `Host` header otherwise. The port is ignored, and host names are
+/// compared case-insensitively. Requests whose host isn't matched by any route
+/// with a matching path are rejected with `404 Not Found`.
+///
+/// Routes with different hosts may use the same path. Routes whose hosts
+/// overlap (like `api.example.com` and `{tenant}.example.com`) need different
+/// ranks.
+///
+/// ```
+/// use hyperdrive::FromRequest;
+///
+/// #[derive(FromRequest, Debug, PartialEq)]
+/// #[host("{tenant}.example.com")]
+/// enum Routes {
+///     #[get("/", rank = 1)]
+///     Index { tenant: String },
+///
+///     #[get("/", host = "api.example.com")]
+///     Api,
+/// }
+///
+/// let request = http::Request::get("/")
+///     .header("Host", "acme.example.com:8080")
+///     .body(hyper::Body::empty())
+///     .unwrap();
+/// let route = Routes::from_request_sync(request, hyperdrive::NoContext).unwrap();
+/// assert_eq!(route, Routes::Index { tenant: "acme".to_string() });
+/// ```
+///
+/// `#[forward]`ed types are not restricted by the host pattern of the type
+/// forwarding to them.
+///
+/// #### Matching media types
+///
+/// The `consumes = "type/subtype"` route argument restricts a route to
+/// requests whose `Content-Type` header has the given media type (parameters
+/// like `charset` are ignored). `produces = "type/subtype"` restricts it to
+/// requests whose `Accept` header allows the given media type, or that don't
+/// have an `Accept` header. Both may use `type/*` to match any subtype.
+///
+/// Routes for the same path and method may then coexist as long as their
+/// media types differ. Of the routes matching the request, the one producing
+/// the media type with the highest quality (`q`) in the `Accept` header is
+/// used, then the one matched by the most specific media range, then the first
+/// in order of rank and declaration. If routes match the path and method, but
+/// none matches the media types, the request is rejected with
+/// `415 Unsupported Media Type` (if no route accepts the `Content-Type`) or
+/// `406 Not Acceptable`, unless a `#[forward]`ed type accepts it.
+///
+/// ```
+/// use hyperdrive::FromRequest;
+///
+/// #[derive(FromRequest, Debug, PartialEq)]
+/// enum Routes {
+///     #[post("/upload", consumes = "application/json")]
+///     UploadJson,
+///
+///     #[post("/upload", consumes = "text/csv")]
+///     UploadCsv,
+///
+///     #[get("/report", produces = "text/csv")]
+///     ReportCsv,
+///
+///     #[get("/report", produces = "application/json")]
+///     ReportJson,
+/// }
+///
+/// let request = http::Request::get("/report")
+///     .header("Accept", "application/json")
+///     .body(hyper::Body::empty())
+///     .unwrap();
+/// let route = Routes::from_request_sync(request, hyperdrive::NoContext).unwrap();
+/// assert_eq!(route, Routes::ReportJson);
+/// ```
+///
+/// #### Collecting placeholders in a struct (`#[path_params]` attribute)
+///
+/// Instead of declaring a field for every placeholder, a single field can be
+/// marked with `#[path_params]` (`#[path]` is taken by a builtin attribute).
+/// All path and host placeholders without a field of their own are then
+/// percent-decoded and deserialized into it using `serde`, with the
+/// placeholder names as the keys. The field type has to implement
+/// `DeserializeOwned`, and the variant needs a route attribute.
+///
+/// If deserialization fails, the route doesn't match, just like when a
+/// `FromStr` conversion fails. This isn't the case if the field receives host
+/// placeholders, since those are only checked once the route was chosen: then
+/// the request is rejected with `404 Not Found` instead of trying other routes.
+/// Derived [`ToUri`] and `IntoRequest` impls
+/// serialize the field to fill in the placeholders, so it has to implement
+/// `Serialize` there.
+///
+/// ```
+/// use hyperdrive::FromRequest;
+/// use serde::Deserialize;
+///
+/// #[derive(Deserialize, Debug, PartialEq)]
+/// struct BuildPath {
+///     org: String,
+///     repo: String,
+///     build: u32,
+/// }
+///
+/// #[derive(FromRequest, Debug, PartialEq)]
+/// enum Routes {
+///     #[get("/{org}/{repo}/builds/{build}")]
+///     Build {
+///         #[path_params]
+///         path: BuildPath,
+///     },
+/// }
+///
+/// let request = http::Request::get("/rust-lang/rust/builds/42")
+///     .body(hyper::Body::empty())
+///     .unwrap();
+/// let route = Routes::from_request_sync(request, hyperdrive::NoContext).unwrap();
+/// assert_eq!(route, Routes::Build {
+///     path: BuildPath {
+///         org: "rust-lang".to_string(),
+///         repo: "rust".to_string(),
+///         build: 42,
+///     },
+/// });
+/// ```
 ///
 /// ### Extracting the request body (`#[body]` attribute)
 ///
@@ -343,6 +697,12 @@ pub type BoxedError = Box<dyn std::error::Error + Send + Sync>;
 /// contains predefined adapters implementing that trait, which work with any
 /// type implementing `Deserialize`.
 ///
+/// By using `#[body(buffer_limit = N)]`, the body is read into memory when it
+/// is first read by the [`FromBody`] implementation (or a `#[forward]`ed
+/// type). Bodies larger than `N` bytes are rejected with `413 Payload Too
+/// Large`. This is required for combining `#[body]` with `#[forward]` (see
+/// below).
+///
 /// ### Extracting query parameters (`#[query_params]` attribute)
 ///
 /// The route attribute cannot match or extract query parameters (`?name=val`).
@@ -377,6 +737,111 @@ pub type BoxedError = Box<dyn std::error::Error + Send + Sync>;
 /// trait and the conversion will be performed using the `serde_urlencoded`
 /// crate.
 ///
+/// `serde_urlencoded` only supports flat structures. Using
+/// `#[query_params(nested)]` instead also decodes repeated parameters
+/// (`ids=1&ids=2` or `ids[]=1&ids[]=2`) into sequences, and bracketed
+/// parameters (`filter[status]=open`) into nested structs or maps. The
+/// generated [`ToUri`] and `IntoRequest` implementations encode the field
+/// the same way. Since empty sequences and maps are left out when encoding,
+/// fields of these types should use `#[serde(default)]`. Request bodies in
+/// this format can be decoded using [`body::NestedForm`].
+///
+/// ### Extracting single query parameters (`#[query]` attribute)
+///
+/// When only a few query parameters are needed, defining a separate struct for
+/// them can be avoided by marking fields with `#[query("name")]`. A plain
+/// `#[query]` attribute uses the field name as the parameter name. The value
+/// of the parameter is converted using `FromStr`:
+///
+/// ```
+/// use hyperdrive::FromRequest;
+///
+/// #[derive(FromRequest)]
+/// enum Routes {
+///     #[get("/search")]
+///     Search {
+///         #[query("q")]
+///         term: String,
+///
+///         #[query]
+///         page: Option<u32>,
+///
+///         // `?tag=a&tag=b`
+///         #[query("tag")]
+///         tags: Vec<String>,
+///
+///         #[query(default)]
+///         exact: bool,
+///     },
+/// }
+/// ```
+///
+/// If the parameter is missing or its value cannot be converted, the request
+/// is rejected with a `400 Bad Request` error whose message names the
+/// parameter. Fields of type `Option<T>` are set to `None` when the parameter
+/// is missing, fields of type `Vec<T>` collect all values of a repeated
+/// parameter, and `#[query(default)]` (or `#[query("name", default)]`) uses
+/// `Default::default()` for a missing parameter. `#[query]` fields can be
+/// combined with a `#[query_params]` field, which sees all parameters.
+///
+/// ### Extracting headers (`#[header]` attribute)
+///
+/// Fields marked with `#[header("Header-Name")]` will be populated with the
+/// value of the named request header, converted using `FromStr`. A plain
+/// `#[header]` attribute uses the field name, with underscores replaced by
+/// dashes, as the header name (header names are case-insensitive):
+///
+/// ```
+/// use hyperdrive::FromRequest;
+///
+/// #[derive(FromRequest)]
+/// enum Routes {
+///     #[get("/")]
+///     Index {
+///         #[header("X-Request-Id")]
+///         request_id: u64,
+///
+///         // Uses the `X-Client-Version` header
+///         #[header]
+///         x_client_version: Option<String>,
+///     },
+/// }
+/// ```
+///
+/// If the header is missing or its value cannot be converted, the generated
+/// `FromRequest` implementation will fail with a `400 Bad Request` error. The
+/// error caused by the conversion is available as the error's source. Fields of
+/// type `Option<T>` are optional and will be set to `None` when the header is
+/// missing. If a header is specified multiple times, only the first value is
+/// used.
+///
+/// ### Extracting cookies (`#[cookie]` attribute)
+///
+/// Similarly, fields marked with `#[cookie("name")]` will be populated with the
+/// value of the named cookie, converted using `FromStr`. A plain `#[cookie]`
+/// attribute uses the field name as the cookie name:
+///
+/// ```
+/// use hyperdrive::FromRequest;
+///
+/// #[derive(FromRequest)]
+/// enum Routes {
+///     #[get("/")]
+///     Index {
+///         #[cookie("session")]
+///         session_id: String,
+///
+///         #[cookie]
+///         theme: Option<String>,
+///     },
+/// }
+/// ```
+///
+/// Missing or malformed cookies are handled exactly like headers, resulting in
+/// a `400 Bad Request` error unless the field is an `Option<T>`. If you need
+/// access to all cookies of a request, you can use the [`guard::Cookies`]
+/// guard.
+///
 /// ## Guards
 ///
 /// Guards can be used to prevent a route from being called when a condition is
@@ -427,17 +892,76 @@ pub type BoxedError = Box<dyn std::error::Error + Send + Sync>;
 /// The library will then generate code that invokes this nested `FromRequest`
 /// implementation.
 ///
-/// This feature can not be combined with `#[body]` inside the same variant,
-/// since both consume the request body.
+/// Since both consume the request body, this feature can only be combined with
+/// `#[body]` inside the same variant if the body is buffered. The body is then
+/// read once, and both fields receive a copy of it. If the forwarded-to type
+/// rejects the request first, the body isn't read at all. This is useful for
+/// wrappers that need to inspect the raw body, like a signature check:
 ///
-/// Currently, this is limited to `FromRequest` implementations that use the
-/// same [`RequestContext`] as the outer type (ie. no automatic `AsRef`
-/// conversion will take place).
+/// ```notrust
+/// struct Signed {
+///     #[body(buffer_limit = 1048576)]
+///     signature: VerifiedSignature,
+///
+///     #[forward]
+///     api: Api,
+/// }
+/// ```
+///
+/// The forwarded-to type may use a different [`RequestContext`] than the outer
+/// type, as long as the outer context implements `AsRef` for it (for example,
+/// by using an `#[as_ref]` field). Since `FromRequest` takes its context by
+/// value, the context of the forwarded-to type must also implement `Clone`.
 ///
 /// A variant or struct defining a `#[forward]` field does not have to define
 /// a route. If no other route matches, this variant will automatically be
 /// created, and is considered a *fallback route*.
 ///
+/// An enum can have multiple fallback variants, which are tried in declaration
+/// order. The next variant is used when the previous one fails with a
+/// `404 Not Found` or `405 Method Not Allowed` error, as long as it didn't read
+/// the request body yet. If all of them reject the request method, their
+/// allowed methods are merged. Since each variant receives its own context,
+/// this requires the context type to implement `Clone`.
+///
+/// The forwarded-to type can also be *mounted* under a path prefix by using
+/// `#[forward(prefix = "/some/path")]`. The variant is then used for all
+/// requests whose path starts with the prefix, and the nested `FromRequest`
+/// implementation sees a request with the prefix removed from its path. This
+/// allows reusing the same type in multiple places:
+///
+/// ```
+/// use hyperdrive::FromRequest;
+///
+/// #[derive(FromRequest)]
+/// enum Api {
+///     #[get("/users")]  // matches `/api/v1/users` and `/api/v2/users`
+///     Users,
+/// }
+///
+/// #[derive(FromRequest)]
+/// enum Routes {
+///     #[get("/")]
+///     Index,
+///
+///     V1 {
+///         #[forward(prefix = "/api/v1")]
+///         api: Api,
+///     },
+///
+///     V2 {
+///         #[forward(prefix = "/api/v2")]
+///         api: Api,
+///     },
+/// }
+/// ```
+///
+/// Any number of variants can be mounted, as long as their prefixes don't
+/// overlap with each other or with any route of the outer type. Prefixes must
+/// not contain placeholders. The forwarded request carries the original
+/// headers, but no request extensions except for the original URI, which can
+/// be accessed using the [`guard::OriginalUri`] guard.
+///
 /// Combined with generics, this feature can be used to make request wrappers
 /// that attach a guard or a guard group to any type implementing `FromRequest`:
 ///
@@ -491,6 +1015,64 @@ pub type BoxedError = Box<dyn std::error::Error + Send + Sync>;
 ///
 /// For more info on this, refer to the [`RequestContext`] trait.
 ///
+/// ## Building URIs
+///
+/// Deriving [`ToUri`] as well adds a `to_uri` method, which builds the URI of
+/// the route a value would be decoded from, so that links and `Location`
+/// headers don't have to be hard-coded. The placeholders and query parameters
+/// are taken from the fields of the value, and the first route attribute of
+/// its variant is used:
+///
+/// ```
+/// use hyperdrive::{FromRequest, ToUri};
+///
+/// #[derive(FromRequest, ToUri)]
+/// enum Routes {
+///     #[get("/")]
+///     Index,
+///
+///     #[get("/users/{id}/posts/{title}")]
+///     UserPost { id: u32, title: String },
+/// }
+///
+/// let route = Routes::UserPost { id: 42, title: "Hello World".to_string() };
+/// assert_eq!(route.to_uri(), "/users/42/posts/Hello%20World");
+/// ```
+///
+/// To build a whole request (including the body, headers and cookies) from a
+/// route value, derive [`IntoRequest`] as well.
+///
+/// ## Listing routes
+///
+/// The generated implementation also overrides [`FromRequest::routes`], which
+/// returns a [`RouteInfo`] for every route (including implied `HEAD` routes
+/// and the routes of `#[forward]`ed types). This can be used to print all
+/// endpoints at startup or to check them in tests:
+///
+/// ```
+/// use hyperdrive::FromRequest;
+///
+/// #[derive(FromRequest)]
+/// enum Routes {
+///     #[get("/")]
+///     Index,
+///
+///     #[post("/users/{id}")]
+///     User { id: u32 },
+/// }
+///
+/// for route in Routes::routes() {
+///     println!("{} -> {}", route, route.variant());
+/// }
+///
+/// let paths = Routes::routes().iter().map(|r| r.to_string()).collect::<Vec<_>>();
+/// assert_eq!(paths, &["GET /", "POST /users/{id}", "HEAD /"]);
+/// ```
+///
+/// When the `openapi` cargo feature is enabled, an OpenAPI document can be
+/// generated from the same attributes by also deriving `Describe`. Refer to
+/// the [`openapi`] module for details.
+///
 /// [`AsyncService`]: service/struct.AsyncService.html
 /// [`SyncService`]: service/struct.SyncService.html
 /// [`FromBody`]: trait.FromBody.html
@@ -499,7 +1081,20 @@ pub type BoxedError = Box<dyn std::error::Error + Send + Sync>;
 /// [`NoContext`]: struct.NoContext.html
 /// [`DefaultFuture`]: type.DefaultFuture.html
 /// [`body`]: body/index.html
+/// [`body::NestedForm`]: body/struct.NestedForm.html
+/// [`guard::Cookies`]: guard/struct.Cookies.html
+/// [`guard::OriginalUri`]: guard/struct.OriginalUri.html
 /// [`from_request`]: #tymethod.from_request
+/// [`FromRequest::routes`]: #method.routes
+/// [`RouteInfo`]: struct.RouteInfo.html
+/// [`IntoRequest`]: trait.IntoRequest.html
+/// [`Raw`]: struct.Raw.html
+/// [`ToUri`]: trait.ToUri.html
+/// [`Error::location`]: struct.Error.html#method.location
+/// [`Error::allowed_methods`]: struct.Error.html#method.allowed_methods
+/// [`Error`]: struct.Error.html
+/// [`service::Cors`]: service/struct.Cors.html
+/// [`openapi`]: openapi/index.html
 pub trait FromRequest: Sized {
     /// A context parameter passed to [`from_request`].
     ///
@@ -594,6 +1189,22 @@ pub trait FromRequest: Sized {
         let mut rt = Runtime::new().expect("couldn't start single-threaded tokio runtime");
         rt.block_on(Self::from_request(request, context).into_future())
     }
+
+    /// Returns a description of all routes accepted by this type.
+    ///
+    /// The derive macro generates a table listing every route (including
+    /// implied `HEAD` routes) along with the routes of all `#[forward]`ed
+    /// types. This can be used to print the available endpoints at startup,
+    /// export them to other tools, or check them in tests.
+    ///
+    /// The provided default implementation returns an empty list, which is
+    /// appropriate for manual implementations that don't do any routing.
+    fn routes() -> &'static [RouteInfo]
+    where
+        Self: 'static,
+    {
+        &[]
+    }
 }
 
 /// A request guard that checks a condition or extracts data out of an incoming
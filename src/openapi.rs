@@ -0,0 +1,431 @@
+//! Generation of OpenAPI 3.0 documents.
+//!
+//! This module is only available when the `openapi` cargo feature is enabled.
+//! It provides `#[derive(Describe)]`, which reads the same attributes as
+//! `#[derive(FromRequest)]` and describes the routes of a type as a list of
+//! [`Operation`]s, and the [`OpenApi`] builder, which assembles these into a
+//! document.
+//!
+//! The derived implementation documents:
+//!
+//! * The method and path of every route (implied `HEAD` routes, the asterisk
+//!   path `*` and methods not supported by OpenAPI are left out).
+//! * Path parameters for all placeholders. Their schema is taken from the
+//!   [`JsonSchema`] implementation of the field type (or from the matching
+//!   property of the `#[path_params]` type), and regex constraints are
+//!   included as a `pattern`.
+//! * Query parameters for the properties of the `#[query_params]` type, which
+//!   must implement [`JsonSchema`].
+//! * A query parameter for every `#[query]` field, whose type (or the element
+//!   type of an `Option` or `Vec` field) must implement [`JsonSchema`].
+//! * The request body of the `#[body]` field, whose type must implement
+//!   [`BodySchema`] (this is the case for [`Json`], [`HtmlForm`] and
+//!   [`NestedForm`]).
+//! * The doc comment of the variant (or struct): its first paragraph becomes
+//!   the operation's `summary`, and the rest becomes its `description`.
+//! * The operations of all `#[forward]`ed types, which must implement
+//!   [`Describe`] themselves.
+//!
+//! Schemas are generated by the [`schemars`] crate, which provides a derive
+//! for [`JsonSchema`].
+//!
+//! # Examples
+//!
+//! ```
+//! use hyperdrive::{FromRequest, body::Json, openapi::{Describe, OpenApi}};
+//! use schemars::JsonSchema;
+//! use serde::Deserialize;
+//!
+//! #[derive(Deserialize, JsonSchema)]
+//! struct NewUser {
+//!     name: String,
+//! }
+//!
+//! #[derive(FromRequest, Describe)]
+//! enum Routes {
+//!     /// Fetches a user.
+//!     #[get("/users/{id}")]
+//!     User { id: u32 },
+//!
+//!     /// Creates a user.
+//!     #[post("/users")]
+//!     CreateUser {
+//!         #[body]
+//!         user: Json<NewUser>,
+//!     },
+//! }
+//!
+//! let doc = OpenApi::new("Users", "1.0.0").add::<Routes>().to_json();
+//! assert_eq!(doc["paths"]["/users/{id}"]["get"]["summary"], "Fetches a user.");
+//! assert_eq!(doc["paths"]["/users/{id}"]["get"]["parameters"][0]["in"], "path");
+//! assert!(doc["components"]["schemas"]["NewUser"].is_object());
+//! ```
+//!
+//! [`Operation`]: struct.Operation.html
+//! [`OpenApi`]: struct.OpenApi.html
+//! [`JsonSchema`]: ../../schemars/trait.JsonSchema.html
+//! [`BodySchema`]: trait.BodySchema.html
+//! [`Describe`]: trait.Describe.html
+//! [`Json`]: ../body/struct.Json.html
+//! [`HtmlForm`]: ../body/struct.HtmlForm.html
+//! [`NestedForm`]: ../body/struct.NestedForm.html
+//! [`schemars`]: https://docs.rs/schemars
+
+use crate::body::{HtmlForm, Json, NestedForm};
+use crate::Raw;
+use http::Method;
+use schemars::{
+    gen::{SchemaGenerator, SchemaSettings},
+    schema::Schema,
+    JsonSchema,
+};
+use serde::de::DeserializeOwned;
+use serde_json::{json, Map, Value};
+
+pub use hyperderive::Describe;
+#[doc(hidden)]
+pub use schemars;
+
+/// Trait for types that can describe their routes as OpenAPI operations.
+///
+/// This can be derived using `#[derive(Describe)]` on any type that also
+/// derives `FromRequest`. Refer to the [module documentation] for details.
+///
+/// [module documentation]: index.html
+pub trait Describe {
+    /// Returns the operations of all routes of `Self`.
+    ///
+    /// Schemas are generated using `gen`, which stores all schema definitions
+    /// that are referenced by the returned operations.
+    fn describe(gen: &mut SchemaGenerator) -> Vec<Operation>;
+}
+
+/// Trait for `#[body]` types that can describe the request body they accept.
+pub trait BodySchema {
+    /// Returns the media type and the schema of the accepted request body.
+    fn body_schema(gen: &mut SchemaGenerator) -> (&'static str, Schema);
+}
+
+impl<T: DeserializeOwned + Send + JsonSchema + 'static> BodySchema for Json<T> {
+    fn body_schema(gen: &mut SchemaGenerator) -> (&'static str, Schema) {
+        ("application/json", gen.subschema_for::<T>())
+    }
+}
+
+impl<T: DeserializeOwned + Send + JsonSchema + 'static> BodySchema for HtmlForm<T> {
+    fn body_schema(gen: &mut SchemaGenerator) -> (&'static str, Schema) {
+        ("application/x-www-form-urlencoded", gen.subschema_for::<T>())
+    }
+}
+
+impl<T: DeserializeOwned + Send + JsonSchema + 'static> BodySchema for NestedForm<T> {
+    fn body_schema(gen: &mut SchemaGenerator) -> (&'static str, Schema) {
+        ("application/x-www-form-urlencoded", gen.subschema_for::<T>())
+    }
+}
+
+/// `Raw` placeholders are documented like the type they wrap.
+impl<T: JsonSchema> JsonSchema for Raw<T> {
+    fn is_referenceable() -> bool {
+        T::is_referenceable()
+    }
+
+    fn schema_name() -> String {
+        T::schema_name()
+    }
+
+    fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+        T::json_schema(gen)
+    }
+}
+
+/// An OpenAPI operation, describing a single route.
+#[derive(Debug, Clone)]
+pub struct Operation {
+    method: Method,
+    path: String,
+    variants: Vec<&'static str>,
+    summary: Option<&'static str>,
+    description: Option<&'static str>,
+    parameters: Vec<Value>,
+    request_body: Option<Value>,
+}
+
+impl Operation {
+    #[doc(hidden)]
+    pub fn new(method: Method, path: &str, variant: &'static str) -> Self {
+        Self {
+            method,
+            path: path.to_string(),
+            variants: vec![variant],
+            summary: None,
+            description: None,
+            parameters: Vec::new(),
+            request_body: None,
+        }
+    }
+
+    #[doc(hidden)]
+    pub fn summary(mut self, summary: &'static str) -> Self {
+        self.summary = Some(summary);
+        self
+    }
+
+    #[doc(hidden)]
+    pub fn description(mut self, description: &'static str) -> Self {
+        self.description = Some(description);
+        self
+    }
+
+    #[doc(hidden)]
+    pub fn path_param<T: JsonSchema>(
+        mut self,
+        gen: &mut SchemaGenerator,
+        name: &str,
+        pattern: Option<&str>,
+    ) -> Self {
+        let schema = to_json(&gen.subschema_for::<T>());
+        self.push_path_param(name, schema, pattern);
+        self
+    }
+
+    /// Adds a path parameter for a placeholder that is only stored in the
+    /// `#[path_params]` field of type `T`, using the schema of the property
+    /// `name` of `T`.
+    #[doc(hidden)]
+    pub fn path_param_of<T: JsonSchema>(
+        mut self,
+        gen: &mut SchemaGenerator,
+        name: &str,
+        pattern: Option<&str>,
+    ) -> Self {
+        let property = match T::json_schema(gen) {
+            Schema::Object(schema) => schema
+                .object
+                .and_then(|object| object.properties.get(name).cloned()),
+            Schema::Bool(_) => None,
+        };
+        let schema = match property {
+            Some(property) => to_json(&property),
+            None => to_json(&gen.subschema_for::<String>()),
+        };
+        self.push_path_param(name, schema, pattern);
+        self
+    }
+
+    fn push_path_param(&mut self, name: &str, mut schema: Value, pattern: Option<&str>) {
+        if let (Some(pattern), Some(schema)) = (pattern, schema.as_object_mut()) {
+            schema.insert("pattern".into(), pattern.into());
+        }
+
+        self.parameters.push(json!({
+            "name": name,
+            "in": "path",
+            "required": true,
+            "schema": schema,
+        }));
+    }
+
+    /// Adds a query parameter for every property of `T`.
+    ///
+    /// If `T` isn't a struct, a single `form`-style parameter is added instead.
+    #[doc(hidden)]
+    pub fn query_params<T: JsonSchema>(mut self, gen: &mut SchemaGenerator) -> Self {
+        let schema = match T::json_schema(gen) {
+            Schema::Object(schema) => schema,
+            Schema::Bool(_) => return self,
+        };
+
+        match &schema.object {
+            Some(object) if !object.properties.is_empty() => {
+                for (name, property) in &object.properties {
+                    self.parameters.push(json!({
+                        "name": name,
+                        "in": "query",
+                        "required": object.required.contains(name),
+                        "schema": to_json(property),
+                    }));
+                }
+            }
+            _ => self.parameters.push(json!({
+                "name": T::schema_name(),
+                "in": "query",
+                "style": "form",
+                "explode": true,
+                "schema": to_json(&Schema::Object(schema)),
+            })),
+        }
+        self
+    }
+
+    /// Adds the query parameter of a `#[query]` field.
+    #[doc(hidden)]
+    pub fn query_param<T: JsonSchema>(
+        mut self,
+        gen: &mut SchemaGenerator,
+        name: &str,
+        required: bool,
+    ) -> Self {
+        self.parameters.push(json!({
+            "name": name,
+            "in": "query",
+            "required": required,
+            "schema": to_json(&gen.subschema_for::<T>()),
+        }));
+        self
+    }
+
+    #[doc(hidden)]
+    pub fn body<T: BodySchema>(mut self, gen: &mut SchemaGenerator) -> Self {
+        let (media_type, schema) = T::body_schema(gen);
+        self.request_body = Some(json!({
+            "required": true,
+            "content": {
+                media_type: { "schema": to_json(&schema) },
+            },
+        }));
+        self
+    }
+
+    /// Returns a copy of `self` as seen from a type that forwards to it from
+    /// `variant`, mounted under `prefix`.
+    #[doc(hidden)]
+    pub fn nested(mut self, variant: &'static str, prefix: &str) -> Self {
+        self.path = format!("{}{}", prefix, self.path);
+        self.variants.insert(0, variant);
+        self
+    }
+
+    /// Returns the HTTP method of this operation.
+    pub fn method(&self) -> &Method {
+        &self.method
+    }
+
+    /// Returns the path template of this operation (for example
+    /// `/users/{id}`).
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Returns the names of all variants involved in constructing the route,
+    /// starting with the outermost type.
+    pub fn variants(&self) -> &[&'static str] {
+        &self.variants
+    }
+
+    /// Returns the OpenAPI Operation Object describing this operation.
+    pub fn to_json(&self) -> Value {
+        let mut operation = Map::new();
+        if let Some(summary) = self.summary {
+            operation.insert("summary".into(), summary.into());
+        }
+        if let Some(description) = self.description {
+            operation.insert("description".into(), description.into());
+        }
+        if !self.parameters.is_empty() {
+            operation.insert("parameters".into(), self.parameters.clone().into());
+        }
+        if let Some(body) = &self.request_body {
+            operation.insert("requestBody".into(), body.clone());
+        }
+        operation.insert(
+            "responses".into(),
+            json!({ "default": { "description": "" } }),
+        );
+        operation.into()
+    }
+}
+
+/// Builder for an OpenAPI 3.0 document.
+///
+/// Types implementing [`Describe`] can be added with [`add`], and the
+/// finished document can be obtained by calling [`to_json`].
+///
+/// When multiple operations share a path and method (which can happen with
+/// `#[routing(fallthrough)]` or overlapping ranked routes), only the first one
+/// is included in the document.
+///
+/// [`Describe`]: trait.Describe.html
+/// [`add`]: #method.add
+/// [`to_json`]: #method.to_json
+#[derive(Debug)]
+pub struct OpenApi {
+    title: String,
+    version: String,
+    gen: SchemaGenerator,
+    paths: Map<String, Value>,
+}
+
+impl OpenApi {
+    /// Creates a document with the given API title and version, and no
+    /// operations.
+    pub fn new(title: &str, version: &str) -> Self {
+        Self {
+            title: title.to_string(),
+            version: version.to_string(),
+            gen: SchemaSettings::openapi3().into_generator(),
+            paths: Map::new(),
+        }
+    }
+
+    /// Adds the operations of `T` to the document.
+    pub fn add<T: Describe>(&mut self) -> &mut Self {
+        for operation in T::describe(&mut self.gen) {
+            self.add_operation(&operation);
+        }
+        self
+    }
+
+    /// Adds a single operation to the document.
+    pub fn add_operation(&mut self, operation: &Operation) -> &mut Self {
+        let method = match operation.method {
+            Method::GET => "get",
+            Method::PUT => "put",
+            Method::POST => "post",
+            Method::DELETE => "delete",
+            Method::OPTIONS => "options",
+            Method::HEAD => "head",
+            Method::PATCH => "patch",
+            Method::TRACE => "trace",
+            // OpenAPI can't describe other methods
+            _ => return self,
+        };
+
+        let path_item = self
+            .paths
+            .entry(operation.path.clone())
+            .or_insert_with(|| Map::new().into())
+            .as_object_mut()
+            .expect("path item is always an object");
+        if !path_item.contains_key(method) {
+            path_item.insert(method.into(), operation.to_json());
+        }
+        self
+    }
+
+    /// Returns the document as a JSON value.
+    pub fn to_json(&self) -> Value {
+        let schemas = self
+            .gen
+            .definitions()
+            .iter()
+            .map(|(name, schema)| (name.clone(), to_json(schema)))
+            .collect::<Map<_, _>>();
+
+        json!({
+            "openapi": "3.0.3",
+            "info": {
+                "title": self.title,
+                "version": self.version,
+            },
+            "paths": self.paths,
+            "components": {
+                "schemas": schemas,
+            },
+        })
+    }
+}
+
+fn to_json(schema: &Schema) -> Value {
+    serde_json::to_value(schema).expect("couldn't serialize JSON schema")
+}
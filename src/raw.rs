@@ -0,0 +1,79 @@
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+use std::str::FromStr;
+
+/// Wrapper for path placeholders that should not be percent-decoded.
+///
+/// By default, the values of path placeholders are percent-decoded before
+/// they're converted using `FromStr`. When the type of a placeholder field is
+/// `Raw<T>`, the value is instead passed to `T`'s `FromStr` implementation
+/// exactly as it appears in the request path.
+///
+/// When building URIs (or requests using [`IntoRequest`]), the `Display`
+/// output of `Raw` placeholders is inserted without percent-encoding it.
+///
+/// # Examples
+///
+/// ```
+/// use hyperdrive::{FromRequest, Raw, hyper::Body};
+///
+/// #[derive(FromRequest)]
+/// enum Routes {
+///     #[get("/users/{name}")]
+///     User { name: String },
+///
+///     #[get("/files/{path...}")]
+///     File { path: Raw<String> },
+/// }
+///
+/// let request = http::Request::get("/users/J%C3%BCrgen").body(Body::empty()).unwrap();
+/// match Routes::from_request_sync(request, hyperdrive::NoContext).unwrap() {
+///     Routes::User { name } => assert_eq!(name, "Jürgen"),
+///     _ => unreachable!(),
+/// }
+///
+/// let request = http::Request::get("/files/a%2Fb/c").body(Body::empty()).unwrap();
+/// match Routes::from_request_sync(request, hyperdrive::NoContext).unwrap() {
+///     Routes::File { path } => assert_eq!(*path, "a%2Fb/c"),
+///     _ => unreachable!(),
+/// }
+/// ```
+///
+/// [`IntoRequest`]: trait.IntoRequest.html
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Raw<T>(pub T);
+
+impl<T> Raw<T> {
+    /// Returns the wrapped value.
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T: FromStr> FromStr for Raw<T> {
+    type Err = T::Err;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        T::from_str(s).map(Raw)
+    }
+}
+
+impl<T: fmt::Display> fmt::Display for Raw<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl<T> Deref for Raw<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> DerefMut for Raw<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
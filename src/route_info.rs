@@ -0,0 +1,190 @@
+use http::Method;
+use lazy_static::lazy_static;
+use std::{any::TypeId, collections::HashMap, fmt, sync::Mutex};
+
+/// Describes a route accepted by a [`FromRequest`] implementation.
+///
+/// A list of `RouteInfo`s can be obtained by calling [`FromRequest::routes`].
+/// The derive macro generates this list from the `#[route]`-style attributes,
+/// including the routes of any `#[forward]`ed types.
+///
+/// For routes of a `#[forward]`ed type, the `has_*` methods describe the
+/// innermost variant, the one named by [`variant`].
+///
+/// The `Display` implementation prints the method and path pattern, for
+/// example `GET /users/{id}`.
+///
+/// [`FromRequest`]: trait.FromRequest.html
+/// [`FromRequest::routes`]: trait.FromRequest.html#method.routes
+/// [`variant`]: #method.variant
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RouteInfo {
+    method: Method,
+    path: String,
+    placeholders: Vec<PlaceholderInfo>,
+    variants: Vec<&'static str>,
+    body: bool,
+    query_params: bool,
+    forward: bool,
+    implied_head: bool,
+}
+
+impl RouteInfo {
+    #[doc(hidden)]
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        method: Method,
+        path: &str,
+        placeholders: Vec<PlaceholderInfo>,
+        variant: &'static str,
+        body: bool,
+        query_params: bool,
+        forward: bool,
+        implied_head: bool,
+    ) -> Self {
+        Self {
+            method,
+            path: path.to_string(),
+            placeholders,
+            variants: vec![variant],
+            body,
+            query_params,
+            forward,
+            implied_head,
+        }
+    }
+
+    /// Returns a copy of `self` as seen from a type that forwards to it from
+    /// `variant`, mounted under `prefix`.
+    #[doc(hidden)]
+    pub fn nested(&self, variant: &'static str, prefix: &str) -> Self {
+        let mut info = self.clone();
+        info.path = format!("{}{}", prefix, self.path);
+        info.variants.insert(0, variant);
+        info
+    }
+
+    /// Returns the route table of `T`, building it with `build` the first
+    /// time.
+    ///
+    /// The table is leaked, so that it can be handed out as a `'static` slice.
+    #[doc(hidden)]
+    pub fn cached<T: 'static, F>(build: F) -> &'static [RouteInfo]
+    where
+        F: FnOnce() -> Vec<RouteInfo>,
+    {
+        lazy_static! {
+            static ref TABLES: Mutex<HashMap<TypeId, &'static [RouteInfo]>> =
+                Mutex::new(HashMap::new());
+        }
+
+        let id = TypeId::of::<T>();
+        if let Some(table) = TABLES.lock().unwrap().get(&id) {
+            return table;
+        }
+
+        // Don't hold the lock while building: the table of a type includes the
+        // tables of the types it forwards to.
+        let table = build();
+        TABLES
+            .lock()
+            .unwrap()
+            .entry(id)
+            .or_insert_with(|| Box::leak(table.into_boxed_slice()))
+    }
+
+    /// Returns the HTTP method of this route.
+    pub fn method(&self) -> &Method {
+        &self.method
+    }
+
+    /// Returns the path pattern of this route, as written in the route
+    /// attribute (for example `/users/{id}`).
+    ///
+    /// For routes of a type mounted via `#[forward(prefix = "...")]`, this
+    /// includes the prefix.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Returns the placeholders in the path, in the order they appear.
+    pub fn placeholders(&self) -> &[PlaceholderInfo] {
+        &self.placeholders
+    }
+
+    /// Returns the name of the variant (or struct) that this route constructs.
+    ///
+    /// For routes of a `#[forward]`ed type, this is the name of the variant in
+    /// the innermost type. Use [`variants`] to get the whole chain.
+    ///
+    /// [`variants`]: #method.variants
+    pub fn variant(&self) -> &'static str {
+        self.variants[self.variants.len() - 1]
+    }
+
+    /// Returns the names of all variants involved in constructing this route,
+    /// starting with the outermost type.
+    ///
+    /// For a route that isn't reached through a `#[forward]` field, this
+    /// contains a single name.
+    pub fn variants(&self) -> &[&'static str] {
+        &self.variants
+    }
+
+    /// Returns whether the variant has a `#[body]` field.
+    pub fn has_body(&self) -> bool {
+        self.body
+    }
+
+    /// Returns whether the variant has a `#[query_params]` field.
+    pub fn has_query_params(&self) -> bool {
+        self.query_params
+    }
+
+    /// Returns whether the variant has a `#[forward]` field.
+    pub fn has_forward(&self) -> bool {
+        self.forward
+    }
+
+    /// Returns whether this is a `HEAD` route that was implied by a `GET`
+    /// route instead of being declared explicitly.
+    pub fn is_implied_head(&self) -> bool {
+        self.implied_head
+    }
+}
+
+impl fmt::Display for RouteInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.method, self.path)
+    }
+}
+
+/// Describes a placeholder in the path of a [`RouteInfo`].
+///
+/// [`RouteInfo`]: struct.RouteInfo.html
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlaceholderInfo {
+    name: &'static str,
+    ty: &'static str,
+}
+
+impl PlaceholderInfo {
+    #[doc(hidden)]
+    pub fn new(name: &'static str, ty: &'static str) -> Self {
+        Self { name, ty }
+    }
+
+    /// Returns the name of the placeholder (and of the field it is stored in,
+    /// if any).
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Returns the type of the field, as written in the source code.
+    ///
+    /// For placeholders without a field of their own, this is the type of the
+    /// `#[path_params]` field.
+    pub fn ty(&self) -> &'static str {
+        self.ty
+    }
+}
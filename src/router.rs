@@ -0,0 +1,632 @@
+//! Support code for the path matcher generated by `#[derive(FromRequest)]`.
+
+use crate::{guard::OriginalUri, BoxedError, Error};
+use futures::{Async, Poll, Stream};
+use http::{Method, StatusCode};
+use hyper::{Body, Chunk};
+use regex::Regex;
+use serde::de::DeserializeOwned;
+use std::borrow::Cow;
+use std::string::FromUtf8Error;
+use std::sync::{Arc, Mutex};
+
+/// A piece of a path segment that mixes literal text and placeholders (such
+/// as `{id}.{format}`).
+#[doc(hidden)]
+#[derive(Debug, Clone, Copy)]
+pub enum SegmentPart<'a> {
+    /// Literal text that must match exactly.
+    Literal(&'static str),
+    /// A placeholder matching a nonempty string, or anything matched by its
+    /// (anchored) regex constraint.
+    Placeholder(Option<&'a Regex>),
+}
+
+/// Matches `segment` against `parts`, storing the values of all placeholders
+/// in `captures`.
+///
+/// Like in a regular expression, placeholders are greedy: When there are
+/// multiple ways to split `segment`, earlier placeholders match as much as
+/// possible.
+///
+/// Returns `false` if `segment` doesn't match. The contents of `captures` are
+/// unspecified in that case.
+#[doc(hidden)]
+pub fn match_segment<'s>(
+    segment: &'s str,
+    parts: &[SegmentPart<'_>],
+    captures: &mut [&'s str],
+) -> bool {
+    let (part, parts) = match parts.split_first() {
+        Some(split) => split,
+        None => return segment.is_empty(),
+    };
+
+    match part {
+        SegmentPart::Literal(literal) => {
+            segment.starts_with(literal)
+                && match_segment(&segment[literal.len()..], parts, captures)
+        }
+        SegmentPart::Placeholder(constraint) => {
+            let (capture, captures) = captures
+                .split_first_mut()
+                .expect("more placeholders than captures");
+
+            // Try the longest match first
+            for end in (0..=segment.len()).rev() {
+                if !segment.is_char_boundary(end) {
+                    continue;
+                }
+
+                let value = &segment[..end];
+                let accepted = match constraint {
+                    Some(regex) => regex.is_match(value),
+                    None => !value.is_empty(),
+                };
+                if accepted && match_segment(&segment[end..], parts, captures) {
+                    *capture = value;
+                    return true;
+                }
+            }
+
+            false
+        }
+    }
+}
+
+/// Percent-decodes the value of a path placeholder.
+///
+/// `%` characters that don't start a valid escape sequence are kept as-is.
+/// Fails if the decoded bytes aren't valid UTF-8.
+#[doc(hidden)]
+pub fn percent_decode(value: &str) -> Result<Cow<'_, str>, FromUtf8Error> {
+    if !value.contains('%') {
+        return Ok(Cow::Borrowed(value));
+    }
+
+    fn hex(byte: u8) -> Option<u8> {
+        match byte {
+            b'0'..=b'9' => Some(byte - b'0'),
+            b'a'..=b'f' => Some(byte - b'a' + 10),
+            b'A'..=b'F' => Some(byte - b'A' + 10),
+            _ => None,
+        }
+    }
+
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escape = match bytes.get(i..i + 3) {
+            Some(&[b'%', hi, lo]) => hex(hi).and_then(|hi| hex(lo).map(|lo| (hi << 4) | lo)),
+            _ => None,
+        };
+        match escape {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+
+    String::from_utf8(decoded).map(Cow::Owned)
+}
+
+/// Deserializes the values of all placeholders of a route into a
+/// `#[path_params]` field.
+///
+/// `placeholders` contains the names and captured (still percent-encoded)
+/// values. Fails with `400 Bad Request` if a value doesn't decode to valid
+/// UTF-8, and with `404 Not Found` if the values can't be deserialized (like a
+/// placeholder that fails to parse).
+#[doc(hidden)]
+pub fn decode_path<T: DeserializeOwned>(placeholders: &[(&str, &str)]) -> Result<T, Error> {
+    let mut values = Vec::with_capacity(placeholders.len());
+    for &(name, value) in placeholders {
+        let value = percent_decode(value)
+            .map_err(|e| Error::with_source(StatusCode::BAD_REQUEST, e))?;
+        values.push((name.to_string(), value.into_owned()));
+    }
+
+    crate::form::decode_values(values).map_err(|e| Error::with_source(StatusCode::NOT_FOUND, e))
+}
+
+/// Creates the error returned when the request path matches a route, but the
+/// request method is not accepted by any of the routes for that path.
+///
+/// `OPTIONS` requests are answered by listing the `allowed` methods (and
+/// `OPTIONS` itself), other methods result in `405 Method Not Allowed`.
+#[doc(hidden)]
+pub fn method_not_accepted(method: &Method, mut allowed: Vec<&'static Method>) -> Error {
+    if *method == Method::OPTIONS && !allowed.is_empty() {
+        if !allowed.contains(&&Method::OPTIONS) {
+            allowed.push(&Method::OPTIONS);
+        }
+        Error::options(allowed)
+    } else {
+        Error::wrong_method(allowed)
+    }
+}
+
+/// Holds the request body while multiple fallback variants are tried in order.
+///
+/// A fallback variant only receives a *lent* body, which takes the request
+/// body out of the slot once it is read. If the variant fails without reading
+/// it, the next one can still get the body.
+#[doc(hidden)]
+#[derive(Debug)]
+pub struct BodySlot {
+    body: Arc<Mutex<Option<Body>>>,
+}
+
+impl BodySlot {
+    pub fn new(body: Body) -> Self {
+        Self {
+            body: Arc::new(Mutex::new(Some(body))),
+        }
+    }
+
+    /// Returns a body that forwards to the request body, taking it out of the
+    /// slot when it is first polled.
+    pub fn lend(&self) -> Body {
+        Body::wrap_stream(LentBody {
+            slot: Arc::clone(&self.body),
+            body: None,
+        })
+    }
+
+    /// Takes the request body out of the slot (for the last fallback
+    /// variant).
+    ///
+    /// Returns an empty body if it was already read.
+    pub fn take(&self) -> Body {
+        self.body.lock().unwrap().take().unwrap_or_else(Body::empty)
+    }
+
+    /// Returns whether the next fallback variant should be tried after the
+    /// previous one failed with `error`.
+    ///
+    /// This is the case if the previous variant didn't accept the path (`404`)
+    /// or method (`405`, or a response to an `OPTIONS` request), and didn't
+    /// read the request body.
+    pub fn can_retry(&self, error: &BoxedError) -> bool {
+        is_unrouted(error) && self.body.lock().unwrap().is_some()
+    }
+}
+
+/// Returns whether `error` means that a variant didn't accept the path (`404`)
+/// or method (`405`, or a response to an `OPTIONS` request) of a request.
+fn is_unrouted(error: &BoxedError) -> bool {
+    match error.downcast_ref::<Error>() {
+        Some(error) => {
+            error.http_status() == StatusCode::NOT_FOUND || error.allowed_methods().is_some()
+        }
+        None => false,
+    }
+}
+
+/// A body lent out by a `BodySlot`.
+struct LentBody {
+    slot: Arc<Mutex<Option<Body>>>,
+    body: Option<Body>,
+}
+
+impl Stream for LentBody {
+    type Item = Chunk;
+    type Error = hyper::Error;
+
+    fn poll(&mut self) -> Poll<Option<Chunk>, hyper::Error> {
+        if self.body.is_none() {
+            match self.slot.lock().unwrap().take() {
+                Some(body) => self.body = Some(body),
+                // The body was already read by another fallback variant
+                None => return Ok(Async::Ready(None)),
+            }
+        }
+
+        self.body.as_mut().unwrap().poll()
+    }
+}
+
+/// Combines the errors of two fallback variants that were tried in order.
+///
+/// If both variants only rejected the request method, their allowed methods
+/// are merged. If one of them didn't find the path, the other error is
+/// returned. Otherwise, the error of the later variant is used.
+#[doc(hidden)]
+pub fn merge_fallback_errors(
+    method: &Method,
+    previous: BoxedError,
+    next: BoxedError,
+) -> BoxedError {
+    let previous_methods = match previous.downcast_ref::<Error>() {
+        Some(error) => match error.allowed_methods() {
+            Some(methods) => methods.to_vec(),
+            None => return next,
+        },
+        None => return next,
+    };
+
+    let merged = match next.downcast_ref::<Error>() {
+        Some(error) => match error.allowed_methods() {
+            Some(methods) => {
+                let mut merged = previous_methods;
+                for method in methods {
+                    if !merged.contains(method) {
+                        merged.push(method);
+                    }
+                }
+                merged
+            }
+            None if error.http_status() == StatusCode::NOT_FOUND => return previous,
+            None => return next,
+        },
+        None => return next,
+    };
+
+    Box::new(method_not_accepted(method, merged))
+}
+
+/// Returns the error to respond with after a route matched everything about a
+/// request except its media types, and the fallback variants failed with
+/// `error`.
+///
+/// If the fallback variants didn't accept the request either, it is rejected
+/// with `status` (`415 Unsupported Media Type` or `406 Not Acceptable`).
+#[doc(hidden)]
+pub fn media_type_fallback_error(status: StatusCode, error: BoxedError) -> BoxedError {
+    if is_unrouted(&error) {
+        Box::new(Error::from_status(status))
+    } else {
+        error
+    }
+}
+
+/// The decoded query parameters of a request, used by `#[query]` fields.
+#[doc(hidden)]
+#[derive(Debug)]
+pub struct QueryParams {
+    params: Vec<(String, String)>,
+}
+
+impl QueryParams {
+    /// Decodes the query string of `request`.
+    ///
+    /// Fails with `400 Bad Request` if the query string is malformed.
+    pub fn from_request<B>(request: &http::Request<B>) -> Result<Self, Error> {
+        let query = request.uri().query().unwrap_or("");
+        serde_urlencoded::from_str(query)
+            .map(|params| Self { params })
+            .map_err(|e| Error::with_source(StatusCode::BAD_REQUEST, e))
+    }
+
+    /// Returns the first value of the parameter `name`.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| &value[..])
+    }
+
+    /// Returns all values of the parameter `name`, in order.
+    pub fn get_all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.params
+            .iter()
+            .filter(move |(key, _)| key == name)
+            .map(|(_, value)| &value[..])
+    }
+}
+
+/// Returns the host name a request was sent to, for matching against host
+/// patterns.
+///
+/// The host is taken from the request URI (if it is in absolute form) or the
+/// `Host` header. The port and a trailing `.` are removed, and the name is
+/// converted to lowercase. Returns an empty string if the request has no host.
+#[doc(hidden)]
+pub fn request_host<B>(request: &http::Request<B>) -> String {
+    let host = match request.uri().host() {
+        Some(host) => host,
+        None => match request.headers().get(http::header::HOST) {
+            Some(value) => match value.to_str() {
+                Ok(value) => strip_port(value),
+                Err(_) => "",
+            },
+            None => "",
+        },
+    };
+
+    host.trim_end_matches('.').to_lowercase()
+}
+
+/// Removes the `:port` suffix from the value of a `Host` header.
+fn strip_port(host: &str) -> &str {
+    // IPv6 addresses are enclosed in brackets and contain colons themselves
+    let start = host.rfind(']').unwrap_or(0);
+    match host[start..].find(':') {
+        Some(pos) => &host[..start + pos],
+        None => host,
+    }
+}
+
+/// Returns whether the `Content-Type` of `request` matches `media` (a
+/// lowercase `type/subtype` or `type/*`).
+///
+/// Parameters (such as `charset`) are ignored. Requests without a
+/// `Content-Type` don't match any media type.
+#[doc(hidden)]
+pub fn content_type_matches<B>(request: &http::Request<B>, media: &str) -> bool {
+    let content_type = match request.headers().get(http::header::CONTENT_TYPE) {
+        Some(value) => match value.to_str() {
+            Ok(value) => value,
+            Err(_) => return false,
+        },
+        None => return false,
+    };
+
+    let essence = content_type.split(';').next().unwrap_or("").trim();
+    media_types_match(&essence.to_lowercase(), media)
+}
+
+/// How much a client prefers a media type produced by a route, according to
+/// the `Accept` header of its request.
+///
+/// Preferences compare by quality first, then by how specific the matching
+/// media range of the `Accept` header is, then by how specific the media type
+/// of the route is.
+#[doc(hidden)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct MediaPreference {
+    /// The `q` parameter in thousandths.
+    quality: u16,
+    range_specificity: u8,
+    media_specificity: u8,
+}
+
+impl MediaPreference {
+    /// The preference for routes that don't restrict the media type they
+    /// produce: fully acceptable, but least specific.
+    pub const UNRESTRICTED: Self = Self {
+        quality: 1000,
+        range_specificity: 0,
+        media_specificity: 0,
+    };
+}
+
+/// Returns how much the `Accept` header of `request` prefers a response of
+/// type `media` (a lowercase `type/subtype` or `type/*`).
+///
+/// The most specific media range matching `media` decides, and it must not
+/// have a quality of 0, or `None` is returned. Requests without an `Accept`
+/// header accept any media type.
+#[doc(hidden)]
+pub fn accept_preference<B>(request: &http::Request<B>, media: &str) -> Option<MediaPreference> {
+    let media_specificity = specificity(media);
+    let mut headers = request
+        .headers()
+        .get_all(http::header::ACCEPT)
+        .iter()
+        .peekable();
+    if headers.peek().is_none() {
+        return Some(MediaPreference {
+            media_specificity,
+            ..MediaPreference::UNRESTRICTED
+        });
+    }
+
+    let mut best: Option<MediaPreference> = None;
+    for range in headers
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+    {
+        let mut params = range.split(';');
+        let range = params.next().unwrap_or("").trim().to_lowercase();
+        if range.is_empty() || !media_types_match(&range, media) {
+            continue;
+        }
+
+        let quality = params
+            .find_map(|param| {
+                let mut kv = param.splitn(2, '=');
+                match (kv.next(), kv.next()) {
+                    (Some(key), Some(value)) if key.trim().eq_ignore_ascii_case("q") => {
+                        value.trim().parse::<f32>().ok()
+                    }
+                    _ => None,
+                }
+            })
+            .unwrap_or(1.0);
+        let quality = if quality > 1.0 {
+            1000
+        } else if quality > 0.0 {
+            (quality * 1000.0).round() as u16
+        } else {
+            0
+        };
+        let preference = MediaPreference {
+            quality,
+            range_specificity: specificity(&range),
+            media_specificity,
+        };
+
+        // Among equally specific ranges, the client's highest quality counts
+        let better = match best {
+            Some(best) => {
+                preference.range_specificity > best.range_specificity
+                    || (preference.range_specificity == best.range_specificity
+                        && preference.quality > best.quality)
+            }
+            None => true,
+        };
+        if better {
+            best = Some(preference);
+        }
+    }
+
+    match best {
+        Some(best) if best.quality > 0 => Some(best),
+        _ => None,
+    }
+}
+
+/// Returns 0 for `*/*`, 1 for `type/*` and 2 for `type/subtype`.
+fn specificity(media: &str) -> u8 {
+    if media == "*/*" {
+        0
+    } else if media.ends_with("/*") {
+        1
+    } else {
+        2
+    }
+}
+
+/// Returns whether the media types (or ranges) `a` and `b` have a media type
+/// in common.
+fn media_types_match(a: &str, b: &str) -> bool {
+    fn split(media: &str) -> (&str, &str) {
+        let mut parts = media.splitn(2, '/');
+        (
+            parts.next().unwrap_or("").trim(),
+            parts.next().unwrap_or("").trim(),
+        )
+    }
+    let (a_type, a_subtype) = split(a);
+    let (b_type, b_subtype) = split(b);
+
+    let matches = |a: &str, b: &str| a == b || a == "*" || b == "*";
+    matches(a_type, b_type) && matches(a_subtype, b_subtype)
+}
+
+/// How `#[routing(trailing_slash = "...")]` treats request paths that are not
+/// in their canonical form.
+#[doc(hidden)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrailingSlash {
+    /// Normalize the path, but require an exact match of trailing slashes.
+    Strict,
+    /// Normalize the path, and also match routes with(out) a trailing slash if
+    /// the path itself doesn't match.
+    Ignore,
+    /// Like `Ignore`, but redirect to the canonical path instead of routing
+    /// the request.
+    Redirect,
+}
+
+/// Normalizes the path of `request` according to `policy`.
+///
+/// `is_route` must return whether a path is matched by any route. In the
+/// `Ignore` and `Redirect` modes, it is used to determine whether a trailing
+/// slash should be added or removed.
+///
+/// Returns `Ok(None)` if `request` can be routed as-is, and a copy of
+/// `request` with a canonical path if it can not. Returns an error if the path
+/// can't be normalized, and a redirect in `Redirect` mode.
+///
+/// The copy is only used to match routes and is passed to `#[forward]`ed
+/// types. Guards and fields of the matched route still get `request` itself.
+#[doc(hidden)]
+pub fn normalize_request<F>(
+    request: &http::Request<()>,
+    policy: TrailingSlash,
+    is_route: F,
+) -> Result<Option<http::Request<()>>, Error>
+where
+    F: Fn(&str) -> bool,
+{
+    let path = request.uri().path();
+    let mut canonical = match normalize_path(path) {
+        Some(canonical) => canonical,
+        None => {
+            return Err(Error::with_source(
+                StatusCode::BAD_REQUEST,
+                "request path contains a `..` segment leading outside of the root",
+            ));
+        }
+    };
+
+    if policy != TrailingSlash::Strict && canonical != "/" && !is_route(&canonical) {
+        let alternative = if canonical.ends_with('/') {
+            // There are no duplicate slashes left, so this removes only one
+            canonical.trim_end_matches('/').to_string()
+        } else {
+            format!("{}/", canonical)
+        };
+        if is_route(&alternative) {
+            canonical = Cow::Owned(alternative);
+        }
+    }
+
+    if canonical == path {
+        return Ok(None);
+    }
+
+    if policy == TrailingSlash::Redirect {
+        // If we're mounted under a prefix, it was stripped from `path`. Put it back.
+        let original = OriginalUri::of(request);
+        let original = original.path();
+        let prefix_len = original.len().saturating_sub(path.len());
+        let prefix = if original.get(prefix_len..) == Some(path) {
+            &original[..prefix_len]
+        } else if path == "/" {
+            original
+        } else {
+            ""
+        };
+
+        let location = match request.uri().query() {
+            Some(query) => format!("{}{}?{}", prefix, canonical, query),
+            None => format!("{}{}", prefix, canonical),
+        };
+        let location = location
+            .parse()
+            .expect("normalizing the path produced an invalid URI");
+        Err(Error::redirect(location))
+    } else {
+        Ok(Some(OriginalUri::with_path(request, &canonical)))
+    }
+}
+
+/// Collapses duplicate slashes and resolves `.` and `..` segments in `path`.
+///
+/// Returns `None` if a `..` segment would lead outside of the root. Paths not
+/// starting with `/` (like `*`) are returned unchanged.
+fn normalize_path(path: &str) -> Option<Cow<'_, str>> {
+    if !path.starts_with('/') {
+        return Some(Cow::Borrowed(path));
+    }
+
+    let mut segments = Vec::new();
+    let mut trailing_slash = false;
+    for segment in path[1..].split('/') {
+        trailing_slash = true;
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop()?;
+            }
+            _ => {
+                segments.push(segment);
+                trailing_slash = false;
+            }
+        }
+    }
+
+    let mut normalized = String::with_capacity(path.len());
+    for segment in &segments {
+        normalized.push('/');
+        normalized.push_str(segment);
+    }
+    if trailing_slash || segments.is_empty() {
+        normalized.push('/');
+    }
+
+    if normalized == path {
+        Some(Cow::Borrowed(path))
+    } else {
+        Some(Cow::Owned(normalized))
+    }
+}
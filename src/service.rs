@@ -8,14 +8,22 @@
 //!   [`FromRequest`] as the main entry point of your app.
 //! * [`ServiceExt`] provides adapter methods on Hyper `Service`s that simplify
 //!   common patterns like catching panics.
+//! * [`Cors`] configures how [`AsyncService`] and [`SyncService`] answer
+//!   cross-origin requests.
 //!
 //! [`AsyncService`]: struct.AsyncService.html
 //! [`SyncService`]: struct.SyncService.html
 //! [`ServiceExt`]: trait.ServiceExt.html
+//! [`Cors`]: struct.Cors.html
 //! [`FromRequest`]: ../trait.FromRequest.html
 
 use crate::{BoxedError, DefaultFuture, Error, FromRequest, NoContext};
 use futures::{future::FutureResult, Future, IntoFuture};
+use http::header::{
+    HeaderName, HeaderValue, ACCESS_CONTROL_ALLOW_CREDENTIALS, ACCESS_CONTROL_ALLOW_HEADERS,
+    ACCESS_CONTROL_ALLOW_METHODS, ACCESS_CONTROL_ALLOW_ORIGIN, ACCESS_CONTROL_MAX_AGE,
+    ACCESS_CONTROL_REQUEST_METHOD, ALLOW, ORIGIN, VARY,
+};
 use hyper::{
     service::{MakeService, Service},
     Body, Method, Request, Response,
@@ -24,6 +32,7 @@ use std::any::Any;
 use std::fmt;
 use std::panic::{catch_unwind, AssertUnwindSafe};
 use std::sync::Arc;
+use std::time::Duration;
 
 /// Asynchronous hyper service adapter.
 ///
@@ -34,7 +43,11 @@ use std::sync::Arc;
 /// it handles:
 ///
 /// * Suppressing the body of the response when the request used `HEAD`.
-/// * Turning any [`hyperdrive::Error`] into a proper HTTP response.
+/// * Turning any [`hyperdrive::Error`] into a proper HTTP response. Errors that
+///   answer the request directly (see [`Error::is_response`]), like redirects
+///   and responses to `OPTIONS` requests, are sent unchanged. They are not
+///   failures.
+/// * Adding CORS headers to responses, if configured using [`cors`].
 ///
 /// This type stores an async request handler `H` and the context needed by the
 /// [`FromRequest`] implementation. The context is cloned for every request.
@@ -80,6 +93,8 @@ use std::sync::Arc;
 ///
 /// [`FromRequest`]: ../trait.FromRequest.html
 /// [`hyperdrive::Error`]: ../struct.Error.html
+/// [`Error::is_response`]: ../struct.Error.html#method.is_response
+/// [`cors`]: #method.cors
 pub struct AsyncService<H, R, F>
 where
     H: Fn(R, Arc<Request<()>>) -> F + Send + Sync + 'static,
@@ -90,6 +105,7 @@ where
 {
     handler: Arc<H>,
     context: R::Context,
+    cors: Option<Arc<Cors>>,
 }
 
 impl<H, R, F> AsyncService<H, R, F>
@@ -138,8 +154,19 @@ where
         Self {
             handler: Arc::new(handler),
             context,
+            cors: None,
         }
     }
+
+    /// Makes the service answer cross-origin requests according to `cors`.
+    ///
+    /// Refer to the [`Cors`] documentation for details.
+    ///
+    /// [`Cors`]: struct.Cors.html
+    pub fn cors(mut self, cors: Cors) -> Self {
+        self.cors = Some(Arc::new(cors));
+        self
+    }
 }
 
 impl<H, R, F> Clone for AsyncService<H, R, F>
@@ -154,6 +181,7 @@ where
         Self {
             handler: self.handler.clone(),
             context: self.context.clone(),
+            cors: self.cors.clone(),
         }
     }
 }
@@ -194,8 +222,10 @@ where
     fn call(&mut self, req: Request<Self::ReqBody>) -> Self::Future {
         let is_head = req.method() == Method::HEAD;
         let handler = self.handler.clone();
+        let cors = self.cors.clone();
         let (parts, body) = req.into_parts();
         let req = Arc::new(Request::from_parts(parts, ()));
+        let orig = req.clone();
         let fut = R::from_request_and_body(&req, body, self.context.clone())
             .and_then(move |r| handler(r, req))
             .map(move |response| {
@@ -212,6 +242,12 @@ where
                 } else {
                     Err(err)
                 }
+            })
+            .map(move |mut response| {
+                if let Some(cors) = cors {
+                    cors.apply(&orig, &mut response);
+                }
+                response
             });
 
         Box::new(fut)
@@ -244,6 +280,7 @@ where
                 },
             )
             .field("context", &self.context)
+            .field("cors", &self.cors)
             .finish()
     }
 }
@@ -254,7 +291,11 @@ where
 /// from your app. Specifically, it handles:
 ///
 /// * Suppressing the body of the response when the request used `HEAD`.
-/// * Turning any [`hyperdrive::Error`] into a proper HTTP response.
+/// * Turning any [`hyperdrive::Error`] into a proper HTTP response. Errors that
+///   answer the request directly (see [`Error::is_response`]), like redirects
+///   and responses to `OPTIONS` requests, are sent unchanged. They are not
+///   failures.
+/// * Adding CORS headers to responses, if configured using [`cors`].
 ///
 /// This is effectively a bridge between async hyper and a synchronous,
 /// blocking app. Writing sync code is much simpler than writing async code
@@ -294,6 +335,8 @@ where
 ///
 /// [`AsyncService`]: struct.AsyncService.html
 /// [`hyperdrive::Error`]: ../struct.Error.html
+/// [`Error::is_response`]: ../struct.Error.html#method.is_response
+/// [`cors`]: #method.cors
 pub struct SyncService<H, R>
 where
     H: Fn(R, Arc<Request<()>>) -> Response<Body> + Send + Sync + 'static,
@@ -302,6 +345,7 @@ where
 {
     handler: Arc<H>,
     context: R::Context,
+    cors: Option<Arc<Cors>>,
 }
 
 impl<H, R> SyncService<H, R>
@@ -340,8 +384,19 @@ where
         Self {
             handler: Arc::new(handler),
             context,
+            cors: None,
         }
     }
+
+    /// Makes the service answer cross-origin requests according to `cors`.
+    ///
+    /// Refer to the [`Cors`] documentation for details.
+    ///
+    /// [`Cors`]: struct.Cors.html
+    pub fn cors(mut self, cors: Cors) -> Self {
+        self.cors = Some(Arc::new(cors));
+        self
+    }
 }
 
 impl<H, R> Clone for SyncService<H, R>
@@ -354,6 +409,7 @@ where
         Self {
             handler: self.handler.clone(),
             context: self.context.clone(),
+            cors: self.cors.clone(),
         }
     }
 }
@@ -390,9 +446,11 @@ where
     fn call(&mut self, req: Request<Self::ReqBody>) -> Self::Future {
         let is_head = req.method() == Method::HEAD;
         let handler = self.handler.clone();
+        let cors = self.cors.clone();
 
         let (parts, body) = req.into_parts();
         let req = Arc::new(Request::from_parts(parts, ()));
+        let orig = req.clone();
 
         let fut = R::from_request_and_body(&req, body, self.context.clone())
             .and_then(move |route| {
@@ -413,6 +471,12 @@ where
                 } else {
                     Err(err)
                 }
+            })
+            .map(move |mut response| {
+                if let Some(cors) = cors {
+                    cors.apply(&orig, &mut response);
+                }
+                response
             });
 
         Box::new(fut)
@@ -443,10 +507,223 @@ where
                 },
             )
             .field("context", &self.context)
+            .field("cors", &self.cors)
             .finish()
     }
 }
 
+/// Cross-origin resource sharing (CORS) configuration for [`AsyncService`] and
+/// [`SyncService`].
+///
+/// When a service is configured using `cors`, it adds an
+/// `Access-Control-Allow-Origin` header to all responses to requests from an
+/// allowed origin.
+///
+/// Browsers send a preflight `OPTIONS` request before most cross-origin
+/// requests. `#[derive(FromRequest)]` answers `OPTIONS` requests to any path
+/// without an explicit `OPTIONS` route by listing the methods accepted by the
+/// routes of that path in the `Allow` header. If the method of the actual
+/// request is among them, the preflight request is answered with these
+/// methods, the configured headers and max-age, and whether credentials are
+/// allowed. Otherwise, no CORS headers are added, and the browser will reject
+/// the request.
+///
+/// Routes with an explicit `OPTIONS` route get their preflight requests
+/// passed to the handler, which has to answer them itself.
+///
+/// # Examples
+///
+/// ```
+/// use hyperdrive::{FromRequest, service::{Cors, SyncService}};
+/// use hyper::{service::Service, Body, Request, Response};
+/// use futures::Future;
+/// use std::time::Duration;
+///
+/// #[derive(FromRequest)]
+/// enum Route {
+///     #[get("/users")]
+///     #[post("/users")]
+///     Users,
+/// }
+///
+/// let mut service = SyncService::new(|route: Route, _| match route {
+///     Route::Users => Response::new(Body::empty()),
+/// })
+/// .cors(
+///     Cors::new()
+///         .allow_origin("https://example.com")
+///         .allow_header("Content-Type")
+///         .max_age(Duration::from_secs(3600)),
+/// );
+///
+/// let preflight = Request::options("/users")
+///     .header("Origin", "https://example.com")
+///     .header("Access-Control-Request-Method", "POST")
+///     .body(Body::empty())
+///     .unwrap();
+/// let response = service.call(preflight).wait().unwrap();
+/// let headers = response.headers();
+/// assert_eq!(headers["Access-Control-Allow-Origin"], "https://example.com");
+/// assert_eq!(headers["Access-Control-Allow-Methods"], "GET, POST, HEAD, OPTIONS");
+/// assert_eq!(headers["Access-Control-Allow-Headers"], "content-type");
+/// assert_eq!(headers["Access-Control-Max-Age"], "3600");
+/// ```
+///
+/// [`AsyncService`]: struct.AsyncService.html
+/// [`SyncService`]: struct.SyncService.html
+#[derive(Debug, Clone)]
+pub struct Cors {
+    any_origin: bool,
+    origins: Vec<String>,
+    headers: Vec<HeaderName>,
+    max_age: Option<Duration>,
+    credentials: bool,
+}
+
+impl Cors {
+    /// Creates a CORS configuration that doesn't allow any origin.
+    ///
+    /// Use [`allow_origin`] or [`allow_any_origin`] to allow cross-origin
+    /// requests.
+    ///
+    /// [`allow_origin`]: #method.allow_origin
+    /// [`allow_any_origin`]: #method.allow_any_origin
+    pub fn new() -> Self {
+        Self {
+            any_origin: false,
+            origins: Vec::new(),
+            headers: Vec::new(),
+            max_age: None,
+            credentials: false,
+        }
+    }
+
+    /// Allows requests from `origin` (for example `https://example.com`).
+    pub fn allow_origin(mut self, origin: &str) -> Self {
+        self.origins.push(origin.to_string());
+        self
+    }
+
+    /// Allows requests from any origin.
+    ///
+    /// Unless credentials are allowed, responses will use the `*` wildcard
+    /// instead of listing the origin of the request.
+    pub fn allow_any_origin(mut self) -> Self {
+        self.any_origin = true;
+        self
+    }
+
+    /// Allows cross-origin requests to include the request header `name`.
+    ///
+    /// # Panics
+    ///
+    /// This will panic when `name` is not a valid header name.
+    pub fn allow_header(mut self, name: &str) -> Self {
+        let name = HeaderName::from_bytes(name.as_bytes())
+            .unwrap_or_else(|_| panic!("`{}` is not a valid header name", name));
+        self.headers.push(name);
+        self
+    }
+
+    /// Sets how long browsers may cache the response to a preflight request.
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    /// Sets whether cross-origin requests may include credentials (cookies,
+    /// authorization headers or TLS client certificates).
+    pub fn allow_credentials(mut self, allow: bool) -> Self {
+        self.credentials = allow;
+        self
+    }
+
+    fn allows_origin(&self, origin: &HeaderValue) -> bool {
+        self.any_origin
+            || self
+                .origins
+                .iter()
+                .any(|allowed| allowed.as_bytes() == origin.as_bytes())
+    }
+
+    /// Adds the CORS headers to `response`, which answers `request`.
+    fn apply(&self, request: &Request<()>, response: &mut Response<Body>) {
+        let wildcard = self.any_origin && !self.credentials;
+        if !wildcard {
+            // The response depends on the `Origin` header, so caches must not reuse it for other
+            // origins.
+            response
+                .headers_mut()
+                .append(VARY, HeaderValue::from_static("Origin"));
+        }
+
+        let origin = match request.headers().get(ORIGIN) {
+            Some(origin) => origin,
+            None => return,
+        };
+        if !self.allows_origin(origin) {
+            return;
+        }
+
+        if request.method() == Method::OPTIONS {
+            if let Some(requested) = request.headers().get(ACCESS_CONTROL_REQUEST_METHOD) {
+                // A preflight request. The `Allow` header of the response lists the methods
+                // accepted for the path.
+                let allowed = match response.headers().get(ALLOW) {
+                    Some(allowed) if response.status().is_success() => allowed.clone(),
+                    _ => return,
+                };
+                let is_allowed = match allowed.to_str() {
+                    Ok(allowed) => allowed
+                        .split(',')
+                        .any(|method| method.trim().as_bytes() == requested.as_bytes()),
+                    Err(_) => false,
+                };
+                if !is_allowed {
+                    return;
+                }
+
+                let headers = response.headers_mut();
+                headers.insert(ACCESS_CONTROL_ALLOW_METHODS, allowed);
+                if !self.headers.is_empty() {
+                    let names = self
+                        .headers
+                        .iter()
+                        .map(HeaderName::as_str)
+                        .collect::<Vec<_>>()
+                        .join(", ");
+                    headers.insert(
+                        ACCESS_CONTROL_ALLOW_HEADERS,
+                        HeaderValue::from_str(&names).expect("invalid header name list"),
+                    );
+                }
+                if let Some(max_age) = self.max_age {
+                    headers.insert(ACCESS_CONTROL_MAX_AGE, max_age.as_secs().into());
+                }
+            }
+        }
+
+        let headers = response.headers_mut();
+        if wildcard {
+            headers.insert(ACCESS_CONTROL_ALLOW_ORIGIN, HeaderValue::from_static("*"));
+        } else {
+            headers.insert(ACCESS_CONTROL_ALLOW_ORIGIN, origin.clone());
+        }
+        if self.credentials {
+            headers.insert(
+                ACCESS_CONTROL_ALLOW_CREDENTIALS,
+                HeaderValue::from_static("true"),
+            );
+        }
+    }
+}
+
+impl Default for Cors {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 /// Extension trait for types implementing Hyper's `Service` trait.
 ///
 /// This adds a number of convenience methods that can be used to build robust
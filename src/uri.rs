@@ -0,0 +1,169 @@
+use serde::Serialize;
+use std::fmt::{self, Write};
+
+/// Builds the URI of the route a value is decoded from.
+///
+/// This is useful for links and `Location` headers, which then don't have to
+/// be hard-coded. It can be derived for any type that also derives
+/// `FromRequest` and has a route attribute on every variant (deriving it for a
+/// type with a `#[forward]` fallback variant is a compile error). The
+/// generated implementation renders the URI from the first route attribute of
+/// the variant:
+///
+/// * Placeholder values are formatted using `Display` and percent-encoded
+///   (`/` is only kept in `{rest...}` placeholders). [`Raw`] placeholders are
+///   inserted as they are. Placeholders without a field of their own are
+///   taken from the serialized `#[path_params]` field.
+/// * The `#[query_params]` field is serialized using `serde_urlencoded`, or as
+///   nested parameters when using `#[query_params(nested)]` or when the value
+///   contains nested structs, maps or sequences.
+/// * `#[query]` fields are formatted using `Display`. `None` values are left
+///   out, and every element of a `Vec` field is added as a separate parameter.
+/// * All other fields (like the `#[body]`, headers and guards) are ignored.
+///
+/// The URI only contains the path and query. Use [`IntoRequest`] to build a
+/// whole request, including the host.
+///
+/// # Panics
+///
+/// The derived implementation will panic if the `#[query_params]` field
+/// doesn't serialize to a struct or a map (or, without `nested`, a sequence of
+/// key-value pairs), like a plain number or string.
+///
+/// # Examples
+///
+/// ```
+/// use hyperdrive::{FromRequest, ToUri};
+///
+/// #[derive(FromRequest, ToUri)]
+/// enum Routes {
+///     #[get("/")]
+///     Index,
+///
+///     #[get("/users/{id}/posts/{title}")]
+///     UserPost { id: u32, title: String },
+/// }
+///
+/// assert_eq!(Routes::Index.to_uri(), "/");
+///
+/// let route = Routes::UserPost { id: 42, title: "Hello World".to_string() };
+/// assert_eq!(route.to_uri(), "/users/42/posts/Hello%20World");
+/// ```
+///
+/// [`Raw`]: struct.Raw.html
+/// [`IntoRequest`]: trait.IntoRequest.html
+pub trait ToUri {
+    /// Returns the URI of the route matched by `self`.
+    fn to_uri(&self) -> http::Uri;
+}
+
+/// Incrementally builds the URI of a route.
+///
+/// Placeholder values are formatted using `Display` and percent-encoded (unless
+/// the placeholder field is a `Raw<T>`).
+#[doc(hidden)]
+#[derive(Debug, Default)]
+pub struct UriBuilder {
+    uri: String,
+    /// Whether the query string was started.
+    has_query: bool,
+}
+
+impl UriBuilder {
+    pub fn new() -> Self {
+        Self {
+            uri: String::new(),
+            has_query: false,
+        }
+    }
+
+    /// Appends literal text from the route attribute, without encoding it.
+    pub fn literal(&mut self, literal: &str) {
+        self.uri.push_str(literal);
+    }
+
+    /// Appends the value of a `{placeholder}`, encoding `/` and all other
+    /// characters not allowed in a path segment.
+    pub fn placeholder(&mut self, value: &dyn fmt::Display) {
+        self.push_encoded(value, false);
+    }
+
+    /// Appends the value of a `{placeholder...}`, which may contain `/`.
+    pub fn rest(&mut self, value: &dyn fmt::Display) {
+        self.push_encoded(value, true);
+    }
+
+    /// Appends the value of a `Raw` placeholder, which is inserted as-is.
+    pub fn raw(&mut self, value: &dyn fmt::Display) {
+        write!(self.uri, "{}", value).unwrap();
+    }
+
+    /// Appends the serialized query parameters (if there are any).
+    ///
+    /// Values that `serde_urlencoded` can't encode (like nested structs and
+    /// sequences) are encoded like `#[query_params(nested)]` fields instead.
+    ///
+    /// # Panics
+    ///
+    /// This will panic when `query` doesn't serialize to a struct, a map or a
+    /// sequence of key-value pairs.
+    pub fn query<T: Serialize + ?Sized>(&mut self, query: &T) {
+        let query = serde_urlencoded::to_string(query)
+            .or_else(|_| crate::encode_nested(query))
+            .unwrap_or_else(|e| panic!("couldn't serialize query parameters: {}", e));
+        self.push_query(&query);
+    }
+
+    /// Appends the query parameters of a `#[query_params(nested)]` field, using
+    /// repeated and bracketed parameter names for sequences and nested values.
+    ///
+    /// # Panics
+    ///
+    /// This will panic when `query` doesn't serialize to a struct or a map.
+    pub fn query_nested<T: Serialize + ?Sized>(&mut self, query: &T) {
+        let query = crate::encode_nested(query)
+            .unwrap_or_else(|e| panic!("couldn't serialize query parameters: {}", e));
+        self.push_query(&query);
+    }
+
+    /// Appends the value of a `#[query]` field as the query parameter `name`.
+    pub fn query_param(&mut self, name: &str, value: &dyn fmt::Display) {
+        let param = serde_urlencoded::to_string(&[(name, value.to_string())])
+            .expect("couldn't serialize query parameter");
+        self.push_query(&param);
+    }
+
+    pub fn build(self) -> http::Uri {
+        self.uri
+            .parse()
+            .unwrap_or_else(|e| panic!("route produced invalid URI `{}`: {}", self.uri, e))
+    }
+
+    fn push_query(&mut self, query: &str) {
+        if query.is_empty() {
+            return;
+        }
+
+        self.uri.push(if self.has_query { '&' } else { '?' });
+        self.uri.push_str(query);
+        self.has_query = true;
+    }
+
+    fn push_encoded(&mut self, value: &dyn fmt::Display, allow_slash: bool) {
+        for byte in value.to_string().bytes() {
+            let keep = match byte {
+                b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' => true,
+                b'-' | b'.' | b'_' | b'~' => true,
+                b'!' | b'$' | b'&' | b'\'' | b'(' | b')' | b'*' | b'+' | b',' | b';' | b'=' => true,
+                b':' | b'@' => true,
+                b'/' => allow_slash,
+                _ => false,
+            };
+            if keep {
+                self.uri.push(char::from(byte));
+            } else {
+                write!(self.uri, "%{:02X}", byte).unwrap();
+            }
+        }
+    }
+}
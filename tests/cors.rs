@@ -0,0 +1,133 @@
+use futures::{Future, IntoFuture};
+use http::{Request, Response, StatusCode};
+use hyper::{service::Service, Body};
+use hyperdrive::service::{AsyncService, Cors};
+use hyperdrive::FromRequest;
+use std::time::Duration;
+
+#[derive(FromRequest)]
+enum Route {
+    #[get("/users")]
+    #[post("/users")]
+    Users,
+
+    #[get("/custom")]
+    Custom,
+
+    #[options("/custom")]
+    CustomOptions,
+}
+
+fn call(cors: Cors, request: Request<Body>) -> Response<Body> {
+    let mut service = AsyncService::new(|route: Route, _| {
+        let response = match route {
+            Route::Users | Route::Custom => Response::new(Body::empty()),
+            Route::CustomOptions => Response::builder()
+                .status(StatusCode::IM_A_TEAPOT)
+                .body(Body::empty())
+                .unwrap(),
+        };
+        Ok(response).into_future()
+    })
+    .cors(cors);
+
+    service.call(request).wait().unwrap()
+}
+
+fn preflight(path: &str, origin: &str, method: &str) -> Request<Body> {
+    Request::options(path)
+        .header("Origin", origin)
+        .header("Access-Control-Request-Method", method)
+        .body(Body::empty())
+        .unwrap()
+}
+
+fn has_header(response: &Response<Body>, name: &str) -> bool {
+    response.headers().contains_key(name)
+}
+
+#[test]
+fn preflight_request() {
+    let cors = || {
+        Cors::new()
+            .allow_origin("https://a.example")
+            .allow_origin("https://b.example")
+            .allow_header("Content-Type")
+            .allow_header("X-Request-Id")
+            .max_age(Duration::from_secs(600))
+    };
+
+    let response = call(cors(), preflight("/users", "https://b.example", "POST"));
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    let headers = response.headers();
+    assert_eq!(headers["Access-Control-Allow-Origin"], "https://b.example");
+    assert_eq!(
+        headers["Access-Control-Allow-Methods"],
+        "GET, POST, HEAD, OPTIONS"
+    );
+    assert_eq!(
+        headers["Access-Control-Allow-Headers"],
+        "content-type, x-request-id"
+    );
+    assert_eq!(headers["Access-Control-Max-Age"], "600");
+    assert_eq!(headers["Vary"], "Origin");
+    assert!(!headers.contains_key("Access-Control-Allow-Credentials"));
+
+    // Unknown origin
+    let response = call(cors(), preflight("/users", "https://c.example", "POST"));
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    assert!(!has_header(&response, "Access-Control-Allow-Origin"));
+    assert!(!has_header(&response, "Access-Control-Allow-Methods"));
+
+    // Method not accepted by any route of the path
+    let response = call(cors(), preflight("/users", "https://a.example", "DELETE"));
+    assert!(!has_header(&response, "Access-Control-Allow-Origin"));
+    assert!(!has_header(&response, "Access-Control-Allow-Methods"));
+
+    // Unknown path
+    let response = call(cors(), preflight("/unknown", "https://a.example", "GET"));
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert!(!has_header(&response, "Access-Control-Allow-Methods"));
+
+    // Explicit OPTIONS routes are passed to the handler
+    let response = call(cors(), preflight("/custom", "https://a.example", "GET"));
+    assert_eq!(response.status(), StatusCode::IM_A_TEAPOT);
+    assert!(!has_header(&response, "Access-Control-Allow-Methods"));
+}
+
+#[test]
+fn actual_request() {
+    let request = || {
+        Request::get("/users")
+            .header("Origin", "https://a.example")
+            .body(Body::empty())
+            .unwrap()
+    };
+
+    let response = call(Cors::new().allow_any_origin(), request());
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["Access-Control-Allow-Origin"], "*");
+    assert!(!has_header(&response, "Vary"));
+
+    let cors = Cors::new().allow_any_origin().allow_credentials(true);
+    let response = call(cors, request());
+    let headers = response.headers();
+    assert_eq!(headers["Access-Control-Allow-Origin"], "https://a.example");
+    assert_eq!(headers["Access-Control-Allow-Credentials"], "true");
+    assert_eq!(headers["Vary"], "Origin");
+
+    let response = call(Cors::new().allow_origin("https://b.example"), request());
+    assert!(!has_header(&response, "Access-Control-Allow-Origin"));
+
+    // Errors get CORS headers too, so the browser can read them
+    let request = Request::get("/unknown")
+        .header("Origin", "https://a.example")
+        .body(Body::empty())
+        .unwrap();
+    let response = call(Cors::new().allow_origin("https://a.example"), request);
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert_eq!(
+        response.headers()["Access-Control-Allow-Origin"],
+        "https://a.example"
+    );
+}
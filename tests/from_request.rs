@@ -1,8 +1,11 @@
+use futures::{Future, Stream};
 use hyperdrive::{
     body::Json,
+    guard::{Cookies, OriginalUri},
     http::{Method, Request, StatusCode},
     hyper::Body,
-    BoxedError, Error, FromRequest, Guard, NoContext, RequestContext,
+    BoxedError, DefaultFuture, Error, FromBody, FromRequest, Guard, NoContext, Raw, RequestContext,
+    ToUri,
 };
 use serde::Deserialize;
 use std::str::FromStr;
@@ -268,6 +271,7 @@ fn asterisk() {
 
     invoke::<Routes>(Request::options("*").body(Body::empty()).unwrap()).unwrap();
     invoke::<Routes>(Request::options("/").body(Body::empty()).unwrap()).unwrap_err();
+    invoke::<Routes>(Request::options("/*").body(Body::empty()).unwrap()).unwrap_err();
     invoke::<Routes>(Request::head("/").body(Body::empty()).unwrap()).unwrap_err();
 
     #[derive(FromRequest, Debug)]
@@ -280,107 +284,1775 @@ fn asterisk() {
 }
 
 #[test]
-fn implicit_head_route() {
+fn custom_methods() {
     #[derive(FromRequest, Debug, PartialEq, Eq)]
     enum Routes {
-        #[get("/")]
-        Index,
+        #[get("/files/{path...}")]
+        Get { path: String },
 
-        #[get("/2/other")]
-        Other,
+        #[route(method = "PROPFIND", path = "/files/{path...}")]
+        PropFind { path: String },
 
-        // We should still be able to define our own HEAD route instead
-        #[head("/2/other")]
-        OtherHead,
+        #[route(method = "PURGE", path = "/cache")]
+        Purge,
     }
 
-    let head = invoke::<Routes>(Request::head("/").body(Body::empty()).unwrap()).unwrap();
-    assert_eq!(head, Routes::Index);
+    let propfind = Method::from_bytes(b"PROPFIND").unwrap();
+    let purge = Method::from_bytes(b"PURGE").unwrap();
 
-    let anyhead = invoke::<Routes>(Request::head("/2/other").body(Body::empty()).unwrap()).unwrap();
-    assert_eq!(anyhead, Routes::OtherHead);
+    let route = invoke::<Routes>(
+        Request::builder()
+            .method(propfind.clone())
+            .uri("/files/dir/file.txt")
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(
+        route,
+        Routes::PropFind {
+            path: "dir/file.txt".to_string()
+        }
+    );
+
+    let route = invoke::<Routes>(
+        Request::builder()
+            .method(purge.clone())
+            .uri("/cache")
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(route, Routes::Purge);
+
+    let err: Box<Error> = invoke::<Routes>(Request::put("/files/x").body(Body::empty()).unwrap())
+        .unwrap_err()
+        .downcast()
+        .unwrap();
+    assert_eq!(err.http_status(), StatusCode::METHOD_NOT_ALLOWED);
+    assert_eq!(
+        err.allowed_methods().expect("allowed_methods()"),
+        &[&Method::GET, &propfind, &Method::HEAD]
+    );
+
+    let err: Box<Error> = invoke::<Routes>(Request::get("/cache").body(Body::empty()).unwrap())
+        .unwrap_err()
+        .downcast()
+        .unwrap();
+    assert_eq!(err.http_status(), StatusCode::METHOD_NOT_ALLOWED);
+    assert_eq!(
+        err.allowed_methods().expect("allowed_methods()"),
+        &[&purge]
+    );
+}
+
+#[test]
+fn ranked_routes() {
+    #[derive(FromRequest, Debug, PartialEq, Eq)]
+    enum Routes {
+        #[get("/users/me")]
+        Me,
+
+        #[get("/users/{id}", rank = 1)]
+        #[delete("/users/{id}", rank = 1)]
+        User { id: u32 },
+
+        #[post("/users/{name}", rank = 2)]
+        Create { name: String },
+    }
+
+    let route = invoke::<Routes>(Request::get("/users/me").body(Body::empty()).unwrap()).unwrap();
+    assert_eq!(route, Routes::Me);
+
+    let route = invoke::<Routes>(Request::get("/users/123").body(Body::empty()).unwrap()).unwrap();
+    assert_eq!(route, Routes::User { id: 123 });
+
+    let route =
+        invoke::<Routes>(Request::delete("/users/123").body(Body::empty()).unwrap()).unwrap();
+    assert_eq!(route, Routes::User { id: 123 });
+
+    // A higher-ranked route with a different method is still reachable
+    let route = invoke::<Routes>(Request::post("/users/me").body(Body::empty()).unwrap()).unwrap();
+    assert_eq!(
+        route,
+        Routes::Create {
+            name: "me".to_string()
+        }
+    );
+
+    // Allowed methods are collected from all matching routes
+    let err: Box<Error> = invoke::<Routes>(Request::put("/users/me").body(Body::empty()).unwrap())
+        .unwrap_err()
+        .downcast()
+        .unwrap();
+    assert_eq!(err.http_status(), StatusCode::METHOD_NOT_ALLOWED);
+    assert_eq!(
+        err.allowed_methods().expect("allowed_methods()"),
+        &[&Method::GET, &Method::HEAD, &Method::POST]
+    );
+
+    let err: Box<Error> = invoke::<Routes>(Request::put("/users/123").body(Body::empty()).unwrap())
+        .unwrap_err()
+        .downcast()
+        .unwrap();
+    assert_eq!(err.http_status(), StatusCode::METHOD_NOT_ALLOWED);
+    assert_eq!(
+        err.allowed_methods().expect("allowed_methods()"),
+        &[&Method::GET, &Method::DELETE, &Method::POST, &Method::HEAD]
+    );
+}
+
+#[test]
+fn fallthrough() {
+    #[derive(FromRequest, Debug, PartialEq, Eq)]
+    #[routing(fallthrough)]
+    enum Routes {
+        #[get("/users/{id}")]
+        UserById { id: u32 },
+
+        #[get("/users/{name}")]
+        UserByName { name: String },
+
+        #[delete("/items/{id}")]
+        DeleteItem { id: u32 },
+    }
+
+    let route = invoke::<Routes>(Request::get("/users/42").body(Body::empty()).unwrap()).unwrap();
+    assert_eq!(route, Routes::UserById { id: 42 });
+
+    let route = invoke::<Routes>(Request::get("/users/me").body(Body::empty()).unwrap()).unwrap();
+    assert_eq!(
+        route,
+        Routes::UserByName {
+            name: "me".to_string()
+        }
+    );
+
+    let route = invoke::<Routes>(Request::head("/users/me").body(Body::empty()).unwrap()).unwrap();
+    assert_eq!(
+        route,
+        Routes::UserByName {
+            name: "me".to_string()
+        }
+    );
+
+    let route =
+        invoke::<Routes>(Request::delete("/items/42").body(Body::empty()).unwrap()).unwrap();
+    assert_eq!(route, Routes::DeleteItem { id: 42 });
+
+    // No route accepts the placeholder
+    let err: Box<Error> =
+        invoke::<Routes>(Request::delete("/items/abc").body(Body::empty()).unwrap())
+            .unwrap_err()
+            .downcast()
+            .unwrap();
+    assert_eq!(err.http_status(), StatusCode::NOT_FOUND);
+
+    // Only routes whose placeholders parse contribute to the allowed methods
+    let err: Box<Error> = invoke::<Routes>(Request::get("/items/42").body(Body::empty()).unwrap())
+        .unwrap_err()
+        .downcast()
+        .unwrap();
+    assert_eq!(err.http_status(), StatusCode::METHOD_NOT_ALLOWED);
+    assert_eq!(
+        err.allowed_methods().expect("allowed_methods()"),
+        &[&Method::DELETE]
+    );
+
+    let err: Box<Error> =
+        invoke::<Routes>(Request::post("/users/me").body(Body::empty()).unwrap())
+            .unwrap_err()
+            .downcast()
+            .unwrap();
+    assert_eq!(err.http_status(), StatusCode::METHOD_NOT_ALLOWED);
+    assert_eq!(
+        err.allowed_methods().expect("allowed_methods()"),
+        &[&Method::GET, &Method::HEAD]
+    );
+
+    let err: Box<Error> = invoke::<Routes>(Request::get("/other").body(Body::empty()).unwrap())
+        .unwrap_err()
+        .downcast()
+        .unwrap();
+    assert_eq!(err.http_status(), StatusCode::NOT_FOUND);
+}
+
+#[test]
+fn regex_constraints() {
+    #[derive(FromRequest, Debug, PartialEq, Eq)]
+    enum Routes {
+        #[get("/files/{id:[0-9]+}")]
+        ById { id: u32 },
+
+        #[get("/files/{name:[a-z]+}")]
+        ByName { name: String },
+
+        #[get("/files/{id:[0-9]+}/{path:[^/]+}")]
+        Nested { id: u32, path: String },
+
+        #[get("/archive/{year:[0-9]{4}}")]
+        Archive { year: u16 },
+    }
+
+    let route = invoke::<Routes>(Request::get("/files/123").body(Body::empty()).unwrap()).unwrap();
+    assert_eq!(route, Routes::ById { id: 123 });
+
+    let route = invoke::<Routes>(Request::get("/files/abc").body(Body::empty()).unwrap()).unwrap();
+    assert_eq!(
+        route,
+        Routes::ByName {
+            name: "abc".to_string()
+        }
+    );
+
+    let route =
+        invoke::<Routes>(Request::get("/files/1/x.txt").body(Body::empty()).unwrap()).unwrap();
+    assert_eq!(
+        route,
+        Routes::Nested {
+            id: 1,
+            path: "x.txt".to_string()
+        }
+    );
+
+    let route =
+        invoke::<Routes>(Request::get("/archive/2019").body(Body::empty()).unwrap()).unwrap();
+    assert_eq!(route, Routes::Archive { year: 2019 });
+
+    // Paths not matching any constraint are not routed at all
+    for path in &["/files/ABC", "/files/a1", "/archive/19", "/archive/20190"] {
+        let err: Box<Error> = invoke::<Routes>(Request::get(*path).body(Body::empty()).unwrap())
+            .unwrap_err()
+            .downcast()
+            .unwrap();
+        assert_eq!(err.http_status(), StatusCode::NOT_FOUND);
+        assert!(err.source().is_none(), "{} was routed", path);
+    }
+}
+
+#[test]
+fn mixed_segments() {
+    #[derive(FromRequest, Debug, PartialEq, Eq)]
+    enum Routes {
+        #[get("/reports/{id}.{format}")]
+        Report { id: u32, format: String },
+
+        #[get("/v{version}/items")]
+        Items { version: u8 },
+
+        #[get("/v{version}/items/{id}.json")]
+        Item { version: u8, id: u32 },
+
+        #[get("/files/{name}.{ext:[a-z]+}")]
+        File { name: String, ext: String },
+    }
+
+    let route =
+        invoke::<Routes>(Request::get("/reports/12.csv").body(Body::empty()).unwrap()).unwrap();
+    assert_eq!(
+        route,
+        Routes::Report {
+            id: 12,
+            format: "csv".to_string()
+        }
+    );
+
+    let route =
+        invoke::<Routes>(Request::get("/reports/1.tar.gz").body(Body::empty()).unwrap());
+    assert!(route.is_err(), "`1.tar` is not a valid `id`");
+
+    let route = invoke::<Routes>(Request::get("/v2/items").body(Body::empty()).unwrap()).unwrap();
+    assert_eq!(route, Routes::Items { version: 2 });
+
+    let route =
+        invoke::<Routes>(Request::get("/v1/items/7.json").body(Body::empty()).unwrap()).unwrap();
+    assert_eq!(route, Routes::Item { version: 1, id: 7 });
+
+    let route = invoke::<Routes>(
+        Request::get("/files/backup.tar.gz")
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(
+        route,
+        Routes::File {
+            name: "backup.tar".to_string(),
+            ext: "gz".to_string()
+        }
+    );
+
+    for path in &[
+        "/reports/12",
+        "/v/items",
+        "/items",
+        "/v1/items/7.xml",
+        "/files/archive.7z",
+        "/files/.gz",
+    ] {
+        let err: Box<Error> = invoke::<Routes>(Request::get(*path).body(Body::empty()).unwrap())
+            .unwrap_err()
+            .downcast()
+            .unwrap();
+        assert_eq!(err.http_status(), StatusCode::NOT_FOUND, "{}", path);
+    }
+}
+
+#[test]
+fn percent_decoding() {
+    #[derive(FromRequest, ToUri, Debug, PartialEq, Eq)]
+    enum Routes {
+        #[get("/users/{name}")]
+        User { name: String },
+
+        #[get("/numbers/{n}.txt")]
+        Number { n: u32 },
+
+        #[get("/files/{path...}")]
+        File { path: String },
+
+        #[get("/raw/{name}/{path...}")]
+        Raw {
+            name: Raw<String>,
+            path: Raw<String>,
+        },
+    }
+
+    #[derive(FromRequest, Debug)]
+    #[routing(fallthrough)]
+    #[allow(dead_code)]
+    enum Fallthrough {
+        #[get("/users/{id}")]
+        Id { id: u32 },
+
+        #[get("/users/{name}")]
+        Name { name: String },
+    }
+
+    let route = invoke::<Routes>(
+        Request::get("/users/J%C3%BCrgen")
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(
+        route,
+        Routes::User {
+            name: "Jürgen".to_string()
+        }
+    );
+
+    // Malformed escapes are kept
+    let route = invoke::<Routes>(
+        Request::get("/users/a%2Fb%zz%4")
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(
+        route,
+        Routes::User {
+            name: "a/b%zz%4".to_string()
+        }
+    );
+
+    let route = invoke::<Routes>(
+        Request::get("/numbers/%34%32.txt")
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(route, Routes::Number { n: 42 });
+
+    let route = invoke::<Routes>(
+        Request::get("/files/my%20docs/a%2Fb.txt")
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(
+        route,
+        Routes::File {
+            path: "my docs/a/b.txt".to_string()
+        }
+    );
+
+    let route = invoke::<Routes>(
+        Request::get("/raw/J%C3%BCrgen/a%2Fb")
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(
+        route,
+        Routes::Raw {
+            name: Raw("J%C3%BCrgen".to_string()),
+            path: Raw("a%2Fb".to_string()),
+        }
+    );
+
+    // Invalid UTF-8
+    let err: Box<Error> = invoke::<Routes>(Request::get("/users/%FF").body(Body::empty()).unwrap())
+        .unwrap_err()
+        .downcast()
+        .unwrap();
+    assert_eq!(err.http_status(), StatusCode::BAD_REQUEST);
+
+    // The decoded value is also used to determine the allowed methods
+    let err: Box<Error> = invoke::<Routes>(
+        Request::post("/numbers/%34%32.txt")
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .unwrap_err()
+    .downcast()
+    .unwrap();
+    assert_eq!(err.http_status(), StatusCode::METHOD_NOT_ALLOWED);
+    assert_eq!(
+        err.allowed_methods().expect("allowed_methods()"),
+        &[&Method::GET, &Method::HEAD]
+    );
+
+    // Invalid UTF-8 matches the route when determining the allowed methods, and when trying routes
+    // in fallthrough mode, so it's always rejected by the route
+    let err: Box<Error> =
+        invoke::<Routes>(Request::post("/users/%FF").body(Body::empty()).unwrap())
+            .unwrap_err()
+            .downcast()
+            .unwrap();
+    assert_eq!(err.http_status(), StatusCode::METHOD_NOT_ALLOWED);
+    assert_eq!(
+        err.allowed_methods().expect("allowed_methods()"),
+        &[&Method::GET, &Method::HEAD]
+    );
+    let err: Box<Error> =
+        invoke::<Fallthrough>(Request::get("/users/%FF").body(Body::empty()).unwrap())
+            .unwrap_err()
+            .downcast()
+            .unwrap();
+    assert_eq!(err.http_status(), StatusCode::BAD_REQUEST);
+    let err: Box<Error> =
+        invoke::<Fallthrough>(Request::post("/users/%FF").body(Body::empty()).unwrap())
+            .unwrap_err()
+            .downcast()
+            .unwrap();
+    assert_eq!(err.http_status(), StatusCode::METHOD_NOT_ALLOWED);
+    assert_eq!(
+        err.allowed_methods().expect("allowed_methods()"),
+        &[&Method::GET, &Method::HEAD]
+    );
+
+    // Building the URI encodes the value again, and leaves `Raw` values alone
+    let uri = Routes::User {
+        name: "Jürgen & Co".to_string(),
+    }
+    .to_uri();
+    assert_eq!(uri, "/users/J%C3%BCrgen%20&%20Co");
+    let route = invoke::<Routes>(Request::get(uri).body(Body::empty()).unwrap()).unwrap();
+    assert_eq!(
+        route,
+        Routes::User {
+            name: "Jürgen & Co".to_string()
+        }
+    );
+    let route = Routes::Raw {
+        name: Raw("a%2Fb".to_string()),
+        path: Raw("c%20d/e".to_string()),
+    };
+    assert_eq!(route.to_uri(), "/raw/a%2Fb/c%20d/e");
+}
+
+#[test]
+fn trailing_slash() {
+    #[derive(FromRequest, Debug, PartialEq, Eq)]
+    #[routing(trailing_slash = "ignore")]
+    enum Ignore {
+        #[get("/users")]
+        Users,
+
+        #[get("/users/{id}")]
+        User { id: u32 },
+
+        #[get("/files/")]
+        Files,
+    }
+
+    for (path, expected) in &[
+        ("/users", Ignore::Users),
+        ("/users/", Ignore::Users),
+        ("//users", Ignore::Users),
+        ("/files", Ignore::Files),
+        ("/files/", Ignore::Files),
+        ("/users//5", Ignore::User { id: 5 }),
+        ("/users/./5/", Ignore::User { id: 5 }),
+        ("/files/../users/7", Ignore::User { id: 7 }),
+    ] {
+        let route = invoke::<Ignore>(Request::get(*path).body(Body::empty()).unwrap()).unwrap();
+        assert_eq!(route, *expected, "{}", path);
+    }
+
+    let err: Box<Error> = invoke::<Ignore>(Request::post("/users/").body(Body::empty()).unwrap())
+        .unwrap_err()
+        .downcast()
+        .unwrap();
+    assert_eq!(err.http_status(), StatusCode::METHOD_NOT_ALLOWED);
+
+    let err: Box<Error> =
+        invoke::<Ignore>(Request::get("/users/../..").body(Body::empty()).unwrap())
+            .unwrap_err()
+            .downcast()
+            .unwrap();
+    assert_eq!(err.http_status(), StatusCode::BAD_REQUEST);
+
+    #[derive(FromRequest, Debug, PartialEq, Eq)]
+    #[routing(trailing_slash = "strict")]
+    enum Strict {
+        #[get("/users")]
+        Users,
+    }
+
+    let route = invoke::<Strict>(Request::get("//users/.").body(Body::empty()).unwrap());
+    assert!(route.is_err(), "trailing slash must match");
+    let route = invoke::<Strict>(Request::get("//users").body(Body::empty()).unwrap()).unwrap();
+    assert_eq!(route, Strict::Users);
+
+    #[derive(FromRequest, Debug, PartialEq, Eq)]
+    #[routing(trailing_slash = "redirect")]
+    enum Redirect {
+        #[get("/users")]
+        Users,
+
+        #[get("/users/{id}/")]
+        User { id: u32 },
+    }
+
+    #[derive(FromRequest, Debug, PartialEq, Eq)]
+    enum Outer {
+        Api {
+            #[forward(prefix = "/api")]
+            api: Redirect,
+        },
+    }
+
+    let route = invoke::<Redirect>(Request::get("/users").body(Body::empty()).unwrap()).unwrap();
+    assert_eq!(route, Redirect::Users);
+
+    for (path, location) in &[
+        ("/users/", "/users"),
+        ("/users/5", "/users/5/"),
+        ("//users//5?page=2", "/users/5/?page=2"),
+    ] {
+        let err: Box<Error> = invoke::<Redirect>(Request::get(*path).body(Body::empty()).unwrap())
+            .unwrap_err()
+            .downcast()
+            .unwrap();
+        assert_eq!(
+            err.http_status(),
+            StatusCode::PERMANENT_REDIRECT,
+            "{}",
+            path
+        );
+        assert_eq!(err.location().unwrap(), location);
+        assert_eq!(err.response().headers()["Location"], *location);
+        assert!(err.is_response());
+    }
+
+    let err: Box<Error> = invoke::<Outer>(Request::get("/api/users/").body(Body::empty()).unwrap())
+        .unwrap_err()
+        .downcast()
+        .unwrap();
+    assert_eq!(err.http_status(), StatusCode::PERMANENT_REDIRECT);
+    assert_eq!(err.location().unwrap(), "/api/users");
+}
+
+/// Normalizing the path must not hide the extensions of the request from guards.
+#[test]
+fn trailing_slash_extensions() {
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    struct RequestId(u32);
+
+    impl Guard for RequestId {
+        type Context = NoContext;
+        type Result = Result<Self, BoxedError>;
+
+        fn from_request(request: &Arc<http::Request<()>>, _: &Self::Context) -> Self::Result {
+            request
+                .extensions()
+                .get::<RequestId>()
+                .cloned()
+                .ok_or_else(|| Error::from_status(StatusCode::INTERNAL_SERVER_ERROR).into())
+        }
+    }
+
+    #[derive(FromRequest, Debug, PartialEq, Eq)]
+    #[routing(trailing_slash = "ignore")]
+    enum Routes {
+        #[get("/users")]
+        Users { id: RequestId },
+
+        #[get("/users/{user}")]
+        User { user: u32, id: RequestId },
+    }
+
+    for path in &["/users", "/users/", "//users"] {
+        let mut request = Request::get(*path).body(Body::empty()).unwrap();
+        request.extensions_mut().insert(RequestId(7));
+        let route = invoke::<Routes>(request).unwrap();
+        assert_eq!(route, Routes::Users { id: RequestId(7) }, "{}", path);
+    }
+
+    let mut request = Request::get("/users/5/").body(Body::empty()).unwrap();
+    request.extensions_mut().insert(RequestId(7));
+    let route = invoke::<Routes>(request).unwrap();
+    assert_eq!(
+        route,
+        Routes::User {
+            user: 5,
+            id: RequestId(7)
+        }
+    );
+
+    // `#[forward]`ed types get the normalized path, and can still access the received URI
+    #[derive(FromRequest, Debug)]
+    #[routing(trailing_slash = "ignore")]
+    enum Outer {
+        Files {
+            #[forward]
+            files: Files,
+        },
+    }
+
+    #[derive(FromRequest, Debug)]
+    #[get("/files")]
+    struct Files {
+        uri: OriginalUri,
+    }
+
+    let Outer::Files { files } =
+        invoke::<Outer>(Request::get("//files").body(Body::empty()).unwrap()).unwrap();
+    assert_eq!(files.uri.path(), "//files");
+}
+
+#[test]
+fn hosts() {
+    use hyperdrive::IntoRequest;
+
+    #[derive(FromRequest, IntoRequest, Debug, PartialEq, Eq)]
+    #[host("{tenant}.example.com")]
+    enum Routes {
+        #[get("/", rank = 1)]
+        Index { tenant: String },
+
+        #[get("/", host = "api.example.com")]
+        ApiIndex,
+
+        #[get("/users/{id}", host = "api.example.com")]
+        #[delete("/users/{id}", host = "admin.example.com")]
+        User { id: u32 },
+
+        #[post("/users/{id}")]
+        EditUser { tenant: String, id: u32 },
+    }
+
+    let get = |host: &str, path: &str| {
+        invoke::<Routes>(
+            Request::get(path)
+                .header("Host", host)
+                .body(Body::empty())
+                .unwrap(),
+        )
+    };
+
+    assert_eq!(
+        get("acme.example.com", "/").unwrap(),
+        Routes::Index {
+            tenant: "acme".to_string()
+        }
+    );
+    assert_eq!(get("API.example.com.:8080", "/").unwrap(), Routes::ApiIndex);
+    assert_eq!(
+        get("api.example.com", "/users/5").unwrap(),
+        Routes::User { id: 5 }
+    );
+
+    // The URI authority takes precedence over the `Host` header
+    let route = invoke::<Routes>(
+        Request::get("http://api.example.com/")
+            .header("Host", "acme.example.com")
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(route, Routes::ApiIndex);
+
+    // Hosts not matched by any route of the path
+    for (host, path) in &[
+        ("example.com", "/"),
+        ("a.b.example.com", "/"),
+        ("", "/"),
+        ("example.org", "/users/5"),
+    ] {
+        let err: Box<Error> = get(host, path).unwrap_err().downcast().unwrap();
+        assert_eq!(err.http_status(), StatusCode::NOT_FOUND, "{}{}", host, path);
+    }
+
+    // Only methods of routes matching the host are allowed
+    let err: Box<Error> = invoke::<Routes>(
+        Request::put("/users/5")
+            .header("Host", "admin.example.com")
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .unwrap_err()
+    .downcast()
+    .unwrap();
+    assert_eq!(err.http_status(), StatusCode::METHOD_NOT_ALLOWED);
+    assert_eq!(
+        err.allowed_methods().unwrap(),
+        &[&Method::DELETE, &Method::POST]
+    );
+
+    let roundtrip = |route: Routes, host: &str| {
+        let request = route.into_request();
+        assert_eq!(request.headers()["Host"], host);
+        invoke::<Routes>(request).unwrap()
+    };
+    assert_eq!(
+        roundtrip(
+            Routes::EditUser {
+                tenant: "acme".to_string(),
+                id: 7
+            },
+            "acme.example.com"
+        ),
+        Routes::EditUser {
+            tenant: "acme".to_string(),
+            id: 7
+        }
+    );
+    assert_eq!(
+        roundtrip(Routes::User { id: 7 }, "api.example.com"),
+        Routes::User { id: 7 }
+    );
+}
+
+#[test]
+fn media_types() {
+    use hyperdrive::IntoRequest;
+
+    #[derive(FromRequest, IntoRequest, Debug, PartialEq, Eq)]
+    enum Routes {
+        #[post("/upload", consumes = "application/json")]
+        UploadJson,
+
+        #[post("/upload", consumes = "text/csv")]
+        UploadCsv,
+
+        #[get("/report", produces = "text/csv")]
+        ReportCsv,
+
+        #[get("/report", produces = "text/*", rank = 1)]
+        ReportText,
+
+        #[get("/report", produces = "application/json")]
+        ReportJson,
+    }
+
+    let post = |content_type: &str| {
+        invoke::<Routes>(
+            Request::post("/upload")
+                .header("Content-Type", content_type)
+                .body(Body::empty())
+                .unwrap(),
+        )
+    };
+    let get = |accept: &str| {
+        invoke::<Routes>(
+            Request::get("/report")
+                .header("Accept", accept)
+                .body(Body::empty())
+                .unwrap(),
+        )
+    };
+    let status = |result: Result<Routes, BoxedError>| {
+        result
+            .unwrap_err()
+            .downcast::<Error>()
+            .unwrap()
+            .http_status()
+    };
+
+    assert_eq!(post("application/json").unwrap(), Routes::UploadJson);
+    assert_eq!(post("Text/CSV; charset=utf-8").unwrap(), Routes::UploadCsv);
+    assert_eq!(
+        status(post("text/plain")),
+        StatusCode::UNSUPPORTED_MEDIA_TYPE
+    );
+    let no_content_type = invoke::<Routes>(Request::post("/upload").body(Body::empty()).unwrap());
+    assert_eq!(status(no_content_type), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+
+    assert_eq!(get("text/csv").unwrap(), Routes::ReportCsv);
+    assert_eq!(get("text/html").unwrap(), Routes::ReportText);
+    assert_eq!(get("text/html, text/*;q=0.5").unwrap(), Routes::ReportText);
+    assert_eq!(
+        get("text/csv;q=0.5, application/json").unwrap(),
+        Routes::ReportJson
+    );
+    assert_eq!(
+        get("application/json;q=0.5, text/csv").unwrap(),
+        Routes::ReportCsv
+    );
+    // Equal quality: the more specific match, then the rank decides
+    assert_eq!(get("text/*, application/json").unwrap(), Routes::ReportJson);
+    assert_eq!(get("text/*, application/*").unwrap(), Routes::ReportCsv);
+    assert_eq!(get("*/*").unwrap(), Routes::ReportCsv);
+    assert_eq!(get("application/*").unwrap(), Routes::ReportJson);
+    assert_eq!(
+        get("text/csv;q=0, application/json").unwrap(),
+        Routes::ReportJson
+    );
+    assert_eq!(status(get("image/png")), StatusCode::NOT_ACCEPTABLE);
+    assert_eq!(status(get("*/*;q=0")), StatusCode::NOT_ACCEPTABLE);
+    let no_accept = invoke::<Routes>(Request::get("/report").body(Body::empty()).unwrap());
+    assert_eq!(no_accept.unwrap(), Routes::ReportCsv);
+
+    // The method is checked first
+    let err = invoke::<Routes>(Request::delete("/report").body(Body::empty()).unwrap());
+    assert_eq!(status(err), StatusCode::METHOD_NOT_ALLOWED);
+
+    let request = Routes::UploadCsv.into_request();
+    assert_eq!(request.headers()["Content-Type"], "text/csv");
+    assert_eq!(invoke::<Routes>(request).unwrap(), Routes::UploadCsv);
+    let request = Routes::ReportJson.into_request();
+    assert_eq!(request.headers()["Accept"], "application/json");
+    assert_eq!(invoke::<Routes>(request).unwrap(), Routes::ReportJson);
+
+    // Media types are only rejected if the fallback variants don't accept the request either
+    #[derive(FromRequest, Debug, PartialEq, Eq)]
+    enum Inner {
+        #[post("/upload")]
+        Upload,
+
+        #[get("/inner")]
+        Get,
+    }
+
+    #[derive(FromRequest, Debug, PartialEq, Eq)]
+    enum Outer {
+        #[post("/upload", consumes = "application/json")]
+        UploadJson,
+
+        #[get("/report", produces = "text/csv")]
+        ReportCsv,
+
+        Inner {
+            #[forward]
+            inner: Inner,
+        },
+    }
+
+    let route = invoke::<Outer>(
+        Request::post("/upload")
+            .header("Content-Type", "text/plain")
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(
+        route,
+        Outer::Inner {
+            inner: Inner::Upload
+        }
+    );
+    let err: Box<Error> = invoke::<Outer>(
+        Request::get("/report")
+            .header("Accept", "application/json")
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .unwrap_err()
+    .downcast()
+    .unwrap();
+    assert_eq!(err.http_status(), StatusCode::NOT_ACCEPTABLE);
+}
+
+#[test]
+fn implicit_head_route() {
+    #[derive(FromRequest, Debug, PartialEq, Eq)]
+    enum Routes {
+        #[get("/")]
+        Index,
+
+        #[get("/2/other")]
+        Other,
+
+        // We should still be able to define our own HEAD route instead
+        #[head("/2/other")]
+        OtherHead,
+    }
+
+    let head = invoke::<Routes>(Request::head("/").body(Body::empty()).unwrap()).unwrap();
+    assert_eq!(head, Routes::Index);
+
+    let anyhead = invoke::<Routes>(Request::head("/2/other").body(Body::empty()).unwrap()).unwrap();
+    assert_eq!(anyhead, Routes::OtherHead);
+
+    let anyhead = invoke::<Routes>(Request::get("/2/other").body(Body::empty()).unwrap()).unwrap();
+    assert_eq!(anyhead, Routes::Other);
+}
+
+#[test]
+fn implicit_options_route() {
+    #[derive(FromRequest, Debug, PartialEq, Eq)]
+    enum Inner {
+        #[put("/users/{id}")]
+        UpdateUser { id: u32 },
+    }
+
+    #[derive(FromRequest, Debug, PartialEq, Eq)]
+    enum Routes {
+        #[get("/users/{id}")]
+        #[delete("/users/{id}")]
+        User { id: u32 },
+
+        #[get("/custom")]
+        Custom,
+
+        // An explicit OPTIONS route takes precedence
+        #[options("/custom")]
+        CustomOptions,
+
+        Inner {
+            #[forward]
+            inner: Inner,
+        },
+    }
+
+    let options = |path: &str| -> Box<Error> {
+        invoke::<Routes>(Request::options(path).body(Body::empty()).unwrap())
+            .unwrap_err()
+            .downcast()
+            .unwrap()
+    };
+
+    let err = options("/users/5");
+    assert_eq!(err.http_status(), StatusCode::NO_CONTENT);
+    // The automatic response is not a failure
+    assert!(err.is_response());
+    assert!(!err.is_error());
+    assert!(!err.http_status().is_client_error());
+    assert!(!err.http_status().is_server_error());
+    assert_eq!(
+        err.allowed_methods().unwrap(),
+        &[
+            &Method::GET,
+            &Method::DELETE,
+            &Method::HEAD,
+            &Method::PUT,
+            &Method::OPTIONS
+        ]
+    );
+    assert_eq!(
+        err.response().headers()["Allow"],
+        "GET, DELETE, HEAD, PUT, OPTIONS"
+    );
+
+    let route = invoke::<Routes>(Request::options("/custom").body(Body::empty()).unwrap()).unwrap();
+    assert_eq!(route, Routes::CustomOptions);
+
+    let err = options("/unknown");
+    assert_eq!(err.http_status(), StatusCode::NOT_FOUND);
+    assert!(err.is_error());
+    assert!(err.allowed_methods().is_none());
+    assert!(!err.response().headers().contains_key("Allow"));
+
+    // Other methods still result in `405 Method Not Allowed`
+    let err: Box<Error> = invoke::<Routes>(Request::post("/users/5").body(Body::empty()).unwrap())
+        .unwrap_err()
+        .downcast()
+        .unwrap();
+    assert_eq!(err.http_status(), StatusCode::METHOD_NOT_ALLOWED);
+    assert_eq!(
+        err.allowed_methods().unwrap(),
+        &[&Method::GET, &Method::DELETE, &Method::HEAD, &Method::PUT]
+    );
+}
+
+#[test]
+fn path_params() {
+    use hyperdrive::IntoRequest;
+    use serde::Serialize;
+
+    #[derive(Deserialize, Serialize, PartialEq, Eq, Debug)]
+    struct BuildPath {
+        org: String,
+        project: String,
+        build: u32,
+    }
+
+    // Only receives the placeholders without a field of their own
+    #[derive(Deserialize, Serialize, PartialEq, Eq, Debug)]
+    #[serde(deny_unknown_fields)]
+    struct ProjectPath {
+        org: String,
+        project: String,
+    }
+
+    #[derive(Deserialize, Serialize, PartialEq, Eq, Debug)]
+    struct UserPath {
+        id: u32,
+    }
+
+    #[derive(FromRequest, IntoRequest, ToUri, PartialEq, Eq, Debug)]
+    #[routing(fallthrough)]
+    enum Routes {
+        #[get("/orgs/{org}/projects/{project}/builds/{build}")]
+        Build {
+            #[path_params]
+            path: BuildPath,
+        },
+
+        // Placeholders can still be stored in fields as well
+        #[get("/orgs/{org}/projects/{project}/builds/{build}/logs/{line}")]
+        BuildLog {
+            #[path_params]
+            path: ProjectPath,
+            line: u32,
+            build: u32,
+        },
+
+        #[get("/users/{id}")]
+        UserById {
+            #[path_params]
+            path: UserPath,
+        },
+
+        #[get("/users/{name}")]
+        UserByName { name: String },
+    }
+
+    let get = |uri: &str| invoke::<Routes>(Request::get(uri).body(Body::empty()).unwrap());
+
+    let build = || BuildPath {
+        org: "acme corp".to_string(),
+        project: "web".to_string(),
+        build: 7,
+    };
+    assert_eq!(
+        get("/orgs/acme%20corp/projects/web/builds/7").unwrap(),
+        Routes::Build { path: build() }
+    );
+    assert_eq!(
+        get("/orgs/acme%20corp/projects/web/builds/7/logs/12").unwrap(),
+        Routes::BuildLog {
+            path: ProjectPath {
+                org: "acme corp".to_string(),
+                project: "web".to_string(),
+            },
+            line: 12,
+            build: 7,
+        }
+    );
+
+    // Placeholders that fail to deserialize reject the route (and fall through)
+    let err: Box<Error> = get("/orgs/acme/projects/web/builds/latest")
+        .unwrap_err()
+        .downcast()
+        .unwrap();
+    assert_eq!(err.http_status(), StatusCode::NOT_FOUND);
+    // Invalid percent-encoding selects the route and is rejected
+    let err: Box<Error> = get("/orgs/acme%FF/projects/web/builds/7")
+        .unwrap_err()
+        .downcast()
+        .unwrap();
+    assert_eq!(err.http_status(), StatusCode::BAD_REQUEST);
+    assert_eq!(
+        get("/users/5").unwrap(),
+        Routes::UserById {
+            path: UserPath { id: 5 }
+        }
+    );
+    assert_eq!(
+        get("/users/me").unwrap(),
+        Routes::UserByName {
+            name: "me".to_string()
+        }
+    );
+
+    // Round trip through `ToUri` and `IntoRequest`
+    assert_eq!(
+        Routes::Build { path: build() }.to_uri(),
+        "/orgs/acme%20corp/projects/web/builds/7"
+    );
+    let request = Routes::Build { path: build() }.into_request();
+    assert_eq!(request.uri(), "/orgs/acme%20corp/projects/web/builds/7");
+    assert_eq!(
+        invoke::<Routes>(request).unwrap(),
+        Routes::Build { path: build() }
+    );
+    let log = || Routes::BuildLog {
+        path: ProjectPath {
+            org: "acme".to_string(),
+            project: "web".to_string(),
+        },
+        line: 3,
+        build: 9,
+    };
+    let request = log().into_request();
+    assert_eq!(request.uri(), "/orgs/acme/projects/web/builds/9/logs/3");
+    assert_eq!(invoke::<Routes>(request).unwrap(), log());
+
+    let routes = Routes::routes();
+    let placeholders = routes[1]
+        .placeholders()
+        .iter()
+        .map(|p| (p.name(), p.ty()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        placeholders,
+        &[
+            ("org", "ProjectPath"),
+            ("project", "ProjectPath"),
+            ("build", "u32"),
+            ("line", "u32"),
+        ]
+    );
+}
+
+#[test]
+fn host_path_params() {
+    use hyperdrive::IntoRequest;
+    use serde::Serialize;
+
+    #[derive(Deserialize, Serialize, PartialEq, Eq, Debug)]
+    struct ItemPath {
+        tenant: String,
+        id: u32,
+    }
+
+    #[derive(FromRequest, IntoRequest, PartialEq, Eq, Debug)]
+    #[get("/items/{id}", host = "{tenant}.example.com")]
+    struct Item {
+        #[path_params]
+        path: ItemPath,
+    }
+
+    let item = || Item {
+        path: ItemPath {
+            tenant: "acme".to_string(),
+            id: 3,
+        },
+    };
+    let route = invoke::<Item>(
+        Request::get("/items/3")
+            .header("Host", "acme.example.com")
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(route, item());
+
+    let err: Box<Error> = invoke::<Item>(
+        Request::get("/items/x")
+            .header("Host", "acme.example.com")
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .unwrap_err()
+    .downcast()
+    .unwrap();
+    assert_eq!(err.http_status(), StatusCode::NOT_FOUND);
+    assert_eq!(
+        err.source().unwrap().to_string(),
+        "invalid parameter `id`: invalid value `x`: invalid digit found in string"
+    );
+
+    let request = item().into_request();
+    assert_eq!(request.headers()["Host"], "acme.example.com");
+    assert_eq!(invoke::<Item>(request).unwrap(), item());
+}
+
+#[test]
+fn query_params() {
+    #[derive(FromRequest, PartialEq, Eq, Debug)]
+    enum Routes {
+        #[get("/users")]
+        UserList {
+            #[query_params]
+            pagination: Pagination,
+        },
+    }
+
+    #[derive(Deserialize, PartialEq, Eq, Debug)]
+    struct Pagination {
+        #[serde(default)]
+        start: u32,
+        #[serde(default = "default_count")]
+        count: u32,
+    }
+
+    fn default_count() -> u32 {
+        10
+    }
+
+    let route = invoke::<Routes>(Request::get("/users").body(Body::empty()).unwrap()).unwrap();
+    assert_eq!(
+        route,
+        Routes::UserList {
+            pagination: Pagination {
+                start: 0,
+                count: 10,
+            }
+        }
+    );
+
+    let route =
+        invoke::<Routes>(Request::get("/users?count=30").body(Body::empty()).unwrap()).unwrap();
+    assert_eq!(
+        route,
+        Routes::UserList {
+            pagination: Pagination {
+                start: 0,
+                count: 30,
+            }
+        }
+    );
+
+    let route = invoke::<Routes>(
+        Request::get("/users?start=543")
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(
+        route,
+        Routes::UserList {
+            pagination: Pagination {
+                start: 543,
+                count: 10,
+            }
+        }
+    );
+
+    let route = invoke::<Routes>(
+        Request::get("/users?start=123&count=30")
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(
+        route,
+        Routes::UserList {
+            pagination: Pagination {
+                start: 123,
+                count: 30,
+            }
+        }
+    );
+}
+
+#[test]
+fn query() {
+    use hyperdrive::IntoRequest;
+
+    #[derive(FromRequest, IntoRequest, ToUri, PartialEq, Eq, Debug)]
+    enum Routes {
+        #[get("/search")]
+        Search {
+            #[query("q")]
+            term: String,
+            #[query]
+            page: Option<u32>,
+            #[query("tag")]
+            tags: Vec<String>,
+            #[query(default)]
+            exact: bool,
+        },
+
+        #[get("/users")]
+        Users {
+            #[query_params]
+            pagination: Pagination,
+            #[query]
+            sort: Option<String>,
+        },
+    }
+
+    #[derive(Deserialize, serde::Serialize, PartialEq, Eq, Debug)]
+    struct Pagination {
+        #[serde(default)]
+        start: u32,
+    }
+
+    let route = invoke::<Routes>(
+        Request::get("/search?q=hyper%20drive&tag=a&page=3&tag=b")
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(
+        route,
+        Routes::Search {
+            term: "hyper drive".to_string(),
+            page: Some(3),
+            tags: vec!["a".to_string(), "b".to_string()],
+            exact: false,
+        }
+    );
+
+    let route = invoke::<Routes>(
+        Request::get("/search?exact=true&q=")
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(
+        route,
+        Routes::Search {
+            term: String::new(),
+            page: None,
+            tags: Vec::new(),
+            exact: true,
+        }
+    );
+
+    // `#[query]` fields see the same parameters as `#[query_params]`
+    let route = invoke::<Routes>(
+        Request::get("/users?sort=name&start=20")
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(
+        route,
+        Routes::Users {
+            pagination: Pagination { start: 20 },
+            sort: Some("name".to_string()),
+        }
+    );
+
+    // Missing required parameter
+    let err: Box<Error> = invoke::<Routes>(Request::get("/search").body(Body::empty()).unwrap())
+        .unwrap_err()
+        .downcast()
+        .unwrap();
+    assert_eq!(err.http_status(), StatusCode::BAD_REQUEST);
+    assert_eq!(
+        err.source().unwrap().to_string(),
+        "missing required query parameter `q`"
+    );
+
+    // Malformed parameter
+    let err: Box<Error> = invoke::<Routes>(
+        Request::get("/search?q=x&page=last")
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .unwrap_err()
+    .downcast()
+    .unwrap();
+    assert_eq!(err.http_status(), StatusCode::BAD_REQUEST);
+    assert!(err
+        .source()
+        .unwrap()
+        .to_string()
+        .starts_with("invalid value for query parameter `page`: "));
+
+    // Round trip through `ToUri` and `IntoRequest`
+    let route = Routes::Search {
+        term: "a&b".to_string(),
+        page: Some(2),
+        tags: vec!["x".to_string(), "y".to_string()],
+        exact: false,
+    };
+    assert_eq!(
+        route.to_uri(),
+        "/search?q=a%26b&page=2&tag=x&tag=y&exact=false"
+    );
+    let route = Routes::Search {
+        term: "a".to_string(),
+        page: None,
+        tags: Vec::new(),
+        exact: true,
+    };
+    assert_eq!(route.to_uri(), "/search?q=a&exact=true");
+
+    let route = Routes::Search {
+        term: "a b".to_string(),
+        page: None,
+        tags: vec!["x".to_string()],
+        exact: false,
+    };
+    let request = route.into_request();
+    assert_eq!(request.uri(), "/search?q=a+b&tag=x&exact=false");
+    assert_eq!(
+        invoke::<Routes>(request).unwrap(),
+        Routes::Search {
+            term: "a b".to_string(),
+            page: None,
+            tags: vec!["x".to_string()],
+            exact: false,
+        }
+    );
+}
+
+#[test]
+fn nested_query_params() {
+    use hyperdrive::{body::NestedForm, IntoRequest};
+    use serde::Serialize;
+
+    #[derive(FromRequest, IntoRequest, ToUri, PartialEq, Eq, Debug)]
+    enum Routes {
+        #[get("/issues")]
+        Issues {
+            #[query_params(nested)]
+            query: IssueQuery,
+        },
+
+        #[post("/issues")]
+        NewIssues {
+            #[body]
+            form: NestedForm<NewIssues>,
+        },
+    }
+
+    #[derive(Deserialize, Serialize, PartialEq, Eq, Debug)]
+    struct IssueQuery {
+        ids: Vec<u32>,
+        filter: Option<Filter>,
+    }
+
+    #[derive(Deserialize, Serialize, PartialEq, Eq, Debug)]
+    struct Filter {
+        status: String,
+        #[serde(default)]
+        labels: Vec<String>,
+        limit: Option<u32>,
+    }
+
+    #[derive(Deserialize, Serialize, PartialEq, Eq, Debug)]
+    struct NewIssues {
+        issues: Vec<Issue>,
+    }
+
+    #[derive(Deserialize, Serialize, PartialEq, Eq, Debug)]
+    struct Issue {
+        title: String,
+    }
+
+    let get = |uri: &str| invoke::<Routes>(Request::get(uri).body(Body::empty()).unwrap());
+    let error = |uri: &str| -> String {
+        let err: Box<Error> = get(uri).unwrap_err().downcast().unwrap();
+        assert_eq!(err.http_status(), StatusCode::BAD_REQUEST);
+        err.source().unwrap().to_string()
+    };
+
+    let route =
+        get("/issues?ids=1&filter[status]=open&ids=2&filter[labels][]=a&filter[labels][]=b")
+            .unwrap();
+    assert_eq!(
+        route,
+        Routes::Issues {
+            query: IssueQuery {
+                ids: vec![1, 2],
+                filter: Some(Filter {
+                    status: "open".to_string(),
+                    labels: vec!["a".to_string(), "b".to_string()],
+                    limit: None,
+                }),
+            },
+        }
+    );
+
+    // A single value is a sequence of one, indices determine the order
+    let route = get("/issues?ids=5&filter%5Bstatus%5D=closed").unwrap();
+    assert_eq!(
+        route,
+        Routes::Issues {
+            query: IssueQuery {
+                ids: vec![5],
+                filter: Some(Filter {
+                    status: "closed".to_string(),
+                    labels: Vec::new(),
+                    limit: None,
+                }),
+            },
+        }
+    );
+    let route = get("/issues?ids[1]=20&ids[0]=10").unwrap();
+    assert_eq!(
+        route,
+        Routes::Issues {
+            query: IssueQuery {
+                ids: vec![10, 20],
+                filter: None,
+            },
+        }
+    );
+
+    // Errors name the offending parameter
+    assert_eq!(
+        error("/issues?ids=1&ids=x"),
+        "invalid parameter `ids`: invalid value `x`: invalid digit found in string"
+    );
+    assert_eq!(
+        error("/issues?ids=1&filter[status]=open&filter[limit]=-1"),
+        "invalid parameter `filter[limit]`: invalid value `-1`: invalid digit found in string"
+    );
+    assert_eq!(
+        error("/issues?ids=1&filter[limit]=5"),
+        "invalid parameter `filter`: missing field `status`"
+    );
+    assert_eq!(
+        error("/issues?ids=1&filter[status]=a&filter[status]=b"),
+        "invalid parameter `filter[status]`: expected a single value, found 2"
+    );
+    assert_eq!(
+        error("/issues?ids=1&filter=open"),
+        "invalid parameter `filter`: expected nested parameters (like `filter[key]`)"
+    );
+    assert_eq!(
+        error("/issues?ids=1&ids[0]=2"),
+        "parameter `ids[0]` is used both with and without nested keys"
+    );
+    assert_eq!(
+        error("/issues?filter[status=open"),
+        "malformed parameter name `filter[status`"
+    );
+    let deep = format!("/issues?ids=1&filter{}=open", "[a]".repeat(33));
+    assert!(error(&deep).ends_with("is nested more than 32 levels deep"));
+
+    // Nested form bodies
+    let route = invoke::<Routes>(
+        Request::post("/issues")
+            .body("issues[0][title]=a&issues[1][title]=b".into())
+            .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(
+        route,
+        Routes::NewIssues {
+            form: NestedForm(NewIssues {
+                issues: vec![
+                    Issue {
+                        title: "a".to_string()
+                    },
+                    Issue {
+                        title: "b".to_string()
+                    },
+                ],
+            }),
+        }
+    );
+    let err: Box<Error> = invoke::<Routes>(
+        Request::post("/issues")
+            .body("issues[0][name]=a".into())
+            .unwrap(),
+    )
+    .unwrap_err()
+    .downcast()
+    .unwrap();
+    assert_eq!(err.http_status(), StatusCode::BAD_REQUEST);
+    assert_eq!(
+        err.source().unwrap().to_string(),
+        "invalid parameter `issues[0]`: missing field `title`"
+    );
 
-    let anyhead = invoke::<Routes>(Request::get("/2/other").body(Body::empty()).unwrap()).unwrap();
-    assert_eq!(anyhead, Routes::Other);
+    // `ToUri` and `IntoRequest` encode nested values
+    let query = IssueQuery {
+        ids: vec![1, 2],
+        filter: Some(Filter {
+            status: "open".to_string(),
+            labels: vec!["a b".to_string()],
+            limit: Some(10),
+        }),
+    };
+    let route = Routes::Issues { query };
+    let uri = route.to_uri();
+    assert_eq!(
+        uri,
+        "/issues?filter%5Blabels%5D=a+b&filter%5Blimit%5D=10&filter%5Bstatus%5D=open&ids=1&ids=2"
+    );
+    assert_eq!(get(&uri.to_string()).unwrap(), route);
+
+    let route = || Routes::NewIssues {
+        form: NestedForm(NewIssues {
+            issues: vec![Issue {
+                title: "a&b".to_string(),
+            }],
+        }),
+    };
+    let request = route().into_request();
+    assert_eq!(invoke::<Routes>(request).unwrap(), route());
 }
 
 #[test]
-fn query_params() {
+fn headers() {
     #[derive(FromRequest, PartialEq, Eq, Debug)]
     enum Routes {
-        #[get("/users")]
-        UserList {
-            #[query_params]
-            pagination: Pagination,
+        #[get("/")]
+        Index {
+            #[header("X-Request-Id")]
+            id: u64,
+            #[header]
+            x_client_version: Option<u32>,
         },
     }
 
-    #[derive(Deserialize, PartialEq, Eq, Debug)]
-    struct Pagination {
-        #[serde(default)]
-        start: u32,
-        #[serde(default = "default_count")]
-        count: u32,
-    }
-
-    fn default_count() -> u32 {
-        10
-    }
-
-    let route = invoke::<Routes>(Request::get("/users").body(Body::empty()).unwrap()).unwrap();
+    let route = invoke::<Routes>(
+        Request::get("/")
+            .header("x-request-id", "123")
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .unwrap();
     assert_eq!(
         route,
-        Routes::UserList {
-            pagination: Pagination {
-                start: 0,
-                count: 10,
-            }
+        Routes::Index {
+            id: 123,
+            x_client_version: None,
         }
     );
 
-    let route =
-        invoke::<Routes>(Request::get("/users?count=30").body(Body::empty()).unwrap()).unwrap();
+    let route = invoke::<Routes>(
+        Request::get("/")
+            .header("X-Request-Id", "123")
+            .header("X-Client-Version", "2")
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .unwrap();
     assert_eq!(
         route,
-        Routes::UserList {
-            pagination: Pagination {
-                start: 0,
-                count: 30,
-            }
+        Routes::Index {
+            id: 123,
+            x_client_version: Some(2),
         }
     );
 
+    // Missing required header
+    let err: Box<Error> = invoke::<Routes>(Request::get("/").body(Body::empty()).unwrap())
+        .unwrap_err()
+        .downcast()
+        .unwrap();
+    assert_eq!(err.http_status(), StatusCode::BAD_REQUEST);
+    assert!(err.source().is_some());
+
+    // Malformed required header
+    let err: Box<Error> = invoke::<Routes>(
+        Request::get("/")
+            .header("X-Request-Id", "abc")
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .unwrap_err()
+    .downcast()
+    .unwrap();
+    assert_eq!(err.http_status(), StatusCode::BAD_REQUEST);
+    assert!(err
+        .source()
+        .expect("no error source")
+        .is::<std::num::ParseIntError>());
+
+    // Malformed optional header
+    let err: Box<Error> = invoke::<Routes>(
+        Request::get("/")
+            .header("X-Request-Id", "1")
+            .header("X-Client-Version", "latest")
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .unwrap_err()
+    .downcast()
+    .unwrap();
+    assert_eq!(err.http_status(), StatusCode::BAD_REQUEST);
+}
+
+#[test]
+fn cookies() {
+    #[derive(FromRequest, PartialEq, Eq, Debug)]
+    enum Routes {
+        #[get("/")]
+        Index {
+            #[cookie("session")]
+            session: String,
+            #[cookie]
+            page_size: Option<u32>,
+            jar: Cookies,
+        },
+    }
+
     let route = invoke::<Routes>(
-        Request::get("/users?start=543")
+        Request::get("/")
+            .header("Cookie", "session=abc; other=\"quoted value\"")
+            .header("Cookie", "page_size=20; session=ignored")
             .body(Body::empty())
             .unwrap(),
     )
     .unwrap();
-    assert_eq!(
-        route,
-        Routes::UserList {
-            pagination: Pagination {
-                start: 543,
-                count: 10,
-            }
+    match route {
+        Routes::Index {
+            session,
+            page_size,
+            jar,
+        } => {
+            assert_eq!(session, "abc");
+            assert_eq!(page_size, Some(20));
+            assert_eq!(jar.len(), 4);
+            assert_eq!(jar.get("session"), Some("abc"));
+            assert_eq!(jar.get("other"), Some("quoted value"));
+            assert_eq!(jar.get("page_size"), Some("20"));
+            assert_eq!(jar.get("missing"), None);
+            assert_eq!(
+                jar.iter().collect::<Vec<_>>(),
+                vec![
+                    ("session", "abc"),
+                    ("other", "quoted value"),
+                    ("page_size", "20"),
+                    ("session", "ignored"),
+                ]
+            );
         }
-    );
+    }
 
     let route = invoke::<Routes>(
-        Request::get("/users?start=123&count=30")
+        Request::get("/")
+            .header("Cookie", "session=abc")
             .body(Body::empty())
             .unwrap(),
     )
     .unwrap();
-    assert_eq!(
-        route,
-        Routes::UserList {
-            pagination: Pagination {
-                start: 123,
-                count: 30,
-            }
-        }
-    );
+    match route {
+        Routes::Index { page_size, .. } => assert_eq!(page_size, None),
+    }
+
+    // Missing required cookie
+    let err: Box<Error> = invoke::<Routes>(
+        Request::get("/")
+            .header("Cookie", "page_size=1")
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .unwrap_err()
+    .downcast()
+    .unwrap();
+    assert_eq!(err.http_status(), StatusCode::BAD_REQUEST);
+
+    // Malformed optional cookie
+    let err: Box<Error> = invoke::<Routes>(
+        Request::get("/")
+            .header("Cookie", "session=abc; page_size=many")
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .unwrap_err()
+    .downcast()
+    .unwrap();
+    assert_eq!(err.http_status(), StatusCode::BAD_REQUEST);
+    assert!(err
+        .source()
+        .expect("no error source")
+        .is::<std::num::ParseIntError>());
 }
 
 /// Tests that the derive works on generic enums and structs.
@@ -551,23 +2223,290 @@ fn forward() {
         "HEAD /"
     );
 
-    let route = invoke::<Enum>(Request::get("/flabberghast").body(Body::empty()).unwrap()).unwrap();
-    assert_eq!(
-        route,
-        Enum::Second {
-            _inner: Inner::Flabberghast
-        },
-        "GET /flabberghast"
-    );
+    let route = invoke::<Enum>(Request::get("/flabberghast").body(Body::empty()).unwrap()).unwrap();
+    assert_eq!(
+        route,
+        Enum::Second {
+            _inner: Inner::Flabberghast
+        },
+        "GET /flabberghast"
+    );
+
+    let route = invoke::<Enum>(Request::post("/").body(Body::empty()).unwrap()).unwrap();
+    assert_eq!(
+        route,
+        Enum::Second {
+            _inner: Inner::Post
+        },
+        "POST /"
+    );
+}
+
+/// Tests that `#[forward]` converts the context to the one used by the forwarded-to type.
+#[test]
+fn forward_context() {
+    #[derive(RequestContext, Clone, Debug)]
+    struct UsersContext {
+        admin: String,
+    }
+
+    #[derive(RequestContext, Debug)]
+    struct AppContext {
+        #[as_ref]
+        users: UsersContext,
+    }
+
+    #[derive(Debug, PartialEq, Eq)]
+    struct Admin(String);
+
+    impl Guard for Admin {
+        type Context = UsersContext;
+        type Result = Result<Self, BoxedError>;
+
+        fn from_request(
+            _request: &Arc<http::Request<()>>,
+            context: &Self::Context,
+        ) -> Self::Result {
+            Ok(Admin(context.admin.clone()))
+        }
+    }
+
+    #[derive(FromRequest, Debug, PartialEq, Eq)]
+    #[context(UsersContext)]
+    enum Users {
+        #[get("/users")]
+        List { admin: Admin },
+    }
+
+    #[derive(FromRequest, Debug, PartialEq, Eq)]
+    enum Status {
+        #[get("/status")]
+        Status,
+    }
+
+    #[derive(FromRequest, Debug, PartialEq, Eq)]
+    #[context(AppContext)]
+    enum Routes {
+        Users(#[forward] Users),
+
+        #[get("/status")]
+        Status(#[forward] Status),
+    }
+
+    let context = || AppContext {
+        users: UsersContext {
+            admin: "root".to_string(),
+        },
+    };
+
+    let route: Routes = invoke_with(
+        Request::get("/users").body(Body::empty()).unwrap(),
+        context(),
+    )
+    .unwrap();
+    assert_eq!(
+        route,
+        Routes::Users(Users::List {
+            admin: Admin("root".to_string())
+        })
+    );
+
+    let route: Routes = invoke_with(
+        Request::get("/status").body(Body::empty()).unwrap(),
+        context(),
+    )
+    .unwrap();
+    assert_eq!(route, Routes::Status(Status::Status));
+}
+
+/// Tests that multiple fallback variants are tried in declaration order.
+#[test]
+fn fallback_chain() {
+    #[derive(FromRequest, PartialEq, Eq, Debug)]
+    enum Billing {
+        #[get("/invoices")]
+        Invoices,
+
+        #[get("/shared")]
+        Shared,
+    }
+
+    #[derive(FromRequest, PartialEq, Eq, Debug)]
+    enum Users {
+        #[post("/users")]
+        Create(#[body] Json<String>),
+
+        #[post("/shared")]
+        Shared,
+    }
+
+    #[derive(FromRequest, PartialEq, Eq, Debug)]
+    enum Admin {
+        #[get("/admin")]
+        Index,
+    }
+
+    #[derive(FromRequest, PartialEq, Eq, Debug)]
+    enum Routes {
+        #[get("/")]
+        Index,
+
+        Billing(#[forward] Billing),
+        Users(#[forward] Users),
+        Admin(#[forward] Admin),
+    }
+
+    let route = invoke::<Routes>(Request::get("/invoices").body(Body::empty()).unwrap()).unwrap();
+    assert_eq!(route, Routes::Billing(Billing::Invoices));
+
+    let route = invoke::<Routes>(Request::get("/admin").body(Body::empty()).unwrap()).unwrap();
+    assert_eq!(route, Routes::Admin(Admin::Index));
+
+    // The body is passed on to the variant accepting the request
+    let request = Request::post("/users")
+        .header("Content-Type", "application/json")
+        .body(r#""alice""#.into())
+        .unwrap();
+    let route = invoke::<Routes>(request).unwrap();
+    assert_eq!(
+        route,
+        Routes::Users(Users::Create(Json("alice".to_string())))
+    );
+
+    // A variant rejecting the method doesn't stop the chain
+    let route = invoke::<Routes>(Request::get("/shared").body(Body::empty()).unwrap()).unwrap();
+    assert_eq!(route, Routes::Billing(Billing::Shared));
+    let route = invoke::<Routes>(Request::post("/shared").body(Body::empty()).unwrap()).unwrap();
+    assert_eq!(route, Routes::Users(Users::Shared));
+
+    // The allowed methods of all variants are merged
+    let error: Box<Error> =
+        invoke::<Routes>(Request::delete("/shared").body(Body::empty()).unwrap())
+            .unwrap_err()
+            .downcast()
+            .unwrap();
+    assert_eq!(error.http_status(), StatusCode::METHOD_NOT_ALLOWED);
+    assert_eq!(
+        error.allowed_methods().expect("allowed_methods()"),
+        &[&Method::GET, &Method::HEAD, &Method::POST]
+    );
+
+    let error: Box<Error> = invoke::<Routes>(Request::get("/missing").body(Body::empty()).unwrap())
+        .unwrap_err()
+        .downcast()
+        .unwrap();
+    assert_eq!(error.http_status(), StatusCode::NOT_FOUND);
+}
+
+/// Tests that a buffered `#[body]` can be combined with `#[forward]`.
+#[test]
+fn buffered_body() {
+    /// Counts the bytes in the body.
+    #[derive(Debug, PartialEq, Eq)]
+    struct Length(usize);
+
+    impl FromBody for Length {
+        type Context = NoContext;
+        type Result = DefaultFuture<Self, BoxedError>;
+
+        fn from_body(
+            _request: &Arc<http::Request<()>>,
+            body: Body,
+            _context: &Self::Context,
+        ) -> Self::Result {
+            Box::new(
+                body.concat2()
+                    .map(|body| Length(body.len()))
+                    .map_err(Into::into),
+            )
+        }
+    }
+
+    #[derive(FromRequest, Debug, PartialEq, Eq)]
+    enum Inner {
+        #[post("/items")]
+        Create(#[body] Json<String>),
+    }
+
+    #[derive(FromRequest, Debug, PartialEq, Eq)]
+    struct Outer {
+        #[body(buffer_limit = 16)]
+        length: Length,
+
+        #[forward]
+        inner: Inner,
+    }
+
+    #[derive(FromRequest, Debug, PartialEq, Eq)]
+    enum Routes {
+        #[put("/length")]
+        Length(#[body(buffer_limit = 16)] Length),
+    }
+
+    let request = |path: &str, body: &'static str| {
+        Request::post(path)
+            .header("Content-Type", "application/json")
+            .body(Body::from(body))
+            .unwrap()
+    };
+
+    let route = invoke::<Outer>(request("/items", r#""hello""#)).unwrap();
+    assert_eq!(
+        route,
+        Outer {
+            length: Length(7),
+            inner: Inner::Create(Json("hello".to_string())),
+        }
+    );
+
+    let error: Box<Error> = invoke::<Outer>(request("/items", r#""hello, world!!!""#))
+        .unwrap_err()
+        .downcast()
+        .unwrap();
+    assert_eq!(error.http_status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+    // Errors of the forwarded-to type are passed through
+    let error: Box<Error> = invoke::<Outer>(request("/other", r#""hello""#))
+        .unwrap_err()
+        .downcast()
+        .unwrap();
+    assert_eq!(error.http_status(), StatusCode::NOT_FOUND);
+
+    // The body is only read once the forwarded-to type accepts the request
+    let error: Box<Error> = invoke::<Outer>(request("/other", r#""hello, world!!!""#))
+        .unwrap_err()
+        .downcast()
+        .unwrap();
+    assert_eq!(error.http_status(), StatusCode::NOT_FOUND);
+
+    #[derive(FromRequest, Debug, PartialEq, Eq)]
+    enum Other {
+        #[post("/other")]
+        Create(#[body] Json<String>),
+    }
+
+    #[derive(FromRequest, Debug, PartialEq, Eq)]
+    enum Fallbacks {
+        Outer(#[forward] Outer),
+        Other(#[forward] Other),
+    }
 
-    let route = invoke::<Enum>(Request::post("/").body(Body::empty()).unwrap()).unwrap();
+    let route = invoke::<Fallbacks>(request("/other", r#""hello""#)).unwrap();
     assert_eq!(
         route,
-        Enum::Second {
-            _inner: Inner::Post
-        },
-        "POST /"
+        Fallbacks::Other(Other::Create(Json("hello".to_string())))
     );
+
+    // Buffering also works without `#[forward]`
+    let request = Request::put("/length").body("16 bytes exactly".into());
+    let route = invoke::<Routes>(request.unwrap()).unwrap();
+    assert_eq!(route, Routes::Length(Length(16)));
+    let request = Request::put("/length").body("seventeen bytes!!".into());
+    let error: Box<Error> = invoke::<Routes>(request.unwrap())
+        .unwrap_err()
+        .downcast()
+        .unwrap();
+    assert_eq!(error.http_status(), StatusCode::PAYLOAD_TOO_LARGE);
 }
 
 /// Tests that invalid methods return the right set of allowed methods, even in the presence of
@@ -681,6 +2620,599 @@ fn forward_allowed_methods() {
     assert_eq!(route, Wrapper::Shared2 { s: 123 });
 }
 
+/// Tests that `#[routing(fallthrough)]` falls back to the `#[forward]` variant when no route's
+/// placeholders parse.
+#[test]
+fn fallthrough_forward() {
+    #[derive(FromRequest, PartialEq, Eq, Debug)]
+    enum Inner {
+        #[get("/{name}")]
+        Name { name: String },
+    }
+
+    #[derive(FromRequest, PartialEq, Eq, Debug)]
+    #[routing(fallthrough)]
+    enum Wrapper {
+        #[get("/{id}")]
+        Id { id: u32 },
+
+        Fallback {
+            #[forward]
+            inner: Inner,
+        },
+    }
+
+    let route = invoke::<Wrapper>(Request::get("/123").body(Body::empty()).unwrap()).unwrap();
+    assert_eq!(route, Wrapper::Id { id: 123 });
+
+    let route = invoke::<Wrapper>(Request::get("/abc").body(Body::empty()).unwrap()).unwrap();
+    assert_eq!(
+        route,
+        Wrapper::Fallback {
+            inner: Inner::Name {
+                name: "abc".to_string()
+            }
+        }
+    );
+
+    let err: Box<Error> = invoke::<Wrapper>(Request::post("/abc").body(Body::empty()).unwrap())
+        .unwrap_err()
+        .downcast()
+        .unwrap();
+    assert_eq!(err.http_status(), StatusCode::METHOD_NOT_ALLOWED);
+    assert_eq!(
+        err.allowed_methods().expect("allowed_methods()"),
+        &[&Method::GET, &Method::HEAD]
+    );
+}
+
+#[test]
+fn forward_prefix() {
+    #[derive(FromRequest, PartialEq, Eq, Debug)]
+    enum Api {
+        #[get("/")]
+        Index,
+
+        #[get("/users/{id}")]
+        User { id: u32, uri: OriginalUri },
+    }
+
+    #[derive(FromRequest, PartialEq, Eq, Debug)]
+    enum Routes {
+        #[get("/")]
+        Index,
+
+        #[get("/api")]
+        ApiInfo,
+
+        V1 {
+            #[forward(prefix = "/api/v1")]
+            api: Api,
+        },
+
+        V2 {
+            #[forward(prefix = "/api/v2")]
+            api: Api,
+        },
+    }
+
+    let route = invoke::<Routes>(Request::get("/api/v1").body(Body::empty()).unwrap()).unwrap();
+    assert_eq!(route, Routes::V1 { api: Api::Index });
+
+    let route = invoke::<Routes>(Request::get("/api/v2/").body(Body::empty()).unwrap()).unwrap();
+    assert_eq!(route, Routes::V2 { api: Api::Index });
+
+    let route = invoke::<Routes>(
+        Request::get("/api/v1/users/5?verbose")
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .unwrap();
+    match route {
+        Routes::V1 {
+            api: Api::User { id, uri },
+        } => {
+            assert_eq!(id, 5);
+            assert_eq!(uri.path(), "/api/v1/users/5");
+            assert_eq!(uri.uri().query(), Some("verbose"));
+        }
+        _ => panic!("unexpected route {:?}", route),
+    }
+
+    let route = invoke::<Routes>(Request::get("/api").body(Body::empty()).unwrap()).unwrap();
+    assert_eq!(route, Routes::ApiInfo);
+
+    for path in &["/api/v10", "/api/v3/users/5", "/api/v1/users"] {
+        let err: Box<Error> = invoke::<Routes>(Request::get(*path).body(Body::empty()).unwrap())
+            .unwrap_err()
+            .downcast()
+            .unwrap();
+        assert_eq!(err.http_status(), StatusCode::NOT_FOUND, "{}", path);
+    }
+
+    let err: Box<Error> =
+        invoke::<Routes>(Request::post("/api/v2/users/5").body(Body::empty()).unwrap())
+            .unwrap_err()
+            .downcast()
+            .unwrap();
+    assert_eq!(err.http_status(), StatusCode::METHOD_NOT_ALLOWED);
+    assert_eq!(
+        err.allowed_methods().expect("allowed_methods()"),
+        &[&Method::GET, &Method::HEAD]
+    );
+}
+
+#[test]
+fn route_table() {
+    #[derive(Deserialize)]
+    struct Filter {}
+
+    #[derive(FromRequest)]
+    #[allow(dead_code)]
+    enum Api {
+        #[get("/users/{id}")]
+        User { id: u32 },
+
+        #[post("/users/{id}/posts/{post}")]
+        NewPost {
+            id: u32,
+            post: std::string::String,
+
+            #[body]
+            body: Json<Filter>,
+        },
+    }
+
+    #[derive(FromRequest)]
+    #[allow(dead_code)]
+    enum Routes {
+        #[get("/")]
+        #[head("/")]
+        Index,
+
+        #[get("/search")]
+        Search {
+            #[query_params]
+            query: Filter,
+        },
+
+        Api {
+            #[forward(prefix = "/api")]
+            api: Api,
+        },
+    }
+
+    let table = Routes::routes()
+        .iter()
+        .map(|route| route.to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        table,
+        &[
+            "GET /",
+            "HEAD /",
+            "GET /search",
+            "HEAD /search",
+            "GET /api/users/{id}",
+            "POST /api/users/{id}/posts/{post}",
+            "HEAD /api/users/{id}",
+        ]
+    );
+
+    let routes = Routes::routes();
+    assert!(!routes[1].is_implied_head());
+    assert!(routes[3].is_implied_head());
+    assert!(routes[3].has_query_params());
+    assert!(!routes[0].has_query_params());
+    assert_eq!(routes[4].variants(), &["Api", "User"]);
+    assert_eq!(routes[4].variant(), "User");
+    assert!(routes[5].has_body());
+    assert_eq!(routes[5].method(), Method::POST);
+    assert_eq!(routes[5].path(), "/api/users/{id}/posts/{post}");
+
+    let placeholders = routes[5]
+        .placeholders()
+        .iter()
+        .map(|p| (p.name(), p.ty()))
+        .collect::<Vec<_>>();
+    assert_eq!(placeholders, &[("id", "u32"), ("post", "std::string::String")]);
+
+    // The table is only built once
+    assert_eq!(Routes::routes().as_ptr(), routes.as_ptr());
+    assert_eq!(Api::routes()[0].path(), "/users/{id}");
+    assert_eq!(Api::routes()[0].variants(), &["User"]);
+}
+
+#[test]
+fn build_uri() {
+    #[derive(Deserialize, serde::Serialize, Debug, PartialEq, Eq)]
+    struct Search {
+        q: String,
+        page: Option<u32>,
+    }
+
+    #[derive(Deserialize, serde::Serialize, Debug, PartialEq, Eq)]
+    struct Filters {
+        tags: Vec<String>,
+        range: Range,
+    }
+
+    #[derive(Deserialize, serde::Serialize, Debug, PartialEq, Eq)]
+    struct Range {
+        from: u32,
+        to: u32,
+    }
+
+    #[derive(FromRequest, ToUri, Debug, PartialEq, Eq)]
+    enum Routes {
+        #[get("/")]
+        Index,
+
+        #[get("/users/{id}/posts/{post}")]
+        #[delete("/posts/{id}/{post}")]
+        UserPost { id: u32, post: String },
+
+        #[get("/reports/{id}.{format}")]
+        Report { format: String, id: u32 },
+
+        #[get("/files/{path...}")]
+        File { path: String },
+
+        #[get("/search")]
+        Search {
+            #[query_params]
+            query: Search,
+        },
+
+        #[route(method = "OPTIONS", path = "*")]
+        Asterisk,
+
+        #[get("/list")]
+        List {
+            #[query_params]
+            filters: Filters,
+        },
+    }
+
+    #[derive(FromRequest, ToUri, Debug, PartialEq, Eq)]
+    #[get("/inner/{name}")]
+    struct Inner {
+        name: String,
+    }
+
+    let user_post = |id, post: &str| Routes::UserPost {
+        id,
+        post: post.to_string(),
+    };
+    assert_eq!(Routes::Index.to_uri(), "/");
+    assert_eq!(user_post(5, "hello").to_uri(), "/users/5/posts/hello");
+    assert_eq!(
+        user_post(5, "a/b c?%").to_uri(),
+        "/users/5/posts/a%2Fb%20c%3F%25"
+    );
+    let report = Routes::Report {
+        format: "csv".to_string(),
+        id: 12,
+    };
+    assert_eq!(report.to_uri(), "/reports/12.csv");
+    let file = Routes::File {
+        path: "dir/a b.txt".to_string(),
+    };
+    assert_eq!(file.to_uri(), "/files/dir/a%20b.txt");
+    assert_eq!(Routes::Asterisk.to_uri(), "*");
+    let inner = Inner {
+        name: "ü".to_string(),
+    };
+    assert_eq!(inner.to_uri(), "/inner/%C3%BC");
+
+    let search = Routes::Search {
+        query: Search {
+            q: "rust & http".to_string(),
+            page: Some(2),
+        },
+    };
+    assert_eq!(search.to_uri(), "/search?q=rust+%26+http&page=2");
+    let search = Routes::Search {
+        query: Search {
+            q: String::new(),
+            page: None,
+        },
+    };
+    assert_eq!(search.to_uri(), "/search?q=");
+
+    // Values `serde_urlencoded` can't encode use bracketed names instead of panicking
+    let list = Routes::List {
+        filters: Filters {
+            tags: vec!["a".to_string(), "b".to_string()],
+            range: Range { from: 1, to: 2 },
+        },
+    };
+    assert_eq!(
+        list.to_uri(),
+        "/list?range%5Bfrom%5D=1&range%5Bto%5D=2&tags=a&tags=b"
+    );
+
+    // The built URIs are accepted by the routes
+    let uri = user_post(7, "post").to_uri();
+    let route = invoke::<Routes>(Request::get(uri).body(Body::empty()).unwrap()).unwrap();
+    assert_eq!(route, user_post(7, "post"));
+}
+
+#[test]
+fn into_request() {
+    use hyperdrive::{body::HtmlForm, IntoRequest};
+    use serde::Serialize;
+
+    #[derive(Deserialize, Serialize, Debug, PartialEq, Eq)]
+    struct Data {
+        name: String,
+        tags: Vec<String>,
+    }
+
+    #[derive(Deserialize, Serialize, Debug, PartialEq, Eq)]
+    struct Login {
+        user: String,
+    }
+
+    #[derive(FromRequest, IntoRequest, Debug, PartialEq, Eq)]
+    enum Api {
+        #[get("/")]
+        Index,
+
+        #[put("/items/{name}")]
+        Item {
+            name: String,
+
+            #[body]
+            data: Json<Data>,
+        },
+    }
+
+    #[derive(FromRequest, IntoRequest, Debug, PartialEq, Eq)]
+    enum Routes {
+        #[get("/files/{dir}/{path...}")]
+        File {
+            dir: String,
+            path: String,
+
+            #[header("X-Request-Id")]
+            request_id: Option<u32>,
+
+            #[cookie]
+            session: String,
+
+            #[cookie]
+            theme: Option<String>,
+        },
+
+        #[post("/login")]
+        Login {
+            #[body]
+            form: HtmlForm<Login>,
+        },
+
+        #[get("/search")]
+        Search {
+            #[query_params]
+            query: Login,
+        },
+
+        V1 {
+            #[forward(prefix = "/api/v1")]
+            api: Api,
+        },
+
+        Unroutable,
+    }
+
+    let routes = || {
+        vec![
+            Routes::File {
+                dir: "dir".to_string(),
+                path: "some/file.txt".to_string(),
+                request_id: Some(7),
+                session: "abc".to_string(),
+                theme: None,
+            },
+            Routes::File {
+                dir: "x".to_string(),
+                path: String::new(),
+                request_id: None,
+                session: "abc".to_string(),
+                theme: Some("dark".to_string()),
+            },
+            Routes::Login {
+                form: HtmlForm(Login {
+                    user: "me & you".to_string(),
+                }),
+            },
+            Routes::Search {
+                query: Login {
+                    user: "?".to_string(),
+                },
+            },
+            Routes::V1 { api: Api::Index },
+            Routes::V1 {
+                api: Api::Item {
+                    name: "thing".to_string(),
+                    data: Json(Data {
+                        name: "Thing".to_string(),
+                        tags: vec!["a".to_string(), "b".to_string()],
+                    }),
+                },
+            },
+        ]
+    };
+
+    let requests = routes()
+        .into_iter()
+        .map(IntoRequest::into_request)
+        .collect::<Vec<_>>();
+    assert_eq!(
+        requests
+            .iter()
+            .map(|request| format!("{} {}", request.method(), request.uri()))
+            .collect::<Vec<_>>(),
+        &[
+            "GET /files/dir/some/file.txt",
+            "GET /files/x/",
+            "POST /login",
+            "GET /search?user=%3F",
+            "GET /api/v1/",
+            "PUT /api/v1/items/thing",
+        ]
+    );
+
+    let headers = requests[0].headers();
+    assert_eq!(headers["x-request-id"], "7");
+    assert_eq!(headers["cookie"], "session=abc");
+    let headers = requests[1].headers();
+    assert!(headers.get("x-request-id").is_none());
+    assert_eq!(headers["cookie"], "session=abc; theme=dark");
+    assert_eq!(
+        requests[2].headers()["content-type"],
+        "application/x-www-form-urlencoded"
+    );
+    assert_eq!(requests[5].headers()["content-type"], "application/json");
+
+    // Every request decodes to the original route
+    for (route, request) in routes().into_iter().zip(requests) {
+        assert_eq!(invoke::<Routes>(request).unwrap(), route);
+    }
+
+    let result = std::panic::catch_unwind(|| Routes::Unroutable.into_request());
+    assert!(result.is_err());
+
+    // Cookie values that would corrupt the `Cookie` header are rejected
+    let result = std::panic::catch_unwind(|| {
+        Routes::File {
+            dir: "dir".to_string(),
+            path: String::new(),
+            request_id: None,
+            session: "abc; admin=1".to_string(),
+            theme: None,
+        }
+        .into_request()
+    });
+    assert!(result.is_err());
+}
+
+#[test]
+fn tuple_variants() {
+    use hyperdrive::IntoRequest;
+
+    #[derive(FromRequest, IntoRequest, Debug, PartialEq, Eq)]
+    enum Inner {
+        #[get("/inner/{0}")]
+        Item(u32),
+    }
+
+    #[derive(FromRequest, IntoRequest, Debug, PartialEq, Eq)]
+    enum Routes {
+        #[get("/users/{0}/posts/{1}")]
+        Post(u32, String),
+
+        #[put("/notes/{1}")]
+        Note(
+            #[body] Json<Vec<String>>,
+            u32,
+            #[header("X-Request-Id")] Option<String>,
+            MyGuard,
+        ),
+
+        #[get("/files/{0...}", host = "{1}.example.com")]
+        File(String, String),
+
+        Fallback(#[forward] Inner),
+    }
+
+    #[derive(FromRequest, IntoRequest, ToUri, Debug, PartialEq, Eq)]
+    #[get("/download/{0}")]
+    struct Download(u32, #[query_params] Query);
+
+    #[derive(Deserialize, serde::Serialize, Debug, PartialEq, Eq)]
+    struct Query {
+        name: String,
+    }
+
+    let route = invoke::<Routes>(
+        Request::get("/users/5/posts/hello%20world")
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(route, Routes::Post(5, "hello world".to_string()));
+
+    let route = invoke::<Routes>(
+        Request::put("/notes/3")
+            .header("X-Request-Id", "abc")
+            .body(Body::from(r#"["a","b"]"#))
+            .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(
+        route,
+        Routes::Note(
+            Json(vec!["a".to_string(), "b".to_string()]),
+            3,
+            Some("abc".to_string()),
+            MyGuard
+        )
+    );
+
+    let route = invoke::<Routes>(Request::get("/inner/9").body(Body::empty()).unwrap()).unwrap();
+    assert_eq!(route, Routes::Fallback(Inner::Item(9)));
+
+    let route = invoke::<Download>(
+        Request::get("/download/1?name=x")
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(
+        route,
+        Download(
+            1,
+            Query {
+                name: "x".to_string()
+            }
+        )
+    );
+
+    let placeholders = Routes::routes()[0]
+        .placeholders()
+        .iter()
+        .map(|placeholder| placeholder.name())
+        .collect::<Vec<_>>();
+    assert_eq!(placeholders, &["0", "1"]);
+
+    let routes = || {
+        vec![
+            Routes::Post(1, "x".to_string()),
+            Routes::File("a/b.txt".to_string(), "docs".to_string()),
+            Routes::Fallback(Inner::Item(2)),
+        ]
+    };
+    for (route, expected) in routes().into_iter().zip(routes()) {
+        assert_eq!(invoke::<Routes>(route.into_request()).unwrap(), expected);
+    }
+    let request = Download(
+        4,
+        Query {
+            name: "y".to_string(),
+        },
+    )
+    .into_request();
+    assert_eq!(request.uri(), "/download/4?name=y");
+    let download = Download(
+        4,
+        Query {
+            name: "a/b".to_string(),
+        },
+    );
+    assert_eq!(download.to_uri(), "/download/4?name=a%2Fb");
+}
+
 #[test]
 fn generic_forward() {
     #[derive(FromRequest, Debug, PartialEq, Eq)]
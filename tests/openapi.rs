@@ -0,0 +1,235 @@
+//! Tests for `#[derive(Describe)]` and OpenAPI document generation.
+
+#![cfg(feature = "openapi")]
+#![allow(unused)]
+
+use hyperdrive::{
+    body::{HtmlForm, Json},
+    openapi::{Describe, OpenApi},
+    FromRequest,
+};
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json::json;
+
+#[derive(Deserialize, JsonSchema)]
+struct NewPost {
+    title: String,
+    draft: bool,
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct Login {
+    user: String,
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct Pagination {
+    page: u32,
+    per_page: Option<u32>,
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct CommentPath {
+    id: u32,
+    comment: String,
+}
+
+#[derive(FromRequest, Describe)]
+enum Api {
+    /// Lists all posts.
+    ///
+    /// Posts are sorted by date.
+    /// Newest first.
+    ///
+    /// Drafts are not included.
+    #[get("/posts")]
+    Posts {
+        #[query_params]
+        pagination: Pagination,
+
+        #[query("tag")]
+        tags: Vec<String>,
+    },
+
+    #[post("/posts/{id:[0-9]+}")]
+    NewPost {
+        id: u32,
+
+        #[body]
+        post: Json<NewPost>,
+    },
+
+    #[get("/posts/{id}/comments/{comment}")]
+    Comment {
+        #[path_params]
+        path: CommentPath,
+    },
+}
+
+#[derive(FromRequest, Describe)]
+enum Routes {
+    /// The index page.
+    #[get("/")]
+    Index,
+
+    #[post("/login")]
+    Login {
+        #[body]
+        form: HtmlForm<Login>,
+    },
+
+    #[route(method = "PROPFIND", path = "/files/{path...}")]
+    Files { path: String },
+
+    Api {
+        #[forward(prefix = "/api")]
+        api: Api,
+    },
+}
+
+#[test]
+fn operations() {
+    let mut gen = schemars::gen::SchemaGenerator::default();
+    let ops = Routes::describe(&mut gen);
+    let ops = ops
+        .iter()
+        .map(|op| (op.method().as_str(), op.path(), op.variants()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        ops,
+        &[
+            ("GET", "/", &["Index"][..]),
+            ("POST", "/login", &["Login"][..]),
+            ("PROPFIND", "/files/{path}", &["Files"][..]),
+            ("GET", "/api/posts", &["Api", "Posts"][..]),
+            ("POST", "/api/posts/{id}", &["Api", "NewPost"][..]),
+            (
+                "GET",
+                "/api/posts/{id}/comments/{comment}",
+                &["Api", "Comment"][..]
+            ),
+        ]
+    );
+}
+
+#[test]
+fn document() {
+    let doc = OpenApi::new("Blog", "1.2.3").add::<Routes>().to_json();
+
+    assert_eq!(doc["openapi"], "3.0.3");
+    assert_eq!(doc["info"], json!({ "title": "Blog", "version": "1.2.3" }));
+
+    // Implied HEAD routes and non-standard methods are left out
+    let paths = doc["paths"].as_object().unwrap();
+    assert_eq!(
+        paths.keys().collect::<Vec<_>>(),
+        &[
+            "/",
+            "/api/posts",
+            "/api/posts/{id}",
+            "/api/posts/{id}/comments/{comment}",
+            "/login"
+        ]
+    );
+    assert_eq!(
+        paths["/"].as_object().unwrap().keys().collect::<Vec<_>>(),
+        &["get"]
+    );
+    assert_eq!(paths["/"]["get"]["summary"], "The index page.");
+    assert!(paths["/"]["get"].get("description").is_none());
+
+    let posts = &paths["/api/posts"]["get"];
+    assert_eq!(posts["summary"], "Lists all posts.");
+    assert_eq!(
+        posts["description"],
+        "Posts are sorted by date. Newest first.\n\nDrafts are not included."
+    );
+    assert_eq!(posts["parameters"][0]["name"], "page");
+    assert_eq!(posts["parameters"][0]["in"], "query");
+    assert_eq!(posts["parameters"][0]["required"], true);
+    assert_eq!(posts["parameters"][1]["name"], "per_page");
+    assert_eq!(posts["parameters"][1]["required"], false);
+    assert_eq!(
+        posts["parameters"][2],
+        json!({
+            "name": "tag",
+            "in": "query",
+            "required": false,
+            "schema": { "type": "array", "items": { "type": "string" } },
+        })
+    );
+
+    let new_post = &paths["/api/posts/{id}"]["post"];
+    assert_eq!(
+        new_post["parameters"][0],
+        json!({
+            "name": "id",
+            "in": "path",
+            "required": true,
+            "schema": {
+                "type": "integer",
+                "format": "uint32",
+                "minimum": 0.0,
+                "pattern": "^(?:[0-9]+)$",
+            },
+        })
+    );
+    assert_eq!(
+        new_post["requestBody"]["content"]["application/json"]["schema"],
+        json!({ "$ref": "#/components/schemas/NewPost" })
+    );
+
+    // Placeholders stored in a `#[path_params]` field use the schema of its properties
+    let comment = &paths["/api/posts/{id}/comments/{comment}"]["get"];
+    assert_eq!(comment["parameters"][0]["name"], "id");
+    assert_eq!(comment["parameters"][0]["schema"]["type"], "integer");
+    assert_eq!(comment["parameters"][1]["name"], "comment");
+    assert_eq!(
+        comment["parameters"][1]["schema"],
+        json!({ "type": "string" })
+    );
+
+    let login = &paths["/login"]["post"];
+    assert_eq!(
+        login["requestBody"]["content"]["application/x-www-form-urlencoded"]["schema"],
+        json!({ "$ref": "#/components/schemas/Login" })
+    );
+
+    let schemas = doc["components"]["schemas"].as_object().unwrap();
+    assert_eq!(schemas.keys().collect::<Vec<_>>(), &["Login", "NewPost"]);
+    assert_eq!(schemas["NewPost"]["required"], json!(["draft", "title"]));
+}
+
+#[test]
+fn fallthrough_duplicates() {
+    #[derive(FromRequest, Describe)]
+    #[routing(fallthrough)]
+    enum Routes {
+        /// By ID.
+        #[get("/users/{id:[0-9]+}")]
+        ById { id: u32 },
+
+        /// By name.
+        #[get("/users/{id}")]
+        ByName { id: String },
+    }
+
+    // Both routes have the same path template, only the first one is kept
+    let doc = OpenApi::new("Users", "1.0.0").add::<Routes>().to_json();
+    assert_eq!(doc["paths"]["/users/{id}"]["get"]["summary"], "By ID.");
+}
+
+#[test]
+fn generic_forward() {
+    #[derive(FromRequest, Describe)]
+    struct Wrapper<T> {
+        #[forward]
+        inner: T,
+    }
+
+    let doc = OpenApi::new("Wrapped", "1.0.0")
+        .add::<Wrapper<Api>>()
+        .to_json();
+    assert!(doc["paths"]["/posts"]["get"].is_object());
+}